@@ -0,0 +1,59 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use study_buddy::blocks::BlockRenderer;
+use study_buddy::parse_markdown;
+
+//Lecture notes with `sections` headings, each with prose, a list and some code
+fn lecture_notes(sections: usize) -> String {
+    let mut notes = String::new();
+
+    for section in 0..sections {
+        notes.push_str(&format!(
+            "## Section {section}\n\n\
+            Cells divide through *mitosis*, which keeps the number of chromosomes, \
+            and **meiosis**, which halves it. See section {section} of the reader.\n\n\
+            - Prophase\n- Metaphase\n- Anaphase\n- Telophase\n\n\
+            ```rust\nfn phase(step: usize) -> &'static str {{\n    \
+            [\"pro\", \"meta\", \"ana\", \"telo\"][step % 4]\n}}\n```\n\n"
+        ));
+    }
+
+    notes
+}
+
+//The same notes with one paragraph in the middle changed, like after a keystroke
+fn edited(notes: &str, sections: usize) -> String {
+    let paragraph = format!("See section {} of the reader.", sections / 2);
+    notes.replacen(&paragraph, &format!("{paragraph} Typo"), 1)
+}
+
+fn rendering(c: &mut Criterion) {
+    let mut group = c.benchmark_group("rendering");
+    group.sample_size(20);
+
+    for sections in [50, 500] {
+        let notes = lecture_notes(sections);
+        let edited = edited(&notes, sections);
+
+        group.bench_with_input(BenchmarkId::new("full", sections), &edited, |b, edited| {
+            b.iter(|| parse_markdown(edited))
+        });
+
+        group.bench_with_input(
+            BenchmarkId::new("changed_blocks", sections),
+            &(notes, edited),
+            |b, (notes, edited)| {
+                let mut renderer = BlockRenderer::default();
+                renderer.update(notes);
+
+                //Alternates so every iteration has one block to render again
+                let mut versions = [notes, edited].into_iter().cycle();
+                b.iter(|| renderer.update(versions.next().unwrap()))
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, rendering);
+criterion_main!(benches);
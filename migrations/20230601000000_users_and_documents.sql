@@ -0,0 +1,22 @@
+-- Deployments from before migrations already have users and documents, along
+-- with the session column and temporary table later migrations replace. The
+-- first migrations only create what is missing so those databases upgrade in
+-- place.
+CREATE TABLE IF NOT EXISTS users (
+    id UUID PRIMARY KEY,
+    email TEXT NOT NULL UNIQUE,
+    password TEXT NOT NULL
+);
+
+ALTER TABLE users DROP COLUMN IF EXISTS session_id;
+
+CREATE TABLE IF NOT EXISTS documents (
+    user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    title TEXT NOT NULL,
+    content TEXT NOT NULL,
+    document_id UUID PRIMARY KEY
+);
+
+CREATE INDEX IF NOT EXISTS documents_user_id_idx ON documents (user_id);
+
+DROP TABLE IF EXISTS temporary;
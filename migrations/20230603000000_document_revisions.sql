@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS document_revisions (
+    revision_id UUID PRIMARY KEY,
+    document_id UUID NOT NULL REFERENCES documents (document_id) ON DELETE CASCADE,
+    content TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS document_revisions_document_id_created_at_idx
+    ON document_revisions (document_id, created_at DESC);
@@ -0,0 +1,15 @@
+CREATE TABLE notebooks (
+    notebook_id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    parent_id UUID REFERENCES notebooks (notebook_id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX notebooks_user_id_idx ON notebooks (user_id);
+CREATE INDEX notebooks_parent_id_idx ON notebooks (parent_id);
+
+ALTER TABLE documents
+    ADD COLUMN notebook_id UUID REFERENCES notebooks (notebook_id) ON DELETE SET NULL;
+
+CREATE INDEX documents_notebook_id_idx ON documents (notebook_id);
@@ -0,0 +1,8 @@
+CREATE TABLE document_tags (
+    document_id UUID NOT NULL REFERENCES documents (document_id) ON DELETE CASCADE,
+    tag TEXT NOT NULL,
+    from_content BOOLEAN NOT NULL DEFAULT FALSE,
+    PRIMARY KEY (document_id, tag)
+);
+
+CREATE INDEX document_tags_tag_idx ON document_tags (tag);
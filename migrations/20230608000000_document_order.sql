@@ -0,0 +1,7 @@
+ALTER TABLE documents
+    ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN pinned BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN position INTEGER;
+
+CREATE INDEX documents_user_id_updated_at_idx ON documents (user_id, updated_at);
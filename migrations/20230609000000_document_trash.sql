@@ -0,0 +1,3 @@
+ALTER TABLE documents ADD COLUMN deleted_at TIMESTAMPTZ;
+
+CREATE INDEX documents_deleted_at_idx ON documents (deleted_at) WHERE deleted_at IS NOT NULL;
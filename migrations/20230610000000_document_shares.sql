@@ -0,0 +1,9 @@
+CREATE TABLE document_shares (
+    document_id UUID NOT NULL REFERENCES documents (document_id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    role TEXT NOT NULL CHECK (role IN ('viewer', 'commenter', 'editor')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (document_id, user_id)
+);
+
+CREATE INDEX document_shares_user_id_idx ON document_shares (user_id);
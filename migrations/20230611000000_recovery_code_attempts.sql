@@ -0,0 +1 @@
+ALTER TABLE recovery_codes ADD COLUMN failed_attempts INTEGER NOT NULL DEFAULT 0;
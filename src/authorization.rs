@@ -0,0 +1,172 @@
+use crate::users::UserCtx;
+use crate::StudyBuddyError;
+use serde::{Deserialize, Serialize};
+use sqlx::{postgres::PgPool, FromRow};
+use tracing::info;
+
+/// What a user can do with a document, each role can do everything the ones
+/// before it can. Viewers and commenters can read a document and see who has
+/// access to it, editors can also change it and only owners can share or
+/// delete it.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, sqlx::Type,
+)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+pub enum Access {
+    Viewer,
+    Commenter,
+    Editor,
+    Owner,
+}
+
+#[derive(FromRow)]
+struct Owner {
+    user_id: uuid::Uuid,
+}
+
+fn check_owner(
+    owner: Option<Owner>,
+    ctx: &UserCtx,
+    kind: &str,
+    id: uuid::Uuid,
+    not_found: StudyBuddyError,
+) -> Result<(), StudyBuddyError> {
+    match owner {
+        Some(owner) if owner.user_id == ctx.user_id() => Ok(()),
+        Some(_) => {
+            info!(
+                "User {} tried to access {} {} they don't own",
+                ctx.user_id(),
+                kind,
+                id
+            );
+            Err(StudyBuddyError::Forbidden)
+        }
+        None => Err(not_found),
+    }
+}
+
+async fn authorize_document_in(
+    pool: &PgPool,
+    ctx: &UserCtx,
+    document_id: uuid::Uuid,
+    trashed: bool,
+) -> Result<(), StudyBuddyError> {
+    let owner = sqlx::query_as::<_, Owner>(
+        "SELECT user_id
+        FROM documents
+        WHERE document_id = $1 AND (deleted_at IS NOT NULL) = $2",
+    )
+    .bind(document_id)
+    .bind(trashed)
+    .fetch_optional(pool)
+    .await?;
+
+    check_owner(
+        owner,
+        ctx,
+        "document",
+        document_id,
+        StudyBuddyError::DocumentNotFound,
+    )
+}
+
+/// Checks that the document exists and belongs to the user in `ctx`, documents
+/// in the trash count as not existing. Handlers open to the users a document is
+/// shared with use `authorize_document_access` instead.
+///
+/// Handlers still scope their own queries by `user_id`, this only exists so an
+/// unknown id and someone else's id produce different errors.
+pub async fn authorize_document(
+    pool: &PgPool,
+    ctx: &UserCtx,
+    document_id: uuid::Uuid,
+) -> Result<(), StudyBuddyError> {
+    authorize_document_in(pool, ctx, document_id, false).await
+}
+
+#[derive(FromRow)]
+struct DocumentAccess {
+    user_id: uuid::Uuid,
+    role: Option<Access>,
+}
+
+/// Checks that the user in `ctx` has at least `needed` access to the document,
+/// as its owner or through a share, and returns the access they have.
+/// Documents in the trash count as not existing.
+///
+/// Handlers behind this can't scope their queries by `user_id`, the document
+/// may belong to someone else.
+pub async fn authorize_document_access(
+    pool: &PgPool,
+    ctx: &UserCtx,
+    document_id: uuid::Uuid,
+    needed: Access,
+) -> Result<Access, StudyBuddyError> {
+    let document = sqlx::query_as::<_, DocumentAccess>(
+        "SELECT documents.user_id, document_shares.role
+        FROM documents
+        LEFT JOIN document_shares
+            ON document_shares.document_id = documents.document_id
+            AND document_shares.user_id = $2
+        WHERE documents.document_id = $1 AND documents.deleted_at IS NULL",
+    )
+    .bind(document_id)
+    .bind(ctx.user_id())
+    .fetch_optional(pool)
+    .await?
+    .ok_or(StudyBuddyError::DocumentNotFound)?;
+
+    let access = if document.user_id == ctx.user_id() {
+        Some(Access::Owner)
+    } else {
+        document.role
+    };
+
+    match access {
+        Some(access) if access >= needed => Ok(access),
+        _ => {
+            info!(
+                "User {} tried to use document {} without {:?} access",
+                ctx.user_id(),
+                document_id,
+                needed
+            );
+            Err(StudyBuddyError::Forbidden)
+        }
+    }
+}
+
+/// Same as `authorize_document`, for documents in the trash.
+pub async fn authorize_trashed_document(
+    pool: &PgPool,
+    ctx: &UserCtx,
+    document_id: uuid::Uuid,
+) -> Result<(), StudyBuddyError> {
+    authorize_document_in(pool, ctx, document_id, true).await
+}
+
+/// Same as `authorize_document`, for notebooks.
+pub async fn authorize_notebook(
+    pool: &PgPool,
+    ctx: &UserCtx,
+    notebook_id: uuid::Uuid,
+) -> Result<(), StudyBuddyError> {
+    let owner = sqlx::query_as::<_, Owner>(
+        "SELECT user_id
+        FROM notebooks
+        WHERE notebook_id = $1",
+    )
+    .bind(notebook_id)
+    .fetch_optional(pool)
+    .await?;
+
+    check_owner(
+        owner,
+        ctx,
+        "notebook",
+        notebook_id,
+        StudyBuddyError::NotebookNotFound,
+    )
+}
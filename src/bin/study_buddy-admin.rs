@@ -0,0 +1,230 @@
+use clap::{Parser, Subcommand};
+use sqlx::PgPool;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use study_buddy::config::Config;
+use study_buddy::export::{self, ExportOptions};
+use study_buddy::user_data::{self, UserExport};
+use study_buddy::{database, recovery, sessions, trash, users};
+
+/// Maintenance tasks for Study Buddy, reads the same config as the server.
+#[derive(Parser)]
+#[command(name = "study_buddy-admin")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create a user, the password is read from stdin
+    CreateUser { email: String },
+    /// Set a new password read from stdin and sign the user out everywhere
+    ResetPassword { email: String },
+    /// Sign the user out of every session
+    RevokeSessions { email: String },
+    /// List the documents of a user
+    ListDocuments { email: String },
+    /// Permanently delete one of a user's documents, trashed or not
+    DeleteDocument {
+        email: String,
+        document_id: uuid::Uuid,
+    },
+    /// Delete recovery codes past their expiry
+    PurgeRecoveryCodes,
+    /// Permanently delete documents that have been in the trash past the retention window
+    PurgeTrash,
+    /// Write a user's notebooks and documents as JSON, to stdout unless a file is given
+    Export {
+        email: String,
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Write a user's documents to a zip of markdown files
+    ExportArchive {
+        email: String,
+        output: PathBuf,
+        #[arg(long)]
+        html: bool,
+    },
+    /// Import notebooks and documents from a JSON export into a user's account
+    Import { email: String, input: PathBuf },
+    /// Apply pending database migrations
+    Migrate,
+}
+
+//Passwords never come from arguments, those end up in shell history and `ps`
+fn read_password() -> Result<String, String> {
+    eprint!("Password: ");
+    std::io::stderr().flush().ok();
+
+    let mut password = String::new();
+    std::io::stdin()
+        .lock()
+        .read_line(&mut password)
+        .map_err(|err| format!("Failed to read password: {}", err))?;
+
+    let password = password.trim_end_matches(['\r', '\n']).to_string();
+    if password.is_empty() {
+        return Err("Password can't be empty".to_string());
+    }
+
+    Ok(password)
+}
+
+fn failed(err: impl std::fmt::Display) -> String {
+    err.to_string()
+}
+
+async fn run(pool: &PgPool, config: &Config, command: Command) -> Result<(), String> {
+    match command {
+        Command::CreateUser { email } => {
+            let password = read_password()?;
+            let user_id = users::insert_user(pool, &email, password)
+                .await
+                .map_err(failed)?;
+            println!("Created user {} ({})", email, user_id);
+        }
+        Command::ResetPassword { email } => {
+            let password = read_password()?;
+            let user_id = users::find_user_id(pool, &email).await.map_err(failed)?;
+
+            //Old sessions must not outlive the old password
+            let mut transaction = pool.begin().await.map_err(failed)?;
+            users::set_password(&mut *transaction, user_id, password)
+                .await
+                .map_err(failed)?;
+            let revoked = sessions::revoke_all_sessions(&mut *transaction, user_id, None)
+                .await
+                .map_err(failed)?;
+            transaction.commit().await.map_err(failed)?;
+            println!(
+                "Reset password of {} and revoked {} sessions",
+                email, revoked
+            );
+        }
+        Command::RevokeSessions { email } => {
+            let user_id = users::find_user_id(pool, &email).await.map_err(failed)?;
+            let revoked = sessions::revoke_all_sessions(pool, user_id, None)
+                .await
+                .map_err(failed)?;
+            println!("Revoked {} sessions of {}", revoked, email);
+        }
+        Command::ListDocuments { email } => {
+            let user_id = users::find_user_id(pool, &email).await.map_err(failed)?;
+            for document in users::list_documents(pool, user_id, &users::DocumentFilter::default())
+                .await
+                .map_err(failed)?
+            {
+                println!("{}\t{}", document.document_id, document.title);
+            }
+        }
+        Command::DeleteDocument { email, document_id } => {
+            let user_id = users::find_user_id(pool, &email).await.map_err(failed)?;
+            if !users::remove_document(pool, user_id, document_id)
+                .await
+                .map_err(failed)?
+            {
+                return Err(format!("{} has no document {}", email, document_id));
+            }
+            println!("Deleted document {}", document_id);
+        }
+        Command::PurgeRecoveryCodes => {
+            let purged = recovery::purge_expired_recovery_codes(pool)
+                .await
+                .map_err(failed)?;
+            println!("Purged {} expired recovery codes", purged);
+        }
+        Command::PurgeTrash => {
+            let purged = trash::purge_trash(pool, config.trash_retention)
+                .await
+                .map_err(failed)?;
+            println!("Purged {} documents from the trash", purged);
+        }
+        Command::Export { email, output } => {
+            let user_id = users::find_user_id(pool, &email).await.map_err(failed)?;
+            let export = user_data::export_user_data(pool, user_id)
+                .await
+                .map_err(failed)?;
+            let json = serde_json::to_string_pretty(&export).map_err(failed)?;
+
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, json).map_err(failed)?;
+                    eprintln!(
+                        "Exported {} documents to {}",
+                        export.documents.len(),
+                        path.display()
+                    );
+                }
+                None => println!("{}", json),
+            }
+        }
+        Command::ExportArchive {
+            email,
+            output,
+            html,
+        } => {
+            let user_id = users::find_user_id(pool, &email).await.map_err(failed)?;
+            let file = std::fs::File::create(&output).map_err(failed)?;
+            export::write_archive(
+                pool,
+                user_id,
+                zip::ZipWriter::new(file),
+                ExportOptions { html },
+                || async { Ok(()) },
+            )
+            .await
+            .map_err(failed)?;
+            eprintln!("Exported documents of {} to {}", email, output.display());
+        }
+        Command::Import { email, input } => {
+            let user_id = users::find_user_id(pool, &email).await.map_err(failed)?;
+            let json = std::fs::read_to_string(&input).map_err(failed)?;
+            let export: UserExport = serde_json::from_str(&json).map_err(failed)?;
+            let summary = user_data::import_user_data(pool, user_id, &export)
+                .await
+                .map_err(failed)?;
+            println!(
+                "Imported {} documents and {} notebooks",
+                summary.imported, summary.imported_notebooks
+            );
+            for document_id in &summary.skipped {
+                println!("Skipped document {}, the id is taken", document_id);
+            }
+            for notebook_id in &summary.skipped_notebooks {
+                println!(
+                    "Skipped notebook {}, the id is taken by another user",
+                    notebook_id
+                );
+            }
+        }
+        Command::Migrate => {
+            database::run_migrations(pool).await.map_err(failed)?;
+            println!("Database schema is up to date");
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    dotenv::dotenv().ok();
+    let cli = Cli::parse();
+
+    let config = Config::load().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    });
+
+    let pool = database::connect(&config).await.unwrap_or_else(|err| {
+        eprintln!("Failed to connect to the database: {}", err);
+        std::process::exit(1);
+    });
+
+    if let Err(err) = run(&pool, &config, cli.command).await {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,236 @@
+use crate::parse_markdown;
+use std::time::Instant;
+
+/// Tags of the HTML blocks that keep going over blank lines until they close.
+const RAW_HTML_TAGS: [(&str, &str); 4] = [
+    ("<pre", "</pre>"),
+    ("<script", "</script>"),
+    ("<style", "</style>"),
+    ("<textarea", "</textarea>"),
+];
+
+//What a block spanning blank lines is waiting for before it can end
+enum Closer {
+    Fence(char, usize),
+    Text(&'static str),
+}
+
+fn fence(line: &str) -> Option<Closer> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    let line = line.trim_start_matches(' ');
+    let marker = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let length = line.len() - line.trim_start_matches(marker).len();
+
+    (indent <= 3 && length >= 3).then_some(Closer::Fence(marker, length))
+}
+
+fn opened_closer(line: &str) -> Option<Closer> {
+    if let Some(fence) = fence(line) {
+        return Some(fence);
+    }
+
+    let trimmed = line.trim_start();
+
+    if let Some(comment) = trimmed.strip_prefix("<!--") {
+        return (!comment.contains("-->")).then_some(Closer::Text("-->"));
+    }
+
+    let lowercase = trimmed.to_ascii_lowercase();
+    RAW_HTML_TAGS
+        .iter()
+        .find(|(tag, end)| lowercase.starts_with(tag) && !lowercase.contains(end))
+        .map(|(_, end)| Closer::Text(end))
+}
+
+fn closes(closer: &Closer, line: &str) -> bool {
+    match closer {
+        Closer::Fence(marker, length) => {
+            let trimmed = line.trim_start_matches(' ');
+            let rest = trimmed.trim_start_matches(*marker);
+            line.len() - trimmed.len() <= 3
+                && trimmed.len() - rest.len() >= *length
+                && rest.trim().is_empty()
+        }
+        Closer::Text(end) => line.to_ascii_lowercase().contains(end),
+    }
+}
+
+fn is_list_item(line: &str) -> bool {
+    let line = line.trim_start();
+
+    if let Some(rest) = line.strip_prefix(['-', '*', '+']) {
+        return rest.is_empty() || rest.starts_with([' ', '\t']);
+    }
+
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let rest = &line[digits..];
+    (1..=9).contains(&digits)
+        && rest
+            .strip_prefix(['.', ')'])
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with([' ', '\t']))
+}
+
+//Reference links and footnotes can be used from any block, splitting a
+//document that has them would render their uses as plain text
+fn has_definitions(markdown: &str) -> bool {
+    markdown.lines().any(|line| {
+        let trimmed = line.trim_start();
+        line.len() - trimmed.len() <= 3 && trimmed.starts_with('[') && trimmed.contains("]:")
+    })
+}
+
+/// Splits markdown into top-level blocks that render to the same HTML on their
+/// own as they do inside the whole document. Blocks keep the blank lines that
+/// follow them, so joining them gives back the original text.
+pub fn split_blocks(markdown: &str) -> Vec<&str> {
+    if has_definitions(markdown) {
+        return vec![markdown];
+    }
+
+    let mut blocks = Vec::new();
+    let mut block_start = 0;
+    let mut first_line: Option<&str> = None;
+    let mut closer = None;
+    let mut after_blank = false;
+    let mut offset = 0;
+
+    for line in markdown.split_inclusive('\n') {
+        let line_start = offset;
+        offset += line.len();
+
+        if let Some(open) = &closer {
+            if closes(open, line) {
+                closer = None;
+            }
+            continue;
+        }
+
+        if line.trim().is_empty() {
+            after_blank = first_line.is_some();
+            continue;
+        }
+
+        //Only unindented lines can start a block, anything indented may be
+        //the continuation of a list item or an indented code block
+        let starts_block = after_blank
+            && !line.starts_with([' ', '\t'])
+            && !(is_list_item(line) && first_line.is_some_and(is_list_item));
+
+        if starts_block {
+            blocks.push(&markdown[block_start..line_start]);
+            block_start = line_start;
+            first_line = None;
+        }
+
+        first_line.get_or_insert(line);
+        after_blank = false;
+        closer = opened_closer(line);
+    }
+
+    if block_start < markdown.len() || blocks.is_empty() {
+        blocks.push(&markdown[block_start..]);
+    }
+
+    blocks
+}
+
+/// Blocks that changed between two renders, replacing `delete_count` blocks
+/// from `start` with `blocks` turns the old render into the new one.
+#[derive(Debug, PartialEq, Eq)]
+pub struct BlockPatch {
+    pub start: usize,
+    pub delete_count: usize,
+    pub blocks: Vec<String>,
+}
+
+impl BlockPatch {
+    pub fn is_empty(&self) -> bool {
+        self.delete_count == 0 && self.blocks.is_empty()
+    }
+}
+
+struct RenderedBlock {
+    source: String,
+    html: String,
+}
+
+/// Keeps the last render of a document so a change only re-renders the blocks
+/// it touched.
+#[derive(Default)]
+pub struct BlockRenderer {
+    blocks: Vec<RenderedBlock>,
+}
+
+impl BlockRenderer {
+    /// Renders `markdown`, reusing the blocks that are the same as in the
+    /// last render at the start and end of the document.
+    pub fn update(&mut self, markdown: &str) -> BlockPatch {
+        self.update_while(markdown, || true)
+            .expect("Rendering only stops early when asked to")
+    }
+
+    /// Same as `update`, but gives up once `deadline` has passed. The deadline
+    /// is checked between blocks, a render that gives up keeps the last one.
+    pub fn update_until(&mut self, markdown: &str, deadline: Instant) -> Option<BlockPatch> {
+        self.update_while(markdown, || Instant::now() < deadline)
+    }
+
+    fn update_while(
+        &mut self,
+        markdown: &str,
+        keep_going: impl Fn() -> bool,
+    ) -> Option<BlockPatch> {
+        let sources = split_blocks(markdown);
+
+        let prefix = self
+            .blocks
+            .iter()
+            .zip(&sources)
+            .take_while(|(block, source)| block.source == **source)
+            .count();
+
+        let suffix = self.blocks[prefix..]
+            .iter()
+            .rev()
+            .zip(sources[prefix..].iter().rev())
+            .take_while(|(block, source)| block.source == **source)
+            .count();
+
+        let mut changed = Vec::with_capacity(sources.len() - suffix - prefix);
+
+        for source in &sources[prefix..sources.len() - suffix] {
+            if !keep_going() {
+                return None;
+            }
+
+            changed.push(RenderedBlock {
+                source: source.to_string(),
+                html: parse_markdown(source),
+            });
+        }
+
+        let patch = BlockPatch {
+            start: prefix,
+            delete_count: self.blocks.len() - suffix - prefix,
+            blocks: changed.iter().map(|block| block.html.clone()).collect(),
+        };
+
+        self.blocks
+            .splice(prefix..self.blocks.len() - suffix, changed);
+
+        Some(patch)
+    }
+
+    /// HTML of every block of the last render.
+    pub fn blocks(&self) -> Vec<String> {
+        self.blocks.iter().map(|block| block.html.clone()).collect()
+    }
+
+    /// The last render as a single document.
+    pub fn html(&self) -> String {
+        self.blocks
+            .iter()
+            .map(|block| block.html.as_str())
+            .collect()
+    }
+}
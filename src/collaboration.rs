@@ -0,0 +1,529 @@
+use crate::authorization::Access;
+use crate::live::MAX_DOCUMENT_SIZE;
+use crate::ot::{transform, Operation};
+use crate::server::AppState;
+use crate::users::store_document_content;
+use crate::StudyBuddyError;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+/// How long a room waits after an operation before saving the document, later
+/// operations are saved along with it.
+pub const AUTOSAVE_DELAY: Duration = Duration::from_secs(2);
+
+//Operations based on a version older than this many operations ago are
+//rejected, the client has to join again
+const MAX_HISTORY: usize = 500;
+
+//Events a participant can fall behind by before being dropped from the room
+const EVENT_BUFFER: usize = 256;
+
+/// A participant's selection, `head` is where the cursor is and `anchor` where
+/// the selection started. Offsets count UTF-16 code units like operations do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Selection {
+    pub anchor: usize,
+    pub head: usize,
+}
+
+impl Selection {
+    fn transform(self, operation: &Operation) -> Self {
+        Selection {
+            anchor: operation.transform_index(self.anchor),
+            head: operation.transform_index(self.head),
+        }
+    }
+}
+
+/// Someone in the room of a document, a user can take part from several
+/// connections. Only editors and the owner can send operations.
+#[derive(Clone, Debug, Serialize)]
+pub struct Participant {
+    pub connection_id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    pub email: String,
+    pub role: Access,
+    pub selection: Option<Selection>,
+}
+
+#[derive(Clone, Debug)]
+pub enum RoomEvent {
+    /// An operation was applied, taking the document to `version`.
+    Operation {
+        version: u64,
+        operation: Operation,
+        connection_id: uuid::Uuid,
+        user_id: uuid::Uuid,
+    },
+    /// A participant joined, moved their selection or got another role.
+    Presence(Participant),
+    /// A participant left, or lost access to the document when it is the
+    /// connection's own.
+    Left { connection_id: uuid::Uuid },
+    /// The connection fell too far behind and no longer gets events of the
+    /// room, it has to join again.
+    Lagged,
+    /// The document was deleted and the room is gone along with everyone in it.
+    Closed,
+}
+
+/// A room event for a document a connection joined, `generation` is the one
+/// the connection passed to `join`.
+#[derive(Debug)]
+pub struct RoomMessage {
+    pub document_id: uuid::Uuid,
+    pub generation: u64,
+    pub event: RoomEvent,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RoomError {
+    /// The operation is based on a version the room doesn't have.
+    OutOfSync,
+    /// The operation doesn't fit the text it is based on.
+    InvalidOperation,
+    TooLarge,
+    /// The participant can't edit the document.
+    ReadOnly,
+}
+
+struct Room {
+    document_id: uuid::Uuid,
+    text: String,
+    //UTF-16 length of `text`
+    length: usize,
+    version: u64,
+    saved_version: u64,
+    save_scheduled: bool,
+    //Saves run one at a time so an older text can't overwrite a newer one
+    saving: Arc<tokio::sync::Mutex<()>>,
+    //`history[i]` took the text from version `history_start + i` to the next one
+    history: VecDeque<Operation>,
+    history_start: u64,
+    participants: HashMap<uuid::Uuid, Participant>,
+    events: broadcast::Sender<RoomEvent>,
+}
+
+type SharedRoom = Arc<Mutex<Room>>;
+
+fn lock(room: &SharedRoom) -> MutexGuard<'_, Room> {
+    room.lock().expect("Room lock is never poisoned")
+}
+
+impl Room {
+    fn new(document_id: uuid::Uuid, text: String) -> Self {
+        Room {
+            document_id,
+            length: text.encode_utf16().count(),
+            text,
+            version: 0,
+            saved_version: 0,
+            save_scheduled: false,
+            saving: Arc::default(),
+            history: VecDeque::new(),
+            history_start: 0,
+            participants: HashMap::new(),
+            events: broadcast::channel(EVENT_BUFFER).0,
+        }
+    }
+
+    //Operations applied since `version`
+    fn history_since(&self, version: u64) -> Result<impl Iterator<Item = &Operation>, RoomError> {
+        if version < self.history_start || version > self.version {
+            return Err(RoomError::OutOfSync);
+        }
+
+        Ok(self
+            .history
+            .range((version - self.history_start) as usize..))
+    }
+
+    fn apply(
+        &mut self,
+        connection_id: uuid::Uuid,
+        version: u64,
+        operation: Operation,
+    ) -> Result<u64, RoomError> {
+        //The role can change while the participant is in the room
+        let user_id = match self.participants.get(&connection_id) {
+            Some(participant) if participant.role >= Access::Editor => participant.user_id,
+            _ => return Err(RoomError::ReadOnly),
+        };
+        let mut operation = operation;
+
+        //Moves the operation past everything applied since the version the
+        //client made it on
+        for applied in self.history_since(version)? {
+            operation = transform(&operation, applied)
+                .ok_or(RoomError::InvalidOperation)?
+                .0;
+        }
+
+        let text = operation
+            .apply(&self.text)
+            .ok_or(RoomError::InvalidOperation)?;
+
+        if text.len() > MAX_DOCUMENT_SIZE {
+            return Err(RoomError::TooLarge);
+        }
+
+        self.text = text;
+        self.length = operation.target_len();
+        self.version += 1;
+
+        for other in self.participants.values_mut() {
+            other.selection = other
+                .selection
+                .map(|selection| selection.transform(&operation));
+        }
+
+        self.history.push_back(operation.clone());
+        if self.history.len() > MAX_HISTORY {
+            self.history.pop_front();
+            self.history_start += 1;
+        }
+
+        let _ = self.events.send(RoomEvent::Operation {
+            version: self.version,
+            operation,
+            connection_id,
+            user_id,
+        });
+
+        Ok(self.version)
+    }
+
+    fn select(
+        &mut self,
+        connection_id: uuid::Uuid,
+        version: u64,
+        selection: Selection,
+    ) -> Result<(), RoomError> {
+        let selection = self
+            .history_since(version)?
+            .fold(selection, |selection, applied| selection.transform(applied));
+
+        if selection.anchor.max(selection.head) > self.length {
+            return Err(RoomError::InvalidOperation);
+        }
+
+        let participant = self
+            .participants
+            .get_mut(&connection_id)
+            .ok_or(RoomError::ReadOnly)?;
+        participant.selection = Some(selection);
+
+        let _ = self.events.send(RoomEvent::Presence(participant.clone()));
+        Ok(())
+    }
+}
+
+/// Documents being edited together, each one has a room holding its merged
+/// text for as long as someone has it joined.
+#[derive(Default)]
+pub struct Rooms {
+    rooms: Mutex<HashMap<uuid::Uuid, SharedRoom>>,
+}
+
+impl Rooms {
+    fn lock(&self) -> MutexGuard<'_, HashMap<uuid::Uuid, SharedRoom>> {
+        self.rooms.lock().expect("Rooms lock is never poisoned")
+    }
+
+    /// Whether someone has the document joined.
+    pub fn is_open(&self, document_id: uuid::Uuid) -> bool {
+        self.lock().contains_key(&document_id)
+    }
+
+    /// Gives the connections of a user in the room of a document a new role,
+    /// `None` removes them from the room.
+    pub fn change_access(
+        &self,
+        document_id: uuid::Uuid,
+        user_id: uuid::Uuid,
+        role: Option<Access>,
+    ) {
+        let Some(room) = self.lock().get(&document_id).cloned() else {
+            return;
+        };
+        let mut locked = lock(&room);
+        let room = &mut *locked;
+
+        let connections: Vec<uuid::Uuid> = room
+            .participants
+            .values()
+            .filter(|participant| participant.user_id == user_id)
+            .map(|participant| participant.connection_id)
+            .collect();
+
+        for connection_id in connections {
+            match role {
+                Some(role) => {
+                    let participant = room
+                        .participants
+                        .get_mut(&connection_id)
+                        .expect("Collected above");
+                    participant.role = role;
+                    let _ = room.events.send(RoomEvent::Presence(participant.clone()));
+                }
+                None => {
+                    room.participants.remove(&connection_id);
+                    let _ = room.events.send(RoomEvent::Left { connection_id });
+                }
+            }
+        }
+    }
+}
+
+/// A connection's place in a room, dropping it leaves the room.
+pub struct Membership {
+    app_state: Arc<AppState>,
+    connection_id: uuid::Uuid,
+    room: SharedRoom,
+    forwarder: JoinHandle<()>,
+}
+
+/// What a connection starts from after joining a room.
+pub struct Joined {
+    pub membership: Membership,
+    pub version: u64,
+    pub text: String,
+    /// Everyone else in the room.
+    pub participants: Vec<Participant>,
+}
+
+async fn forward(
+    mut events: broadcast::Receiver<RoomEvent>,
+    sender: mpsc::Sender<RoomMessage>,
+    document_id: uuid::Uuid,
+    generation: u64,
+) {
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(_)) => RoomEvent::Lagged,
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        let lagged = matches!(event, RoomEvent::Lagged);
+
+        let message = RoomMessage {
+            document_id,
+            generation,
+            event,
+        };
+
+        //A connection that doesn't read its events makes the room lag past it
+        if sender.send(message).await.is_err() || lagged {
+            return;
+        }
+    }
+}
+
+/// Joins the room of a document, loading the document into a new room when
+/// nobody has it joined. Events of the room are sent to `sender` from then on.
+/// Callers check that the participant may edit the document beforehand.
+pub async fn join(
+    app_state: &Arc<AppState>,
+    document_id: uuid::Uuid,
+    participant: Participant,
+    generation: u64,
+    sender: mpsc::Sender<RoomMessage>,
+) -> Result<Joined, StudyBuddyError> {
+    let mut stored = None;
+
+    loop {
+        {
+            let mut rooms = app_state.rooms.lock();
+            let room = match (rooms.get(&document_id), stored.take()) {
+                (Some(room), _) => Some(room.clone()),
+                (None, Some(content)) => {
+                    let room = Arc::new(Mutex::new(Room::new(document_id, content)));
+                    rooms.insert(document_id, room.clone());
+                    Some(room)
+                }
+                (None, None) => None,
+            };
+
+            //Entering while holding the rooms lock keeps the room from being
+            //closed in between
+            if let Some(room) = room {
+                let mut locked = lock(&room);
+                let events = locked.events.subscribe();
+                let participants = locked.participants.values().cloned().collect();
+                let (version, text) = (locked.version, locked.text.clone());
+
+                locked
+                    .participants
+                    .insert(participant.connection_id, participant.clone());
+                let _ = locked.events.send(RoomEvent::Presence(participant.clone()));
+                drop(locked);
+
+                let forwarder = tokio::spawn(forward(events, sender, document_id, generation));
+
+                return Ok(Joined {
+                    membership: Membership {
+                        app_state: app_state.clone(),
+                        connection_id: participant.connection_id,
+                        room,
+                        forwarder,
+                    },
+                    version,
+                    text,
+                    participants,
+                });
+            }
+        }
+
+        let content = sqlx::query_scalar!(
+            "SELECT content
+            FROM documents
+            WHERE document_id = $1 AND deleted_at IS NULL",
+            document_id
+        )
+        .fetch_optional(&app_state.pool)
+        .await?
+        .ok_or(StudyBuddyError::DocumentNotFound)?;
+
+        stored = Some(content);
+    }
+}
+
+//Forgets the room of a document that is gone, everyone in it is told and
+//dropped from it
+fn discard(app_state: &AppState, room: &SharedRoom) {
+    let mut rooms = app_state.rooms.lock();
+    let mut locked = lock(room);
+    let document_id = locked.document_id;
+
+    locked.participants.clear();
+    let _ = locked.events.send(RoomEvent::Closed);
+
+    if rooms
+        .get(&document_id)
+        .is_some_and(|current| Arc::ptr_eq(current, room))
+    {
+        rooms.remove(&document_id);
+        info!("Closed the room of deleted document {}", document_id);
+    }
+}
+
+//Stores the text of the room unless it is already stored, returns the version
+//that is stored. The room is discarded when its document is gone
+async fn save(app_state: &AppState, room: &SharedRoom) -> Result<u64, StudyBuddyError> {
+    let saving = lock(room).saving.clone();
+    let _saving = saving.lock().await;
+
+    let (document_id, text, version) = {
+        let room = lock(room);
+
+        if room.version == room.saved_version {
+            return Ok(room.version);
+        }
+
+        (room.document_id, room.text.clone(), room.version)
+    };
+
+    match store_document_content(&app_state.pool, document_id, &text).await {
+        Ok(()) => lock(room).saved_version = version,
+        Err(StudyBuddyError::DocumentNotFound) => {
+            discard(app_state, room);
+            return Err(StudyBuddyError::DocumentNotFound);
+        }
+        Err(err) => return Err(err),
+    }
+
+    info!(
+        "Saved document {} at version {} of its room",
+        document_id, version
+    );
+
+    Ok(version)
+}
+
+async fn autosave(app_state: Arc<AppState>, room: SharedRoom) {
+    tokio::time::sleep(AUTOSAVE_DELAY).await;
+    lock(&room).save_scheduled = false;
+
+    match save(&app_state, &room).await {
+        Ok(_) | Err(StudyBuddyError::DocumentNotFound) => {}
+        Err(err) => error!("Failed to autosave a room: {:?}", err),
+    }
+}
+
+//Saves the room of a document everyone left and forgets it, unless someone
+//joined again meanwhile. A room that fails to save is kept so the edits
+//aren't lost, unless its document is gone
+async fn close(app_state: Arc<AppState>, room: SharedRoom) {
+    match save(&app_state, &room).await {
+        Ok(_) => {}
+        Err(StudyBuddyError::DocumentNotFound) => return,
+        Err(err) => {
+            error!("Failed to save a room everyone left: {:?}", err);
+            return;
+        }
+    }
+
+    let mut rooms = app_state.rooms.lock();
+    let locked = lock(&room);
+
+    if locked.participants.is_empty() && locked.version == locked.saved_version {
+        let document_id = locked.document_id;
+        if rooms
+            .get(&document_id)
+            .is_some_and(|current| Arc::ptr_eq(current, &room))
+        {
+            rooms.remove(&document_id);
+        }
+    }
+}
+
+impl Membership {
+    /// Applies an operation made on `version` of the document, after merging
+    /// it with the operations other participants made since. Returns the new
+    /// version, the operation is also sent to everyone as a room event.
+    pub fn apply(&self, version: u64, operation: Operation) -> Result<u64, RoomError> {
+        let mut room = lock(&self.room);
+        let version = room.apply(self.connection_id, version, operation)?;
+
+        if !room.save_scheduled {
+            room.save_scheduled = true;
+            tokio::spawn(autosave(self.app_state.clone(), self.room.clone()));
+        }
+
+        Ok(version)
+    }
+
+    /// Moves the participant's selection, made on `version` of the document.
+    pub fn select(&self, version: u64, selection: Selection) -> Result<(), RoomError> {
+        lock(&self.room).select(self.connection_id, version, selection)
+    }
+
+    /// Saves the merged text now instead of waiting for the autosave, returns
+    /// the version that got saved.
+    pub async fn save(&self) -> Result<u64, StudyBuddyError> {
+        save(&self.app_state, &self.room).await
+    }
+}
+
+impl Drop for Membership {
+    fn drop(&mut self) {
+        self.forwarder.abort();
+
+        let connection_id = self.connection_id;
+        let mut room = lock(&self.room);
+
+        //Someone who lost access was removed already
+        if room.participants.remove(&connection_id).is_some() {
+            let _ = room.events.send(RoomEvent::Left { connection_id });
+        }
+
+        if room.participants.is_empty() {
+            tokio::spawn(close(self.app_state.clone(), self.room.clone()));
+        }
+    }
+}
@@ -0,0 +1,402 @@
+use crate::mail::TlsMode;
+use axum::http::HeaderValue;
+use lettre::message::Mailbox;
+use serde::Deserialize;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// File read when `STUDY_BUDDY_CONFIG` doesn't point somewhere else, it is
+/// fine for it to not exist as long as the environment provides everything.
+const DEFAULT_CONFIG_PATH: &str = "study_buddy.toml";
+
+#[derive(Debug)]
+pub enum ConfigError {
+    ReadFailed {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    ParseFailed {
+        path: PathBuf,
+        error: toml::de::Error,
+    },
+    Missing {
+        key: &'static str,
+        env: &'static str,
+    },
+    Invalid {
+        key: &'static str,
+        value: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::ReadFailed { path, error } => {
+                write!(f, "Couldn't read config file {}: {}", path.display(), error)
+            }
+            ConfigError::ParseFailed { path, error } => {
+                write!(
+                    f,
+                    "Couldn't parse config file {}: {}",
+                    path.display(),
+                    error
+                )
+            }
+            ConfigError::Missing { key, env } => write!(
+                f,
+                "Missing config value {}, set it in the config file or through {}",
+                key, env
+            ),
+            ConfigError::Invalid { key, value, reason } => {
+                write!(f, "Invalid value {:?} for {}: {}", value, key, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Everything the server needs to know to start, checked once at boot.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub address: SocketAddr,
+    /// Whether the server sits behind a proxy that sets `X-Forwarded-For`,
+    /// otherwise the header is ignored since clients can send anything in it.
+    pub trust_forwarded_for: bool,
+    pub rate_limit_per_second: u64,
+    pub request_timeout: Duration,
+    pub database_url: String,
+    pub database_max_connections: u32,
+    pub run_migrations: bool,
+    pub trash_retention: Duration,
+    pub pdf: PdfBackend,
+    pub mail: MailBackend,
+}
+
+#[derive(Clone, Debug)]
+pub enum PdfBackend {
+    PdfEndpoint { api_key: String },
+    Local,
+}
+
+#[derive(Clone, Debug)]
+pub enum MailBackend {
+    Smtp(SmtpConfig),
+    File(PathBuf),
+    Stdout,
+}
+
+#[derive(Clone, Debug)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: Option<u16>,
+    pub tls: TlsMode,
+    pub sender: String,
+    pub password: Option<String>,
+}
+
+//The config file as written, every value can still be overridden by the environment
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawConfig {
+    server: RawServerConfig,
+    database: RawDatabaseConfig,
+    documents: RawDocumentsConfig,
+    pdf: RawPdfConfig,
+    mail: RawMailConfig,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawServerConfig {
+    address: Option<SocketAddr>,
+    trust_forwarded_for: Option<bool>,
+    rate_limit_per_second: Option<u64>,
+    request_timeout_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawDatabaseConfig {
+    url: Option<String>,
+    max_connections: Option<u32>,
+    run_migrations: Option<bool>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawDocumentsConfig {
+    trash_retention_days: Option<u64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawPdfConfig {
+    backend: Option<String>,
+    api_key: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawMailConfig {
+    backend: Option<String>,
+    sender: Option<String>,
+    password: Option<String>,
+    smtp_host: Option<String>,
+    smtp_port: Option<u16>,
+    smtp_tls: Option<String>,
+    outbox: Option<PathBuf>,
+}
+
+//A missing file reads as empty unless it is `required`
+fn read_config_file(path: &Path, required: bool) -> Result<String, ConfigError> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => Ok(contents),
+        Err(error) if !required && error.kind() == std::io::ErrorKind::NotFound => {
+            Ok(String::new())
+        }
+        Err(error) => Err(ConfigError::ReadFailed {
+            path: path.to_path_buf(),
+            error,
+        }),
+    }
+}
+
+type EnvLookup<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+impl RawConfig {
+    fn parse(path: &Path, contents: &str) -> Result<Self, ConfigError> {
+        toml::from_str(contents).map_err(|error| ConfigError::ParseFailed {
+            path: path.to_path_buf(),
+            error,
+        })
+    }
+
+    fn apply_env(&mut self, env: EnvLookup) -> Result<(), ConfigError> {
+        env_override(env, "ADDRESS", &mut self.server.address)?;
+        env_override(
+            env,
+            "TRUST_FORWARDED_FOR",
+            &mut self.server.trust_forwarded_for,
+        )?;
+        env_override(
+            env,
+            "RATE_LIMIT_PER_SECOND",
+            &mut self.server.rate_limit_per_second,
+        )?;
+        env_override(
+            env,
+            "REQUEST_TIMEOUT_SECS",
+            &mut self.server.request_timeout_secs,
+        )?;
+        env_override(env, "DATABASE_URL", &mut self.database.url)?;
+        env_override(
+            env,
+            "DATABASE_MAX_CONNECTIONS",
+            &mut self.database.max_connections,
+        )?;
+        env_override(env, "RUN_MIGRATIONS", &mut self.database.run_migrations)?;
+        env_override(
+            env,
+            "TRASH_RETENTION_DAYS",
+            &mut self.documents.trash_retention_days,
+        )?;
+        env_override(env, "PDF_BACKEND", &mut self.pdf.backend)?;
+        env_override(env, "PDF_API_KEY", &mut self.pdf.api_key)?;
+        env_override(env, "MAIL_BACKEND", &mut self.mail.backend)?;
+        env_override(env, "EMAIL", &mut self.mail.sender)?;
+        env_override(env, "EMAIL_APP_PASSWORD", &mut self.mail.password)?;
+        env_override(env, "SMTP_HOST", &mut self.mail.smtp_host)?;
+        env_override(env, "SMTP_PORT", &mut self.mail.smtp_port)?;
+        env_override(env, "SMTP_TLS", &mut self.mail.smtp_tls)?;
+        env_override(env, "MAIL_OUTBOX", &mut self.mail.outbox)?;
+
+        Ok(())
+    }
+}
+
+fn env_override<T>(
+    env: EnvLookup,
+    name: &'static str,
+    value: &mut Option<T>,
+) -> Result<(), ConfigError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    if let Some(env_value) = env(name) {
+        let parsed = env_value
+            .parse()
+            .map_err(|error: T::Err| ConfigError::Invalid {
+                key: name,
+                reason: error.to_string(),
+                value: env_value,
+            })?;
+        *value = Some(parsed);
+    }
+
+    Ok(())
+}
+
+impl Config {
+    /// Reads the file named by `STUDY_BUDDY_CONFIG` (or `study_buddy.toml` if
+    /// there is one) and applies environment overrides on top of it.
+    pub fn load() -> Result<Self, ConfigError> {
+        let (path, required) = match std::env::var("STUDY_BUDDY_CONFIG") {
+            Ok(path) => (PathBuf::from(path), true),
+            Err(_) => (PathBuf::from(DEFAULT_CONFIG_PATH), false),
+        };
+
+        let contents = read_config_file(&path, required)?;
+
+        Config::from_sources(&path, &contents, |name| std::env::var(name).ok())
+    }
+
+    /// Builds the config from the contents of the file at `path` with the
+    /// variables `env` returns applied on top, `load` passes the process
+    /// environment.
+    pub fn from_sources(
+        path: &Path,
+        contents: &str,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, ConfigError> {
+        let mut raw = RawConfig::parse(path, contents)?;
+        raw.apply_env(&env)?;
+
+        Config::validate(raw)
+    }
+
+    fn validate(raw: RawConfig) -> Result<Self, ConfigError> {
+        let database_url = raw.database.url.ok_or(ConfigError::Missing {
+            key: "database.url",
+            env: "DATABASE_URL",
+        })?;
+
+        let rate_limit_per_second = raw.server.rate_limit_per_second.unwrap_or(10);
+        if rate_limit_per_second == 0 {
+            return Err(ConfigError::Invalid {
+                key: "server.rate_limit_per_second",
+                value: rate_limit_per_second.to_string(),
+                reason: "must be at least 1".to_string(),
+            });
+        }
+
+        let database_max_connections = raw.database.max_connections.unwrap_or(8);
+        if database_max_connections == 0 {
+            return Err(ConfigError::Invalid {
+                key: "database.max_connections",
+                value: database_max_connections.to_string(),
+                reason: "must be at least 1".to_string(),
+            });
+        }
+
+        let trash_retention_days = raw.documents.trash_retention_days.unwrap_or(30);
+        if trash_retention_days == 0 {
+            return Err(ConfigError::Invalid {
+                key: "documents.trash_retention_days",
+                value: trash_retention_days.to_string(),
+                reason: "must be at least 1".to_string(),
+            });
+        }
+
+        Ok(Config {
+            address: raw
+                .server
+                .address
+                .unwrap_or_else(|| SocketAddr::from(([0, 0, 0, 0], 8080))),
+            trust_forwarded_for: raw.server.trust_forwarded_for.unwrap_or(false),
+            rate_limit_per_second,
+            request_timeout: Duration::from_secs(raw.server.request_timeout_secs.unwrap_or(60)),
+            database_url,
+            database_max_connections,
+            run_migrations: raw.database.run_migrations.unwrap_or(true),
+            trash_retention: Duration::from_secs(trash_retention_days * 24 * 60 * 60),
+            pdf: Config::validate_pdf(raw.pdf)?,
+            mail: Config::validate_mail(raw.mail)?,
+        })
+    }
+
+    //pdfendpoint.com is used when an API key is around, the local renderer
+    //has to be asked for
+    fn validate_pdf(raw: RawPdfConfig) -> Result<PdfBackend, ConfigError> {
+        match (raw.backend.as_deref(), raw.api_key) {
+            (Some("pdfendpoint") | None, Some(api_key)) => {
+                //The key is sent as a bearer token on every request
+                if let Err(error) = HeaderValue::from_str(&format!("Bearer {}", api_key)) {
+                    return Err(ConfigError::Invalid {
+                        key: "pdf.api_key",
+                        value: api_key,
+                        reason: error.to_string(),
+                    });
+                }
+
+                Ok(PdfBackend::PdfEndpoint { api_key })
+            }
+            (Some("pdfendpoint") | None, None) => Err(ConfigError::Missing {
+                key: "pdf.api_key",
+                env: "PDF_API_KEY",
+            }),
+            (Some("local"), _) => Ok(PdfBackend::Local),
+            (Some(other), _) => Err(ConfigError::Invalid {
+                key: "pdf.backend",
+                value: other.to_string(),
+                reason: "expected pdfendpoint or local".to_string(),
+            }),
+        }
+    }
+
+    //SMTP is used when a sender address is around, the file and stdout
+    //backends have to be asked for
+    fn validate_mail(raw: RawMailConfig) -> Result<MailBackend, ConfigError> {
+        match (raw.backend.as_deref(), raw.sender) {
+            (Some("smtp") | None, Some(sender)) => {
+                if let Err(error) = sender.parse::<Mailbox>() {
+                    return Err(ConfigError::Invalid {
+                        key: "mail.sender",
+                        value: sender,
+                        reason: error.to_string(),
+                    });
+                }
+
+                let tls = match raw.smtp_tls {
+                    Some(tls) => {
+                        TlsMode::try_from(tls.as_str()).map_err(|reason| ConfigError::Invalid {
+                            key: "mail.smtp_tls",
+                            value: tls,
+                            reason,
+                        })?
+                    }
+                    None => TlsMode::default(),
+                };
+
+                Ok(MailBackend::Smtp(SmtpConfig {
+                    host: raw
+                        .smtp_host
+                        .unwrap_or_else(|| "smtp.gmail.com".to_string()),
+                    port: raw.smtp_port,
+                    tls,
+                    sender,
+                    password: raw.password,
+                }))
+            }
+            (Some("smtp") | None, None) => Err(ConfigError::Missing {
+                key: "mail.sender",
+                env: "EMAIL",
+            }),
+            (Some("file"), _) => Ok(MailBackend::File(
+                raw.outbox.unwrap_or_else(|| PathBuf::from("outbox.txt")),
+            )),
+            (Some("stdout"), _) => Ok(MailBackend::Stdout),
+            (Some(other), _) => Err(ConfigError::Invalid {
+                key: "mail.backend",
+                value: other.to_string(),
+                reason: "expected smtp, file or stdout".to_string(),
+            }),
+        }
+    }
+}
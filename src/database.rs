@@ -0,0 +1,30 @@
+use crate::config::Config;
+use sqlx::{
+    migrate::{MigrateError, Migrator},
+    postgres::PgPoolOptions,
+    PgPool,
+};
+use tracing::info;
+
+/// The migrations in `migrations/`, embedded in the binary so a deployment
+/// only needs an empty database.
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+pub async fn connect(config: &Config) -> Result<PgPool, sqlx::Error> {
+    PgPoolOptions::new()
+        .max_connections(config.database_max_connections)
+        .connect(&config.database_url)
+        .await
+}
+
+/// Applies every migration the database hasn't seen yet.
+pub async fn run_migrations(pool: &PgPool) -> Result<(), MigrateError> {
+    MIGRATOR.run(pool).await?;
+
+    info!(
+        "Database schema is up to date ({} migrations)",
+        MIGRATOR.iter().count()
+    );
+
+    Ok(())
+}
@@ -1,7 +1,49 @@
+use crate::request_id::current_request_id;
 use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
+    Json,
 };
+use serde::Serialize;
+use std::fmt;
+use tracing::error;
+
+/// Body of every error response, `code` is stable and meant for clients to
+/// match on, `message` is meant for people.
+#[derive(Serialize)]
+struct ErrorBody {
+    code: &'static str,
+    message: String,
+    details: Option<serde_json::Value>,
+    request_id: Option<String>,
+}
+
+fn error_response(status: StatusCode, code: &'static str, message: impl Into<String>) -> Response {
+    let body = ErrorBody {
+        code,
+        message: message.into(),
+        details: None,
+        request_id: current_request_id(),
+    };
+
+    (status, Json(body)).into_response()
+}
+
+//Logs the cause of an internal error, the client only gets a generic message
+fn internal_error_response(
+    code: &'static str,
+    message: &str,
+    cause: impl std::fmt::Debug,
+) -> Response {
+    error!(
+        "{} (request {}): {:?}",
+        message,
+        current_request_id().unwrap_or_default(),
+        cause
+    );
+
+    error_response(StatusCode::INTERNAL_SERVER_ERROR, code, message)
+}
 
 #[derive(Clone, Debug)]
 pub enum StudyBuddySessionError {
@@ -13,15 +55,21 @@ pub enum StudyBuddySessionError {
 impl IntoResponse for StudyBuddySessionError {
     fn into_response(self) -> Response {
         match self {
-            StudyBuddySessionError::InvalidUserSession => {
-                (StatusCode::UNAUTHORIZED, "Invalid user session").into_response()
-            }
-            StudyBuddySessionError::NoSessionId => {
-                (StatusCode::UNAUTHORIZED, "No session cookie found").into_response()
-            }
-            StudyBuddySessionError::LookupFailed => {
-                (StatusCode::INTERNAL_SERVER_ERROR, "Lookup Failed").into_response()
-            }
+            StudyBuddySessionError::InvalidUserSession => error_response(
+                StatusCode::UNAUTHORIZED,
+                "invalid_session",
+                "Invalid user session",
+            ),
+            StudyBuddySessionError::NoSessionId => error_response(
+                StatusCode::UNAUTHORIZED,
+                "missing_session",
+                "No session cookie found",
+            ),
+            StudyBuddySessionError::LookupFailed => internal_error_response(
+                "session_lookup_failed",
+                "Failed to look up the user session",
+                self,
+            ),
         }
     }
 }
@@ -41,7 +89,22 @@ pub enum StudyBuddyError {
     IncompleteRequest,
     WrongEmailOrPassword,
     DocumentNotFound,
+    DocumentInRoom,
+    RevisionNotFound,
+    NotebookNotFound,
+    NotebookCycle,
+    InvalidTag,
+    InvalidUpload,
+    Forbidden,
+    CrossOrigin,
+    InvalidShare,
+    ShareNotFound,
+    SessionNotFound,
+    TooManyRequests,
     ReqwestWrapper(reqwest::Error),
+    PdfRenderingFailed(String),
+    MailDeliveryFailed(String),
+    ArchiveFailed(String),
     InvalidEmailAddress,
     SqlxWrapper(sqlx::Error),
 }
@@ -58,47 +121,174 @@ impl From<sqlx::Error> for StudyBuddyError {
     }
 }
 
-impl IntoResponse for StudyBuddyError {
-    fn into_response(self) -> Response {
-        match self {
-            StudyBuddyError::WrongEmailOrPassword => {
-                (StatusCode::UNAUTHORIZED, "Invalid email or password").into_response()
-            }
+impl StudyBuddyError {
+    /// Status, stable code and message of errors whose cause is safe to show
+    /// to the client, `None` for internal errors.
+    fn public_parts(&self) -> Option<(StatusCode, &'static str, &'static str)> {
+        let parts = match self {
+            StudyBuddyError::WrongEmailOrPassword => (
+                StatusCode::UNAUTHORIZED,
+                "wrong_email_or_password",
+                "Invalid email or password",
+            ),
             StudyBuddyError::NoMatchingUserRecord => (
                 StatusCode::NOT_FOUND,
+                "user_not_found",
                 "No matching user with provided email",
-            )
-                .into_response(),
-            StudyBuddyError::EmailAlreadyInUse => {
-                (StatusCode::CONFLICT, "Incorrect Password or Email").into_response()
-            }
+            ),
+            StudyBuddyError::EmailAlreadyInUse => (
+                StatusCode::CONFLICT,
+                "email_already_in_use",
+                "Email address is already in use",
+            ),
             StudyBuddyError::IncompleteRequest => (
                 StatusCode::BAD_REQUEST,
+                "incomplete_request",
                 "Request doesn't contain all of the necessary fields",
-            )
-                .into_response(),
-            StudyBuddyError::ReqwestWrapper(error) => {
-                let status_code = match error.status() {
-                    Some(code) => code,
-                    None => axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-                };
+            ),
+            StudyBuddyError::DocumentNotFound => (
+                StatusCode::NOT_FOUND,
+                "document_not_found",
+                "Document ID isn't valid",
+            ),
+            StudyBuddyError::RevisionNotFound => (
+                StatusCode::NOT_FOUND,
+                "revision_not_found",
+                "Revision ID isn't valid",
+            ),
+            StudyBuddyError::DocumentInRoom => (
+                StatusCode::CONFLICT,
+                "document_in_room",
+                "Others are editing the document, join it to save",
+            ),
+            StudyBuddyError::NotebookNotFound => (
+                StatusCode::NOT_FOUND,
+                "notebook_not_found",
+                "Notebook ID isn't valid",
+            ),
+            StudyBuddyError::NotebookCycle => (
+                StatusCode::CONFLICT,
+                "notebook_cycle",
+                "A notebook can't be moved into itself",
+            ),
+            StudyBuddyError::InvalidTag => (
+                StatusCode::BAD_REQUEST,
+                "invalid_tag",
+                "Tags can only contain letters, numbers, -, _ and /",
+            ),
+            StudyBuddyError::InvalidUpload => (
+                StatusCode::BAD_REQUEST,
+                "invalid_upload",
+                "Uploaded files couldn't be read",
+            ),
+            StudyBuddyError::Forbidden => (
+                StatusCode::FORBIDDEN,
+                "forbidden",
+                "You don't have access to this document",
+            ),
+            StudyBuddyError::CrossOrigin => (
+                StatusCode::FORBIDDEN,
+                "cross_origin",
+                "Connections are only accepted from pages of this site",
+            ),
+            StudyBuddyError::InvalidShare => (
+                StatusCode::BAD_REQUEST,
+                "invalid_share",
+                "Documents can only be shared with other users as viewer, commenter or editor",
+            ),
+            StudyBuddyError::ShareNotFound => (
+                StatusCode::NOT_FOUND,
+                "share_not_found",
+                "The document isn't shared with that user",
+            ),
+            StudyBuddyError::SessionNotFound => (
+                StatusCode::NOT_FOUND,
+                "session_not_found",
+                "Session ID isn't valid",
+            ),
+            StudyBuddyError::TooManyRequests => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "too_many_requests",
+                "Too many attempts, try again later",
+            ),
+            StudyBuddyError::InvalidEmailAddress => (
+                StatusCode::BAD_REQUEST,
+                "invalid_email_address",
+                "Invalid email address",
+            ),
+            StudyBuddyError::InvalidRecoveryCode => (
+                StatusCode::UNAUTHORIZED,
+                "invalid_recovery_code",
+                "Invalid or expired recovery code",
+            ),
+            StudyBuddyError::SessionError(_)
+            | StudyBuddyError::ReqwestWrapper(_)
+            | StudyBuddyError::PdfRenderingFailed(_)
+            | StudyBuddyError::MailDeliveryFailed(_)
+            | StudyBuddyError::ArchiveFailed(_)
+            | StudyBuddyError::SqlxWrapper(_) => return None,
+        };
 
-                (status_code, error.to_string()).into_response()
-            }
-            StudyBuddyError::DocumentNotFound => {
-                (StatusCode::NO_CONTENT, "Document ID isn't valid").into_response()
+        Some(parts)
+    }
+
+    /// Code and message to hand to a client outside of an HTTP response, the
+    /// cause of internal errors stays in the logs.
+    pub fn client_parts(&self) -> (&'static str, &'static str) {
+        match self.public_parts() {
+            Some((_, code, message)) => (code, message),
+            None => {
+                error!("{}", self);
+                ("internal_error", "Internal server error")
             }
+        }
+    }
+}
+
+impl fmt::Display for StudyBuddyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((_, _, message)) = self.public_parts() {
+            return write!(f, "{}", message);
+        }
+
+        match self {
+            StudyBuddyError::SessionError(err) => write!(f, "Session error: {:?}", err),
+            StudyBuddyError::ReqwestWrapper(err) => write!(f, "External service failed: {}", err),
+            StudyBuddyError::PdfRenderingFailed(err) => write!(f, "Failed to render PDF: {}", err),
+            StudyBuddyError::MailDeliveryFailed(err) => write!(f, "Email failed to send: {}", err),
+            StudyBuddyError::ArchiveFailed(err) => write!(f, "Failed to write archive: {}", err),
+            StudyBuddyError::SqlxWrapper(err) => write!(f, "Database error: {}", err),
+            _ => unreachable!("Public errors are handled above"),
+        }
+    }
+}
+
+impl std::error::Error for StudyBuddyError {}
+
+impl IntoResponse for StudyBuddyError {
+    fn into_response(self) -> Response {
+        if let Some((status, code, message)) = self.public_parts() {
+            return error_response(status, code, message);
+        }
+
+        match self {
             StudyBuddyError::SessionError(err) => err.into_response(),
-            //Could be better
+            StudyBuddyError::ReqwestWrapper(error) => {
+                internal_error_response("upstream_error", "An external service failed", error)
+            }
+            StudyBuddyError::PdfRenderingFailed(error) => {
+                internal_error_response("pdf_rendering_failed", "Failed to render PDF", error)
+            }
+            StudyBuddyError::MailDeliveryFailed(error) => {
+                internal_error_response("mail_delivery_failed", "Email failed to send", error)
+            }
+            StudyBuddyError::ArchiveFailed(error) => {
+                internal_error_response("archive_failed", "Failed to write archive", error)
+            }
             StudyBuddyError::SqlxWrapper(error) => {
-                (StatusCode::INTERNAL_SERVER_ERROR, error.to_string()).into_response()
-            },
-            StudyBuddyError::InvalidEmailAddress => {
-                (StatusCode::BAD_REQUEST, "Invalid email address").into_response()
-            },
-            StudyBuddyError::InvalidRecoveryCode => {
-                (StatusCode::UNAUTHORIZED, "Invalid temporary password").into_response()
+                internal_error_response("internal_error", "Internal server error", error)
             }
+            _ => unreachable!("Public errors are handled above"),
         }
     }
 }
@@ -0,0 +1,414 @@
+use crate::parsing::front_matter_block;
+use crate::server::AppState;
+use crate::users::UserCtx;
+use crate::{parse_markdown, StudyBuddyError};
+use axum::{
+    body::StreamBody,
+    extract::{Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use futures::Future;
+use serde::Deserialize;
+use sqlx::{FromRow, PgPool};
+use std::collections::{HashMap, HashSet};
+use std::io::{Seek, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{error, info};
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+const ARCHIVE_NAME: &str = "study_buddy_export.zip";
+const MAX_FILE_NAME_LENGTH: usize = 100;
+
+//Keys the export writes itself, the same keys already in a document's
+//front-matter are dropped instead of showing up twice
+const EXPORTED_KEYS: [&str; 5] = ["title", "id", "created_at", "updated_at", "tags"];
+
+//A download that takes no data for this long is given up on
+const SEND_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize, Default, Clone, Copy)]
+pub struct ExportOptions {
+    /// Also write each document rendered to `.html` next to its `.md`.
+    #[serde(default)]
+    pub html: bool,
+}
+
+#[derive(FromRow)]
+struct ArchiveDocument {
+    document_id: uuid::Uuid,
+    title: String,
+    content: String,
+    notebook_id: Option<uuid::Uuid>,
+    tags: Vec<String>,
+    created_at: time::OffsetDateTime,
+    updated_at: time::OffsetDateTime,
+}
+
+#[derive(FromRow)]
+struct NotebookRow {
+    notebook_id: uuid::Uuid,
+    parent_id: Option<uuid::Uuid>,
+    name: String,
+}
+
+fn safe_file_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .take(MAX_FILE_NAME_LENGTH)
+        .collect();
+
+    let name = name.trim().trim_matches('.').trim();
+
+    if name.is_empty() {
+        "Untitled".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+fn yaml_string(value: &str) -> String {
+    format!(
+        "\"{}\"",
+        value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace(['\n', '\r'], " ")
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn rfc3339(timestamp: time::OffsetDateTime) -> String {
+    timestamp
+        .format(&time::format_description::well_known::Rfc3339)
+        .expect("Database timestamps are always formattable")
+}
+
+/// The document as markdown with its metadata in the front-matter, keys the
+/// document already had in its own front-matter are kept.
+fn document_markdown(document: &ArchiveDocument) -> (String, &str) {
+    let mut markdown = format!(
+        "---\ntitle: {}\nid: {}\ncreated_at: {}\nupdated_at: {}\n",
+        yaml_string(&document.title),
+        document.document_id,
+        rfc3339(document.created_at),
+        rfc3339(document.updated_at),
+    );
+
+    if !document.tags.is_empty() {
+        markdown.push_str(&format!("tags: [{}]\n", document.tags.join(", ")));
+    }
+
+    let body = match front_matter_block(&document.content) {
+        Some((block, body)) => {
+            let mut skipping = false;
+
+            for line in block.lines() {
+                let nested = line.starts_with([' ', '\t', '-']);
+
+                if !nested {
+                    let key = line.split(':').next().unwrap_or_default().trim();
+                    skipping = EXPORTED_KEYS.contains(&key);
+                }
+
+                if !skipping {
+                    markdown.push_str(line);
+                    markdown.push('\n');
+                }
+            }
+
+            body
+        }
+        None => document.content.as_str(),
+    };
+
+    markdown.push_str("---\n\n");
+    markdown.push_str(body);
+
+    (markdown, body)
+}
+
+fn document_html(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+        <title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        escape_html(title),
+        include_str!("../templates/lightpdf.css"),
+        parse_markdown(body)
+    )
+}
+
+fn zip_timestamp(timestamp: time::OffsetDateTime) -> zip::DateTime {
+    let timestamp = timestamp.to_offset(time::UtcOffset::UTC);
+
+    zip::DateTime::from_date_and_time(
+        timestamp.year().clamp(1980, 2107) as u16,
+        timestamp.month() as u8,
+        timestamp.day(),
+        timestamp.hour(),
+        timestamp.minute(),
+        timestamp.second(),
+    )
+    .unwrap_or_default()
+}
+
+/// Writes a user's documents into a zip archive, laid out in folders that
+/// follow their notebooks.
+struct ArchiveWriter<W: Write + Seek> {
+    zip: ZipWriter<W>,
+    options: ExportOptions,
+    notebooks: HashMap<uuid::Uuid, (Option<uuid::Uuid>, String)>,
+    used_paths: HashSet<String>,
+}
+
+impl<W: Write + Seek> ArchiveWriter<W> {
+    fn folder(&self, mut notebook_id: Option<uuid::Uuid>) -> String {
+        let mut names = Vec::new();
+
+        //Notebooks can't form cycles, the limit only guards against a corrupt tree
+        while let Some((parent_id, name)) = notebook_id.and_then(|id| self.notebooks.get(&id)) {
+            if names.len() > self.notebooks.len() {
+                break;
+            }
+
+            names.push(safe_file_name(name));
+            notebook_id = *parent_id;
+        }
+
+        names.reverse();
+        names.into_iter().map(|name| name + "/").collect()
+    }
+
+    //Titles aren't unique, later documents with a taken name get a number
+    fn unique_path(&mut self, folder: &str, title: &str) -> String {
+        let name = safe_file_name(title);
+        let mut path = format!("{}{}", folder, name);
+        let mut copy = 1;
+
+        while !self.used_paths.insert(path.to_lowercase()) {
+            copy += 1;
+            path = format!("{}{} ({})", folder, name, copy);
+        }
+
+        path
+    }
+
+    fn add(
+        &mut self,
+        document: &ArchiveDocument,
+        markdown: &str,
+        html: Option<&str>,
+    ) -> zip::result::ZipResult<()> {
+        let folder = self.folder(document.notebook_id);
+        let path = self.unique_path(&folder, &document.title);
+
+        let options = SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .last_modified_time(zip_timestamp(document.updated_at));
+
+        self.zip.start_file(format!("{}.md", path), options)?;
+        self.zip.write_all(markdown.as_bytes())?;
+
+        if let Some(html) = html {
+            self.zip.start_file(format!("{}.html", path), options)?;
+            self.zip.write_all(html.as_bytes())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes every document of `user_id` that isn't in the trash to `writer` as a
+/// zip archive. `flush` runs after each document, so a caller can pass what
+/// has been written so far along while the rest is still being written.
+pub async fn write_archive<W, F, Fut>(
+    pool: &PgPool,
+    user_id: uuid::Uuid,
+    writer: ZipWriter<W>,
+    options: ExportOptions,
+    mut flush: F,
+) -> Result<W, StudyBuddyError>
+where
+    W: Write + Seek,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<(), StudyBuddyError>>,
+{
+    let notebooks = sqlx::query_as::<_, NotebookRow>(
+        "SELECT notebook_id, parent_id, name
+        FROM notebooks
+        WHERE user_id = $1",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|notebook| (notebook.notebook_id, (notebook.parent_id, notebook.name)))
+    .collect();
+
+    let mut archive = ArchiveWriter {
+        zip: writer,
+        options,
+        notebooks,
+        used_paths: HashSet::new(),
+    };
+
+    //Documents are fetched one at a time instead of streamed, a connection
+    //isn't held for as long as a slow download takes
+    let document_ids = sqlx::query_scalar!(
+        "SELECT document_id
+        FROM documents
+        WHERE user_id = $1 AND deleted_at IS NULL
+        ORDER BY created_at, document_id",
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let archive_failed =
+        |err: zip::result::ZipError| StudyBuddyError::ArchiveFailed(err.to_string());
+
+    for document_id in document_ids {
+        let document = sqlx::query_as::<_, ArchiveDocument>(
+            "SELECT document_id, title, content, notebook_id, created_at, updated_at,
+                ARRAY(
+                    SELECT tag FROM document_tags
+                    WHERE document_tags.document_id = documents.document_id
+                    ORDER BY tag
+                ) AS tags
+            FROM documents
+            WHERE document_id = $1 AND deleted_at IS NULL",
+        )
+        .bind(document_id)
+        .fetch_optional(pool)
+        .await?;
+
+        //Deleted while the archive was being written
+        let Some(document) = document else {
+            continue;
+        };
+
+        let (markdown, body) = document_markdown(&document);
+
+        let html = if archive.options.html {
+            let (title, body) = (document.title.clone(), body.to_string());
+            let html = tokio::task::spawn_blocking(move || document_html(&title, &body))
+                .await
+                .map_err(|err| StudyBuddyError::ArchiveFailed(err.to_string()))?;
+            Some(html)
+        } else {
+            None
+        };
+
+        archive
+            .add(&document, &markdown, html.as_deref())
+            .map_err(archive_failed)?;
+        flush().await?;
+    }
+
+    archive.zip.finish().map_err(archive_failed)
+}
+
+//Shared between the zip writer and the task handing its output to the response
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.lock().expect("Buffer lock is never poisoned"))
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0
+            .lock()
+            .expect("Buffer lock is never poisoned")
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Streams a zip of every document of the caller as `.md` files with their
+/// metadata in the front-matter, `?html=true` adds rendered `.html` files.
+pub async fn export_documents(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+    Query(options): Query<ExportOptions>,
+) -> Response {
+    let user_id = ctx.user_id();
+    let pool = app_state.pool.clone();
+    let buffer = SharedBuffer::default();
+    let (sender, receiver) = tokio::sync::mpsc::channel::<std::io::Result<Vec<u8>>>(4);
+
+    info!("Exporting documents of user {}", user_id);
+
+    tokio::spawn(async move {
+        let send_chunk = |sender: tokio::sync::mpsc::Sender<_>, chunk: Vec<u8>| async move {
+            if chunk.is_empty() {
+                return Ok(());
+            }
+
+            //The client went away or stopped reading, stop writing
+            match tokio::time::timeout(SEND_TIMEOUT, sender.send(Ok(chunk))).await {
+                Ok(Ok(())) => Ok(()),
+                Ok(Err(_)) => Err(StudyBuddyError::ArchiveFailed(
+                    "Download was cancelled".to_string(),
+                )),
+                Err(_) => Err(StudyBuddyError::ArchiveFailed(
+                    "Download stalled".to_string(),
+                )),
+            }
+        };
+
+        let writer = ZipWriter::new_stream(buffer.clone());
+        let written = write_archive(&pool, user_id, writer, options, || {
+            send_chunk(sender.clone(), buffer.take())
+        })
+        .await;
+
+        let result = match written {
+            Ok(_) => send_chunk(sender.clone(), buffer.take()).await,
+            Err(err) => Err(err),
+        };
+
+        if let Err(err) = result {
+            error!("Export of user {} failed: {}", user_id, err);
+            //Ends the response with an error so the client doesn't keep a truncated zip
+            let error = std::io::Error::other(err.to_string());
+            let _ = tokio::time::timeout(SEND_TIMEOUT, sender.send(Err(error))).await;
+        }
+    });
+
+    let chunks = futures::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    });
+
+    (
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", ARCHIVE_NAME),
+            ),
+        ],
+        StreamBody::new(chunks),
+    )
+        .into_response()
+}
@@ -0,0 +1,459 @@
+use crate::authorization::authorize_notebook;
+use crate::parsing::split_front_matter;
+use crate::revisions::record_revision;
+use crate::server::AppState;
+use crate::tags::sync_content_tags;
+use crate::users::UserCtx;
+use crate::StudyBuddyError;
+use axum::{
+    extract::{Multipart, State},
+    Json,
+};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::path::Path;
+use std::sync::Arc;
+use tracing::info;
+
+/// Largest request `/import_documents` accepts.
+pub const MAX_UPLOAD_SIZE: usize = 32 * 1024 * 1024;
+
+//Zip entries are checked against these before being decompressed, so a small
+//archive can't expand into something that takes the server down
+const MAX_ARCHIVE_ENTRIES: usize = 2_000;
+const MAX_FILE_SIZE: u64 = 4 * 1024 * 1024;
+//Every file of an import is held in memory at once, this bounds all of them
+const MAX_IMPORT_SIZE: u64 = 64 * 1024 * 1024;
+
+const MARKDOWN_EXTENSIONS: [&str; 2] = ["md", "markdown"];
+
+/// A markdown file pulled out of an upload, `path` is relative to the upload
+/// and decides the notebooks the document ends up in.
+pub struct ImportFile {
+    pub path: String,
+    pub contents: Result<String, String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ImportResult {
+    pub path: String,
+    pub document_id: Option<uuid::Uuid>,
+    pub title: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ImportReport {
+    pub imported: usize,
+    pub failed: usize,
+    pub files: Vec<ImportResult>,
+}
+
+fn has_extension(path: &str, extensions: &[&str]) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            extensions
+                .iter()
+                .any(|wanted| extension.eq_ignore_ascii_case(wanted))
+        })
+}
+
+//Drops empty, `.` and `..` components so paths can only ever go down
+fn path_components(path: &str) -> Vec<&str> {
+    path.split(['/', '\\'])
+        .map(str::trim)
+        .filter(|component| !component.is_empty() && *component != "." && *component != "..")
+        .collect()
+}
+
+fn decode(path: &str, bytes: Vec<u8>) -> ImportFile {
+    ImportFile {
+        path: path.to_string(),
+        contents: String::from_utf8(bytes).map_err(|_| "File isn't valid UTF-8".to_string()),
+    }
+}
+
+/// Every markdown file inside a zip archive, other files are skipped. Fails
+/// once the files decompress to more than `max_total` bytes together.
+pub fn read_archive(bytes: &[u8], max_total: u64) -> Result<Vec<ImportFile>, String> {
+    let mut archive =
+        zip::ZipArchive::new(Cursor::new(bytes)).map_err(|err| format!("Invalid zip: {}", err))?;
+
+    if archive.len() > MAX_ARCHIVE_ENTRIES {
+        return Err(format!(
+            "Archive has more than {} entries",
+            MAX_ARCHIVE_ENTRIES
+        ));
+    }
+
+    let mut files = Vec::new();
+    let mut total = 0;
+    let too_large = || format!("Archive expands to more than {} bytes", max_total);
+
+    for index in 0..archive.len() {
+        let mut entry = archive
+            .by_index(index)
+            .map_err(|err| format!("Invalid zip: {}", err))?;
+
+        let Some(path) = entry
+            .enclosed_name()
+            .and_then(|path| path.to_str().map(str::to_string))
+        else {
+            continue;
+        };
+
+        let hidden = path_components(&path)
+            .iter()
+            .any(|component| component.starts_with('.') || *component == "__MACOSX");
+
+        if entry.is_dir() || hidden || !has_extension(&path, &MARKDOWN_EXTENSIONS) {
+            continue;
+        }
+
+        if entry.size() > MAX_FILE_SIZE {
+            files.push(ImportFile {
+                path,
+                contents: Err(format!("File is larger than {} bytes", MAX_FILE_SIZE)),
+            });
+            continue;
+        }
+
+        let remaining = max_total - total;
+        if entry.size() > remaining {
+            return Err(too_large());
+        }
+
+        let mut bytes = Vec::with_capacity(entry.size() as usize);
+        //The size in the header can lie, never read past the limits
+        let read = (&mut entry)
+            .take(MAX_FILE_SIZE.min(remaining) + 1)
+            .read_to_end(&mut bytes)
+            .map_err(|err| err.to_string());
+
+        if bytes.len() as u64 > remaining {
+            return Err(too_large());
+        }
+        total += bytes.len() as u64;
+
+        files.push(match read {
+            Ok(_) if bytes.len() as u64 > MAX_FILE_SIZE => ImportFile {
+                path,
+                contents: Err(format!("File is larger than {} bytes", MAX_FILE_SIZE)),
+            },
+            Ok(_) => decode(&path, bytes),
+            Err(err) => ImportFile {
+                path,
+                contents: Err(err),
+            },
+        });
+    }
+
+    Ok(files)
+}
+
+/// Title of an imported document, the front-matter `title`, else the first
+/// heading, else the file name without its extension.
+pub fn import_title(path: &str, markdown: &str) -> String {
+    let (front_matter, body) = split_front_matter(markdown);
+
+    let from_front_matter = front_matter
+        .as_ref()
+        .and_then(|front_matter| front_matter.get("title"))
+        .map(str::trim)
+        .filter(|title| !title.is_empty())
+        .map(str::to_string);
+
+    let from_heading = || {
+        let mut in_fence = false;
+
+        body.lines().find_map(|line| {
+            let line = line.trim_start();
+
+            if line.starts_with("```") || line.starts_with("~~~") {
+                in_fence = !in_fence;
+                return None;
+            }
+
+            let heading = line.trim_start_matches('#');
+            let level = line.len() - heading.len();
+
+            (!in_fence && (1..=6).contains(&level) && heading.starts_with(' '))
+                .then(|| heading.trim().trim_end_matches('#').trim().to_string())
+                .filter(|title| !title.is_empty())
+        })
+    };
+
+    let from_file_name = || {
+        Path::new(path)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .map(str::trim)
+            .filter(|stem| !stem.is_empty())
+            .unwrap_or("Untitled")
+            .to_string()
+    };
+
+    from_front_matter
+        .or_else(from_heading)
+        .unwrap_or_else(from_file_name)
+}
+
+//Notebooks are matched by name under the same parent, so importing next to
+//existing notebooks fills them instead of making look-alikes
+struct NotebookResolver {
+    user_id: uuid::Uuid,
+    known: HashMap<(Option<uuid::Uuid>, String), uuid::Uuid>,
+}
+
+impl NotebookResolver {
+    async fn resolve(
+        &mut self,
+        pool: &PgPool,
+        mut parent_id: Option<uuid::Uuid>,
+        names: &[&str],
+    ) -> Result<Option<uuid::Uuid>, StudyBuddyError> {
+        for name in names {
+            let key = (parent_id, name.to_string());
+
+            if let Some(notebook_id) = self.known.get(&key) {
+                parent_id = Some(*notebook_id);
+                continue;
+            }
+
+            let existing = sqlx::query_scalar!(
+                "SELECT notebook_id
+                FROM notebooks
+                WHERE user_id = $1 AND parent_id IS NOT DISTINCT FROM $2 AND name = $3
+                ORDER BY created_at
+                LIMIT 1",
+                self.user_id,
+                parent_id,
+                name
+            )
+            .fetch_optional(pool)
+            .await?;
+
+            let notebook_id = match existing {
+                Some(notebook_id) => notebook_id,
+                None => {
+                    let notebook_id = uuid::Uuid::new_v4();
+
+                    sqlx::query!(
+                        "INSERT INTO notebooks (notebook_id, user_id, parent_id, name)
+                        VALUES ($1, $2, $3, $4)",
+                        notebook_id,
+                        self.user_id,
+                        parent_id,
+                        name
+                    )
+                    .execute(pool)
+                    .await?;
+
+                    notebook_id
+                }
+            };
+
+            self.known.insert(key, notebook_id);
+            parent_id = Some(notebook_id);
+        }
+
+        Ok(parent_id)
+    }
+}
+
+async fn import_file(
+    pool: &PgPool,
+    user_id: uuid::Uuid,
+    notebooks: &mut NotebookResolver,
+    base_notebook: Option<uuid::Uuid>,
+    path: &str,
+    content: &str,
+) -> Result<(uuid::Uuid, String), StudyBuddyError> {
+    let (front_matter, _) = split_front_matter(content);
+
+    //A `folder` in the front-matter wins over where the file sits in the upload
+    let folder = front_matter
+        .as_ref()
+        .and_then(|front_matter| front_matter.get("folder").or(front_matter.get("notebook")))
+        .map(str::to_string);
+
+    let components = path_components(path);
+    let names = match &folder {
+        Some(folder) => path_components(folder),
+        None => components[..components.len().saturating_sub(1)].to_vec(),
+    };
+
+    let notebook_id = notebooks.resolve(pool, base_notebook, &names).await?;
+    let title = import_title(path, content);
+    let document_id = uuid::Uuid::new_v4();
+
+    let mut transaction = pool.begin().await?;
+
+    sqlx::query!(
+        "INSERT INTO documents (document_id, user_id, title, content, notebook_id)
+        VALUES ($1, $2, $3, $4, $5)",
+        document_id,
+        user_id,
+        title,
+        content,
+        notebook_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    record_revision(&mut transaction, document_id, content).await?;
+    sync_content_tags(&mut transaction, document_id, content).await?;
+
+    transaction.commit().await?;
+
+    Ok((document_id, title))
+}
+
+/// Creates a document for every file, inside `base_notebook` when given. A
+/// file that can't be imported doesn't stop the others, its result says why.
+pub async fn import_files(
+    pool: &PgPool,
+    user_id: uuid::Uuid,
+    base_notebook: Option<uuid::Uuid>,
+    files: Vec<ImportFile>,
+) -> ImportReport {
+    let mut notebooks = NotebookResolver {
+        user_id,
+        known: HashMap::new(),
+    };
+    let mut results = Vec::with_capacity(files.len());
+
+    for file in files {
+        let imported = match &file.contents {
+            Ok(content) => import_file(
+                pool,
+                user_id,
+                &mut notebooks,
+                base_notebook,
+                &file.path,
+                content,
+            )
+            .await
+            .map_err(|err| err.client_parts().1.to_string()),
+            Err(err) => Err(err.clone()),
+        };
+
+        results.push(match imported {
+            Ok((document_id, title)) => ImportResult {
+                path: file.path,
+                document_id: Some(document_id),
+                title: Some(title),
+                error: None,
+            },
+            Err(error) => ImportResult {
+                path: file.path,
+                document_id: None,
+                title: None,
+                error: Some(error),
+            },
+        });
+    }
+
+    let imported = results
+        .iter()
+        .filter(|result| result.document_id.is_some())
+        .count();
+
+    ImportReport {
+        imported,
+        failed: results.len() - imported,
+        files: results,
+    }
+}
+
+/// Imports uploaded `.md` files and zip archives of them, folders inside an
+/// archive become notebooks. A `notebook_id` field puts everything inside an
+/// existing notebook.
+pub async fn import_documents(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+    mut multipart: Multipart,
+) -> Result<Json<ImportReport>, StudyBuddyError> {
+    let pool = &app_state.pool;
+    let mut base_notebook = None;
+    let mut files = Vec::new();
+    let mut total = 0;
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|_| StudyBuddyError::InvalidUpload)?
+    {
+        if field.name() == Some("notebook_id") {
+            let text = field
+                .text()
+                .await
+                .map_err(|_| StudyBuddyError::InvalidUpload)?;
+            let notebook_id = uuid::Uuid::parse_str(text.trim())
+                .map_err(|_| StudyBuddyError::NotebookNotFound)?;
+
+            authorize_notebook(pool, &ctx, notebook_id).await?;
+            base_notebook = Some(notebook_id);
+            continue;
+        }
+
+        let Some(file_name) = field.file_name().map(str::to_string) else {
+            continue;
+        };
+
+        let bytes = field
+            .bytes()
+            .await
+            .map_err(|_| StudyBuddyError::InvalidUpload)?;
+
+        if has_extension(&file_name, &["zip"]) {
+            let bytes = bytes.to_vec();
+            let max_total = MAX_IMPORT_SIZE.saturating_sub(total);
+            let archive = tokio::task::spawn_blocking(move || read_archive(&bytes, max_total))
+                .await
+                .map_err(|_| StudyBuddyError::InvalidUpload)?;
+
+            match archive {
+                Ok(archive_files) => {
+                    total += archive_files
+                        .iter()
+                        .filter_map(|file| file.contents.as_ref().ok())
+                        .map(|contents| contents.len() as u64)
+                        .sum::<u64>();
+                    files.extend(archive_files);
+                }
+                Err(err) => files.push(ImportFile {
+                    path: file_name,
+                    contents: Err(err),
+                }),
+            }
+        } else if has_extension(&file_name, &MARKDOWN_EXTENSIONS) {
+            total += bytes.len() as u64;
+            files.push(decode(&file_name, bytes.to_vec()));
+        } else {
+            files.push(ImportFile {
+                path: file_name,
+                contents: Err("Only .md files and zip archives can be imported".to_string()),
+            });
+        }
+    }
+
+    if files.is_empty() {
+        return Err(StudyBuddyError::IncompleteRequest);
+    }
+
+    let report = import_files(pool, ctx.user_id(), base_notebook, files).await;
+
+    info!(
+        "Imported {} documents for user {}, {} failed",
+        report.imported,
+        ctx.user_id(),
+        report.failed
+    );
+
+    Ok(Json(report))
+}
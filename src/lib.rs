@@ -1,6 +1,28 @@
+pub mod authorization;
+pub mod blocks;
+pub mod collaboration;
+pub mod config;
+pub mod database;
 mod error;
+pub mod export;
+pub mod import;
+pub mod live;
+pub mod mail;
+pub mod notebooks;
+pub mod ot;
 mod parsing;
+pub mod pdf;
+pub mod recovery;
+pub mod request_id;
+pub mod revisions;
+pub mod search;
 pub mod server;
+pub mod sessions;
+pub mod sharing;
+pub mod tags;
+pub mod throttle;
+pub mod trash;
+pub mod user_data;
 pub mod users;
 
 pub use error::{StudyBuddyError, StudyBuddySessionError};
@@ -0,0 +1,1096 @@
+use crate::authorization::{authorize_document_access, Access};
+use crate::blocks::{BlockPatch, BlockRenderer};
+use crate::collaboration::{
+    self, Membership, Participant, RoomError, RoomEvent, RoomMessage, Selection,
+};
+use crate::ot::Operation;
+use crate::server::AppState;
+use crate::sessions::touch_session;
+use crate::users::{store_document_content, UserCtx};
+use crate::{StudyBuddyError, StudyBuddySessionError};
+use axum::{
+    extract::{
+        ws::{Message, WebSocket},
+        State, WebSocketUpgrade,
+    },
+    http::{header, HeaderMap, Uri},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::info;
+
+/// Subprotocols a client asks for to speak the JSON protocol, connections that
+/// don't ask for either get the legacy raw text preview. Renders in `v1` are
+/// whole documents, in `v2` they only carry the blocks that changed.
+pub const PROTOCOL_V1: &str = "study_buddy.v1";
+pub const PROTOCOL_V2: &str = "study_buddy.v2";
+
+/// Largest document a connection renders, edits that would make a document
+/// larger are rejected.
+pub const MAX_DOCUMENT_SIZE: usize = 1024 * 1024;
+
+//Leaves room for the JSON escaping of a document of the largest size
+const MAX_MESSAGE_SIZE: usize = 4 * MAX_DOCUMENT_SIZE;
+
+//Checked between blocks, so a huge paste can't keep a thread of the blocking
+//pool busy for long
+const RENDER_TIMEOUT: Duration = Duration::from_secs(2);
+
+const MAX_OPEN_DOCUMENTS: usize = 16;
+
+//Operations don't touch the database themselves but get autosaved, so the
+//session is checked again every so often while only operations arrive
+const SESSION_RECHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+//Room events waiting to be sent to the client
+const ROOM_EVENT_BUFFER: usize = 64;
+
+const TOO_LARGE_NOTICE: &str = "<p><em>The document is too large to preview.</em></p>";
+const TIMED_OUT_NOTICE: &str = "<p><em>The preview took too long to render.</em></p>";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Version {
+    V1,
+    V2,
+}
+
+/// Replaces the text between `start` and `end` with `text`. Offsets count
+/// UTF-16 code units, the way browsers index strings.
+#[derive(Deserialize, Debug)]
+pub struct TextChange {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+/// Messages a client sends. `seq` is picked by the client and has to grow with
+/// every edit of a document, renders carry the `seq` of the edit they show.
+/// A `patch` applies its changes in order to the text as of `base_seq`.
+///
+/// Documents that are `join`ed are edited together with everyone else who
+/// joined them. Instead of edits they take `operation`s made on the
+/// `version` the client last got from the server, which merges them with the
+/// operations of others and autosaves the result.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Open {
+        document_id: uuid::Uuid,
+    },
+    Edit {
+        document_id: uuid::Uuid,
+        seq: u64,
+        text: String,
+    },
+    Patch {
+        document_id: uuid::Uuid,
+        seq: u64,
+        base_seq: u64,
+        changes: Vec<TextChange>,
+    },
+    Save {
+        document_id: uuid::Uuid,
+    },
+    Close {
+        document_id: uuid::Uuid,
+    },
+    Join {
+        document_id: uuid::Uuid,
+    },
+    Operation {
+        document_id: uuid::Uuid,
+        version: u64,
+        operation: Operation,
+    },
+    Select {
+        document_id: uuid::Uuid,
+        version: u64,
+        selection: Selection,
+    },
+}
+
+/// Messages the server sends. In a joined document `seq` is the version of
+/// the document, which goes up by one with every operation. Every operation
+/// applied comes back in order, as an `ack` to the client that sent it and as
+/// an `operation` to everyone else.
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Opened {
+        document_id: uuid::Uuid,
+        title: String,
+        content: String,
+        seq: u64,
+    },
+    Render {
+        document_id: uuid::Uuid,
+        seq: u64,
+        html: String,
+    },
+    /// Replaces `delete_count` blocks of the render from `start` with
+    /// `blocks`, a client starts from no blocks when a document is opened.
+    RenderBlocks {
+        document_id: uuid::Uuid,
+        seq: u64,
+        start: usize,
+        delete_count: usize,
+        blocks: Vec<String>,
+    },
+    Saved {
+        document_id: uuid::Uuid,
+        seq: u64,
+    },
+    Closed {
+        document_id: uuid::Uuid,
+    },
+    Joined {
+        document_id: uuid::Uuid,
+        title: String,
+        content: String,
+        version: u64,
+        connection_id: uuid::Uuid,
+        participants: Vec<Participant>,
+    },
+    Ack {
+        document_id: uuid::Uuid,
+        version: u64,
+    },
+    Operation {
+        document_id: uuid::Uuid,
+        version: u64,
+        operation: Operation,
+        connection_id: uuid::Uuid,
+        user_id: uuid::Uuid,
+    },
+    Presence {
+        document_id: uuid::Uuid,
+        participant: Participant,
+    },
+    Left {
+        document_id: uuid::Uuid,
+        connection_id: uuid::Uuid,
+    },
+    Error {
+        document_id: Option<uuid::Uuid>,
+        seq: Option<u64>,
+        code: &'static str,
+        message: String,
+    },
+}
+
+impl ServerMessage {
+    fn error(document_id: Option<uuid::Uuid>, code: &'static str, message: &str) -> Self {
+        ServerMessage::Error {
+            document_id,
+            seq: None,
+            code,
+            message: message.to_string(),
+        }
+    }
+
+    fn from_error(document_id: Option<uuid::Uuid>, err: &StudyBuddyError) -> Self {
+        let (code, message) = err.client_parts();
+        ServerMessage::error(document_id, code, message)
+    }
+
+    fn rejected_edit(document_id: uuid::Uuid, seq: u64, code: &'static str, message: &str) -> Self {
+        ServerMessage::Error {
+            document_id: Some(document_id),
+            seq: Some(seq),
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+//Unsaved state of a document the connection has open
+struct OpenDocument {
+    title: String,
+    text: String,
+    seq: u64,
+    //Tells apart a document from one opened again after being closed while
+    //its render was running
+    generation: u64,
+    //`None` while the document is being rendered
+    renderer: Option<BlockRenderer>,
+    //The text changed since the last render started
+    stale: bool,
+    //The next render has to send every block, not just the changed ones
+    full: bool,
+    //Set for joined documents, whose text follows the room
+    membership: Option<Membership>,
+}
+
+//Which document a render is for, and at which edit
+struct RenderTarget {
+    document_id: uuid::Uuid,
+    generation: u64,
+    seq: u64,
+    full: bool,
+}
+
+//A render on the blocking pool, the renderer comes back along with the patch
+//or `None` when it ran out of time
+type RenderTask<T> = JoinHandle<(T, BlockRenderer, Option<BlockPatch>)>;
+
+fn start_render<T: Send + 'static>(
+    target: T,
+    mut renderer: BlockRenderer,
+    text: String,
+) -> RenderTask<T> {
+    tokio::task::spawn_blocking(move || {
+        let patch = renderer.update_until(&text, Instant::now() + RENDER_TIMEOUT);
+        (target, renderer, patch)
+    })
+}
+
+//Resolves once the running task is done and clears it, never without one.
+//Dropping it before then leaves the task running
+async fn finished<T>(task: &mut Option<JoinHandle<T>>) -> T {
+    let Some(running) = task else {
+        return std::future::pending().await;
+    };
+
+    let result = running.await.expect("Task cant panic");
+    *task = None;
+    result
+}
+
+struct LiveSession {
+    app_state: Arc<AppState>,
+    ctx: UserCtx,
+    version: Version,
+    //Tells apart the connections of a user in the rooms they join
+    connection_id: uuid::Uuid,
+    session_checked_at: Instant,
+    documents: HashMap<uuid::Uuid, OpenDocument>,
+    next_generation: u64,
+    //Only one render runs per connection, edits made meanwhile are coalesced
+    //into the next one
+    rendering: Option<RenderTask<RenderTarget>>,
+    room_sender: mpsc::Sender<RoomMessage>,
+    room_events: mpsc::Receiver<RoomMessage>,
+}
+
+enum Outcome {
+    Continue,
+    SessionEnded,
+}
+
+//Browsers send the origin of the page opening a socket, a page of another
+//site must not get one that acts with the user's cookie. Clients that aren't
+//browsers don't send one and can't be made to use someone else's cookie
+fn same_origin(headers: &HeaderMap) -> bool {
+    let Some(origin) = headers.get(header::ORIGIN) else {
+        return true;
+    };
+
+    let origin = origin
+        .to_str()
+        .ok()
+        .and_then(|origin| origin.parse::<Uri>().ok());
+    let origin_host = origin.as_ref().and_then(Uri::authority);
+    let host = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok());
+
+    matches!((origin_host, host), (Some(origin), Some(host)) if origin.as_str().eq_ignore_ascii_case(host))
+}
+
+//The newest version both sides speak, like `WebSocketUpgrade::protocols` picks
+fn negotiate(headers: &HeaderMap) -> Option<Version> {
+    let offered: Vec<&str> = headers
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+
+    [(PROTOCOL_V2, Version::V2), (PROTOCOL_V1, Version::V1)]
+        .into_iter()
+        .find(|(protocol, _)| offered.contains(protocol))
+        .map(|(_, version)| version)
+}
+
+fn byte_offset(text: &str, offset: usize) -> Option<usize> {
+    let mut units = 0;
+
+    for (index, c) in text.char_indices() {
+        if units == offset {
+            return Some(index);
+        }
+
+        units += c.len_utf16();
+
+        //Half of a surrogate pair
+        if units > offset {
+            return None;
+        }
+    }
+
+    (units == offset).then_some(text.len())
+}
+
+/// `text` with `changes` applied, `None` when one of them is out of bounds.
+pub fn apply_changes(text: &str, changes: &[TextChange]) -> Option<String> {
+    let mut text = text.to_string();
+
+    for change in changes {
+        let start = byte_offset(&text, change.start)?;
+        let end = byte_offset(&text, change.end)?;
+
+        if start > end {
+            return None;
+        }
+
+        text.replace_range(start..end, &change.text);
+    }
+
+    Some(text)
+}
+
+async fn send(socket: &mut WebSocket, message: &ServerMessage) -> bool {
+    let json = serde_json::to_string(message).expect("Server messages always serialize");
+    socket.send(Message::Text(json)).await.is_ok()
+}
+
+impl LiveSession {
+    async fn session_is_valid(&mut self) -> bool {
+        self.session_checked_at = Instant::now();
+
+        let app_state = &self.app_state;
+        matches!(
+            touch_session(
+                &app_state.pool,
+                &app_state.session_policy,
+                self.ctx.session_id()
+            )
+            .await,
+            Ok(Some(_))
+        )
+    }
+
+    async fn open(
+        &mut self,
+        document_id: uuid::Uuid,
+    ) -> Result<Vec<ServerMessage>, StudyBuddyError> {
+        if !self.documents.contains_key(&document_id) {
+            if self.documents.len() >= MAX_OPEN_DOCUMENTS {
+                return Ok(vec![ServerMessage::error(
+                    Some(document_id),
+                    "too_many_documents",
+                    "Close a document before opening another one",
+                )]);
+            }
+
+            let pool = &self.app_state.pool;
+            authorize_document_access(pool, &self.ctx, document_id, Access::Viewer).await?;
+
+            let stored = sqlx::query!(
+                "SELECT title, content
+                FROM documents
+                WHERE document_id = $1 AND deleted_at IS NULL",
+                document_id
+            )
+            .fetch_optional(pool)
+            .await?
+            .ok_or(StudyBuddyError::DocumentNotFound)?;
+
+            self.next_generation += 1;
+            self.documents.insert(
+                document_id,
+                OpenDocument {
+                    title: stored.title,
+                    text: stored.content,
+                    seq: 0,
+                    generation: self.next_generation,
+                    renderer: Some(BlockRenderer::default()),
+                    stale: true,
+                    full: true,
+                    membership: None,
+                },
+            );
+        }
+
+        //Opening a document twice hands back the unsaved text instead of the stored one
+        let document = self
+            .documents
+            .get_mut(&document_id)
+            .expect("Document was opened above");
+        document.stale = true;
+        document.full = true;
+
+        Ok(vec![ServerMessage::Opened {
+            document_id,
+            title: document.title.clone(),
+            content: document.text.clone(),
+            seq: document.seq,
+        }])
+    }
+
+    //The open document an edit numbered `seq` applies to
+    fn editable(
+        &mut self,
+        document_id: uuid::Uuid,
+        seq: u64,
+    ) -> Result<&mut OpenDocument, ServerMessage> {
+        let document = self
+            .documents
+            .get_mut(&document_id)
+            .ok_or_else(|| not_open(document_id))?;
+
+        if document.membership.is_some() {
+            return Err(ServerMessage::rejected_edit(
+                document_id,
+                seq,
+                "joined_document",
+                "Send operations to edit a joined document",
+            ));
+        }
+
+        //An edit older than one already applied arrived late, rendering it
+        //would only show the client something it has moved past
+        if seq <= document.seq {
+            return Err(ServerMessage::rejected_edit(
+                document_id,
+                seq,
+                "stale_edit",
+                "A newer edit of this document was already received",
+            ));
+        }
+
+        Ok(document)
+    }
+
+    fn edit(
+        &mut self,
+        document_id: uuid::Uuid,
+        seq: u64,
+        text: String,
+    ) -> Result<(), ServerMessage> {
+        let document = self.editable(document_id, seq)?;
+        update_text(document, document_id, seq, text)
+    }
+
+    fn patch(
+        &mut self,
+        document_id: uuid::Uuid,
+        seq: u64,
+        base_seq: u64,
+        changes: &[TextChange],
+    ) -> Result<(), ServerMessage> {
+        let document = self.editable(document_id, seq)?;
+
+        //The client has to send the whole text again once it misses an edit
+        if base_seq != document.seq {
+            return Err(ServerMessage::rejected_edit(
+                document_id,
+                seq,
+                "out_of_sync",
+                "The patch isn't based on the latest edit, send the whole text",
+            ));
+        }
+
+        let text = apply_changes(&document.text, changes).ok_or_else(|| {
+            ServerMessage::rejected_edit(
+                document_id,
+                seq,
+                "invalid_patch",
+                "A change of the patch is outside of the text",
+            )
+        })?;
+
+        update_text(document, document_id, seq, text)
+    }
+
+    //Starts rendering a document that changed, unless a render is running
+    fn schedule_render(&mut self) {
+        if self.rendering.is_some() {
+            return;
+        }
+
+        let Some((document_id, document)) = self
+            .documents
+            .iter_mut()
+            .find(|(_, document)| document.stale && document.renderer.is_some())
+        else {
+            return;
+        };
+
+        let target = RenderTarget {
+            document_id: *document_id,
+            generation: document.generation,
+            seq: document.seq,
+            full: std::mem::take(&mut document.full),
+        };
+        let renderer = document.renderer.take().expect("Checked above");
+
+        document.stale = false;
+        self.rendering = Some(start_render(target, renderer, document.text.clone()));
+    }
+
+    //The message for a finished render, `None` when its document was closed
+    fn rendered(
+        &mut self,
+        target: RenderTarget,
+        renderer: BlockRenderer,
+        patch: Option<BlockPatch>,
+    ) -> Option<ServerMessage> {
+        let document = self
+            .documents
+            .get_mut(&target.document_id)
+            .filter(|document| document.generation == target.generation)?;
+        let renderer = document.renderer.insert(renderer);
+        let document_id = target.document_id;
+        let seq = target.seq;
+
+        let Some(patch) = patch else {
+            //The client still needs every block once a render gets through
+            document.full |= target.full;
+
+            return Some(ServerMessage::rejected_edit(
+                document_id,
+                seq,
+                "render_timeout",
+                "The document took too long to render",
+            ));
+        };
+
+        Some(match self.version {
+            Version::V1 => ServerMessage::Render {
+                document_id,
+                seq,
+                html: renderer.html(),
+            },
+            Version::V2 if target.full => ServerMessage::RenderBlocks {
+                document_id,
+                seq,
+                start: 0,
+                delete_count: 0,
+                blocks: renderer.blocks(),
+            },
+            Version::V2 => ServerMessage::RenderBlocks {
+                document_id,
+                seq,
+                start: patch.start,
+                delete_count: patch.delete_count,
+                blocks: patch.blocks,
+            },
+        })
+    }
+
+    async fn join(
+        &mut self,
+        document_id: uuid::Uuid,
+    ) -> Result<Vec<ServerMessage>, StudyBuddyError> {
+        //Joining starts over from the merged text, unsaved edits of a document
+        //opened on its own are dropped
+        self.documents.remove(&document_id);
+
+        if self.documents.len() >= MAX_OPEN_DOCUMENTS {
+            return Ok(vec![ServerMessage::error(
+                Some(document_id),
+                "too_many_documents",
+                "Close a document before opening another one",
+            )]);
+        }
+
+        let pool = &self.app_state.pool;
+        let role = authorize_document_access(pool, &self.ctx, document_id, Access::Viewer).await?;
+
+        let title = sqlx::query_scalar!(
+            "SELECT title FROM documents WHERE document_id = $1 AND deleted_at IS NULL",
+            document_id
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(StudyBuddyError::DocumentNotFound)?;
+
+        let email =
+            sqlx::query_scalar!("SELECT email FROM users WHERE id = $1", self.ctx.user_id())
+                .fetch_one(pool)
+                .await?;
+
+        let participant = Participant {
+            connection_id: self.connection_id,
+            user_id: self.ctx.user_id(),
+            email,
+            role,
+            selection: None,
+        };
+
+        self.next_generation += 1;
+        let joined = collaboration::join(
+            &self.app_state,
+            document_id,
+            participant,
+            self.next_generation,
+            self.room_sender.clone(),
+        )
+        .await?;
+
+        info!(
+            "User {} joined document {} at version {}",
+            self.ctx.user_id(),
+            document_id,
+            joined.version
+        );
+
+        self.documents.insert(
+            document_id,
+            OpenDocument {
+                title: title.clone(),
+                text: joined.text.clone(),
+                seq: joined.version,
+                generation: self.next_generation,
+                renderer: Some(BlockRenderer::default()),
+                stale: true,
+                full: true,
+                membership: Some(joined.membership),
+            },
+        );
+
+        Ok(vec![ServerMessage::Joined {
+            document_id,
+            title,
+            content: joined.text,
+            version: joined.version,
+            connection_id: self.connection_id,
+            participants: joined.participants,
+        }])
+    }
+
+    fn membership(&self, document_id: uuid::Uuid) -> Result<&Membership, ServerMessage> {
+        self.documents
+            .get(&document_id)
+            .and_then(|document| document.membership.as_ref())
+            .ok_or_else(|| {
+                ServerMessage::error(
+                    Some(document_id),
+                    "document_not_joined",
+                    "Join the document before sending operations",
+                )
+            })
+    }
+
+    //Keeps the text of a joined document in step with its room, the message
+    //for the client is `None` for its own presence
+    fn room_event(&mut self, message: RoomMessage) -> Option<ServerMessage> {
+        let document_id = message.document_id;
+        let document = self
+            .documents
+            .get_mut(&document_id)
+            .filter(|document| document.generation == message.generation)?;
+
+        match message.event {
+            RoomEvent::Operation {
+                version,
+                operation,
+                connection_id,
+                user_id,
+            } => {
+                let text = operation
+                    .apply(&document.text)
+                    .filter(|_| version == document.seq + 1);
+
+                let Some(text) = text else {
+                    self.documents.remove(&document_id);
+                    return Some(fell_behind(document_id));
+                };
+
+                document.text = text;
+                document.seq = version;
+                document.stale = true;
+
+                Some(if connection_id == self.connection_id {
+                    ServerMessage::Ack {
+                        document_id,
+                        version,
+                    }
+                } else {
+                    ServerMessage::Operation {
+                        document_id,
+                        version,
+                        operation,
+                        connection_id,
+                        user_id,
+                    }
+                })
+            }
+            RoomEvent::Presence(participant) => (participant.connection_id != self.connection_id)
+                .then_some(ServerMessage::Presence {
+                    document_id,
+                    participant,
+                }),
+            RoomEvent::Left { connection_id } if connection_id == self.connection_id => {
+                self.documents.remove(&document_id);
+                Some(ServerMessage::from_error(
+                    Some(document_id),
+                    &StudyBuddyError::Forbidden,
+                ))
+            }
+            RoomEvent::Left { connection_id } => Some(ServerMessage::Left {
+                document_id,
+                connection_id,
+            }),
+            RoomEvent::Lagged => {
+                self.documents.remove(&document_id);
+                Some(fell_behind(document_id))
+            }
+            RoomEvent::Closed => {
+                self.documents.remove(&document_id);
+                Some(ServerMessage::from_error(
+                    Some(document_id),
+                    &StudyBuddyError::DocumentNotFound,
+                ))
+            }
+        }
+    }
+
+    async fn save(&self, document_id: uuid::Uuid) -> Result<ServerMessage, StudyBuddyError> {
+        let Some(document) = self.documents.get(&document_id) else {
+            return Ok(not_open(document_id));
+        };
+
+        let pool = &self.app_state.pool;
+        authorize_document_access(pool, &self.ctx, document_id, Access::Editor).await?;
+
+        if let Some(membership) = &document.membership {
+            let version = membership.save().await?;
+            return Ok(ServerMessage::Saved {
+                document_id,
+                seq: version,
+            });
+        }
+
+        //Its text would overwrite what the room merged
+        if self.app_state.rooms.is_open(document_id) {
+            return Ok(ServerMessage::from_error(
+                Some(document_id),
+                &StudyBuddyError::DocumentInRoom,
+            ));
+        }
+
+        store_document_content(pool, document_id, &document.text).await?;
+
+        info!("Saved document {} from live session", document_id);
+
+        Ok(ServerMessage::Saved {
+            document_id,
+            seq: document.seq,
+        })
+    }
+
+    async fn handle(&mut self, socket: &mut WebSocket, message: ClientMessage) -> Outcome {
+        //The session can be revoked while the socket stays open, so anything
+        //that touches the database checks it again
+        let needs_session = match message {
+            ClientMessage::Open { .. }
+            | ClientMessage::Join { .. }
+            | ClientMessage::Save { .. } => true,
+            ClientMessage::Operation { .. } => {
+                self.session_checked_at.elapsed() >= SESSION_RECHECK_INTERVAL
+            }
+            _ => false,
+        };
+
+        if needs_session && !self.session_is_valid().await {
+            let ended = ServerMessage::error(None, "invalid_session", "Invalid user session");
+            send(socket, &ended).await;
+            return Outcome::SessionEnded;
+        }
+
+        let replies = match message {
+            ClientMessage::Open { document_id } => self
+                .open(document_id)
+                .await
+                .unwrap_or_else(|err| vec![ServerMessage::from_error(Some(document_id), &err)]),
+            ClientMessage::Edit {
+                document_id,
+                seq,
+                text,
+            } => self
+                .edit(document_id, seq, text)
+                .err()
+                .into_iter()
+                .collect(),
+            ClientMessage::Patch {
+                document_id,
+                seq,
+                base_seq,
+                changes,
+            } => self
+                .patch(document_id, seq, base_seq, &changes)
+                .err()
+                .into_iter()
+                .collect(),
+            ClientMessage::Save { document_id } => vec![self
+                .save(document_id)
+                .await
+                .unwrap_or_else(|err| ServerMessage::from_error(Some(document_id), &err))],
+            ClientMessage::Close { document_id } => match self.documents.remove(&document_id) {
+                Some(_) => vec![ServerMessage::Closed { document_id }],
+                None => vec![not_open(document_id)],
+            },
+            ClientMessage::Join { document_id } => self
+                .join(document_id)
+                .await
+                .unwrap_or_else(|err| vec![ServerMessage::from_error(Some(document_id), &err)]),
+            ClientMessage::Operation {
+                document_id,
+                version,
+                operation,
+            } => self
+                .membership(document_id)
+                .and_then(|membership| {
+                    membership
+                        .apply(version, operation)
+                        .map_err(|err| room_error(document_id, version, err))
+                })
+                .err()
+                .into_iter()
+                .collect(),
+            ClientMessage::Select {
+                document_id,
+                version,
+                selection,
+            } => self
+                .membership(document_id)
+                .and_then(|membership| {
+                    membership
+                        .select(version, selection)
+                        .map_err(|err| room_error(document_id, version, err))
+                })
+                .err()
+                .into_iter()
+                .collect(),
+        };
+
+        for reply in &replies {
+            if !send(socket, reply).await {
+                return Outcome::SessionEnded;
+            }
+        }
+
+        Outcome::Continue
+    }
+}
+
+fn update_text(
+    document: &mut OpenDocument,
+    document_id: uuid::Uuid,
+    seq: u64,
+    text: String,
+) -> Result<(), ServerMessage> {
+    if text.len() > MAX_DOCUMENT_SIZE {
+        return Err(ServerMessage::rejected_edit(
+            document_id,
+            seq,
+            "document_too_large",
+            "The document is too large to preview",
+        ));
+    }
+
+    document.seq = seq;
+    document.text = text;
+    document.stale = true;
+    Ok(())
+}
+
+fn room_error(document_id: uuid::Uuid, version: u64, err: RoomError) -> ServerMessage {
+    let (code, message) = match err {
+        RoomError::OutOfSync => (
+            "out_of_sync",
+            "The server no longer has the version the operation is based on, join the document again",
+        ),
+        RoomError::InvalidOperation => (
+            "invalid_operation",
+            "The operation doesn't fit the text it is based on",
+        ),
+        RoomError::TooLarge => ("document_too_large", "The document is too large to edit"),
+        RoomError::ReadOnly => ("forbidden", "You can't edit this document"),
+    };
+
+    ServerMessage::rejected_edit(document_id, version, code, message)
+}
+
+fn fell_behind(document_id: uuid::Uuid) -> ServerMessage {
+    ServerMessage::error(
+        Some(document_id),
+        "fell_behind",
+        "Missed changes of the document, join it again",
+    )
+}
+
+fn not_open(document_id: uuid::Uuid) -> ServerMessage {
+    ServerMessage::error(
+        Some(document_id),
+        "document_not_open",
+        "Open the document before editing or saving it",
+    )
+}
+
+async fn run_session(
+    mut socket: WebSocket,
+    app_state: Arc<AppState>,
+    ctx: UserCtx,
+    version: Version,
+) {
+    info!("User {} opened a {:?} live session", ctx.user_id(), version);
+
+    let (room_sender, room_events) = mpsc::channel(ROOM_EVENT_BUFFER);
+    let mut session = LiveSession {
+        app_state,
+        ctx,
+        version,
+        connection_id: uuid::Uuid::new_v4(),
+        session_checked_at: Instant::now(),
+        documents: HashMap::new(),
+        next_generation: 0,
+        rendering: None,
+        room_sender,
+        room_events,
+    };
+
+    loop {
+        let message = tokio::select! {
+            message = socket.recv() => message,
+            (target, renderer, patch) = finished(&mut session.rendering) => {
+                if let Some(reply) = session.rendered(target, renderer, patch) {
+                    if !send(&mut socket, &reply).await {
+                        return;
+                    }
+                }
+
+                session.schedule_render();
+                continue;
+            }
+            Some(event) = session.room_events.recv() => {
+                if let Some(reply) = session.room_event(event) {
+                    if !send(&mut socket, &reply).await {
+                        return;
+                    }
+                }
+
+                session.schedule_render();
+                continue;
+            }
+        };
+
+        let Some(Ok(message)) = message else {
+            return;
+        };
+
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Close(_) => return,
+            Message::Binary(_) => {
+                let invalid =
+                    ServerMessage::error(None, "invalid_message", "Messages have to be JSON text");
+                if !send(&mut socket, &invalid).await {
+                    return;
+                }
+                continue;
+            }
+            Message::Ping(_) | Message::Pong(_) => continue,
+        };
+
+        let message = match serde_json::from_str::<ClientMessage>(&text) {
+            Ok(message) => message,
+            Err(err) => {
+                let invalid = ServerMessage::error(None, "invalid_message", &err.to_string());
+                if !send(&mut socket, &invalid).await {
+                    return;
+                }
+                continue;
+            }
+        };
+
+        if let Outcome::SessionEnded = session.handle(&mut socket, message).await {
+            return;
+        }
+
+        session.schedule_render();
+    }
+}
+
+/// Renders every text frame and sends the HTML back, for clients that don't
+/// speak the JSON protocol. Frames that arrive while a render is running
+/// replace each other, only the latest one is rendered next.
+pub async fn modify_md_file_state(mut socket: WebSocket) {
+    let mut renderer = Some(BlockRenderer::default());
+    let mut rendering = None;
+    let mut pending = None;
+
+    loop {
+        tokio::select! {
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(file_state))) if file_state.len() > MAX_DOCUMENT_SIZE => {
+                    pending = None;
+                    if socket.send(Message::Text(TOO_LARGE_NOTICE.to_string())).await.is_err() {
+                        return;
+                    }
+                }
+                Some(Ok(Message::Text(file_state))) => pending = Some(file_state),
+                Some(Ok(_)) => {}
+                Some(Err(_)) | None => return,
+            },
+            ((), returned, patch) = finished(&mut rendering) => {
+                let renderer = renderer.insert(returned);
+                let html = match patch {
+                    Some(_) => renderer.html(),
+                    None => TIMED_OUT_NOTICE.to_string(),
+                };
+
+                if socket.send(Message::Text(html)).await.is_err() {
+                    return;
+                }
+            }
+        }
+
+        if rendering.is_none() {
+            if let Some(file_state) = pending.take() {
+                let idle = renderer.take().expect("No render is running");
+                rendering = Some(start_render((), idle, file_state));
+            }
+        }
+    }
+}
+
+/// Live preview socket, only pages of this site can open it. Clients offering
+/// one of the `study_buddy` subprotocols need a session and can open, edit and
+/// save documents over it, anyone else gets the legacy preview that renders
+/// raw markdown frames and touches no stored data.
+pub async fn refresh_file(
+    State(app_state): State<Arc<AppState>>,
+    ctx: Result<UserCtx, StudyBuddySessionError>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Response {
+    if !same_origin(&headers) {
+        return StudyBuddyError::CrossOrigin.into_response();
+    }
+
+    let ws = ws
+        .max_message_size(MAX_MESSAGE_SIZE)
+        .max_frame_size(MAX_MESSAGE_SIZE);
+
+    let Some(version) = negotiate(&headers) else {
+        info!("Connecting to refresh socket");
+        return ws.on_upgrade(modify_md_file_state);
+    };
+
+    let ctx = match ctx {
+        Ok(ctx) => ctx,
+        Err(err) => return err.into_response(),
+    };
+
+    ws.protocols([PROTOCOL_V2, PROTOCOL_V1])
+        .on_upgrade(move |socket| run_session(socket, app_state, ctx, version))
+}
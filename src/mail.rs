@@ -0,0 +1,52 @@
+mod memory;
+mod outbox;
+mod smtp;
+
+use crate::config::MailBackend;
+use crate::StudyBuddyError;
+use async_trait::async_trait;
+use std::sync::Arc;
+use tracing::info;
+
+pub use memory::MemoryMailer;
+pub use outbox::OutboxMailer;
+pub use smtp::{SmtpMailer, TlsMode};
+
+#[derive(Clone, Debug)]
+pub struct OutgoingMail {
+    pub to: String,
+    pub subject: String,
+    pub html_body: String,
+}
+
+/// Delivers the emails the server sends, like password recovery codes.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, mail: OutgoingMail) -> Result<(), StudyBuddyError>;
+}
+
+/// Builds the mailer picked in the config.
+pub fn mailer_from_config(backend: &MailBackend) -> Result<Arc<dyn Mailer>, StudyBuddyError> {
+    let mailer: Arc<dyn Mailer> = match backend {
+        MailBackend::Smtp(smtp) => {
+            info!("Sending mail through {} over SMTP", smtp.host);
+            Arc::new(SmtpMailer::new(
+                &smtp.host,
+                smtp.port,
+                smtp.tls,
+                &smtp.sender,
+                smtp.password.clone(),
+            )?)
+        }
+        MailBackend::File(path) => {
+            info!("Writing mail to {}", path.display());
+            Arc::new(OutboxMailer::file(path.clone()))
+        }
+        MailBackend::Stdout => {
+            info!("Writing mail to stdout");
+            Arc::new(OutboxMailer::stdout())
+        }
+    };
+
+    Ok(mailer)
+}
@@ -0,0 +1,42 @@
+use super::{Mailer, OutgoingMail};
+use crate::StudyBuddyError;
+use async_trait::async_trait;
+use std::sync::Mutex;
+
+/// Keeps every sent email in memory so tests can look at them.
+#[derive(Debug, Default)]
+pub struct MemoryMailer {
+    sent: Mutex<Vec<OutgoingMail>>,
+}
+
+impl MemoryMailer {
+    pub fn sent(&self) -> Vec<OutgoingMail> {
+        self.sent
+            .lock()
+            .expect("Mailbox lock is never poisoned")
+            .clone()
+    }
+
+    /// The most recent email sent to `to`.
+    pub fn last_sent_to(&self, to: &str) -> Option<OutgoingMail> {
+        self.sent
+            .lock()
+            .expect("Mailbox lock is never poisoned")
+            .iter()
+            .rev()
+            .find(|mail| mail.to == to)
+            .cloned()
+    }
+}
+
+#[async_trait]
+impl Mailer for MemoryMailer {
+    async fn send(&self, mail: OutgoingMail) -> Result<(), StudyBuddyError> {
+        self.sent
+            .lock()
+            .expect("Mailbox lock is never poisoned")
+            .push(mail);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+use super::{Mailer, OutgoingMail};
+use crate::StudyBuddyError;
+use async_trait::async_trait;
+use std::path::PathBuf;
+use tokio::io::AsyncWriteExt;
+
+#[derive(Debug)]
+enum Destination {
+    File(PathBuf),
+    Stdout,
+}
+
+/// Writes emails out as plain text instead of delivering them, for running
+/// the server locally without a mail server.
+#[derive(Debug)]
+pub struct OutboxMailer {
+    destination: Destination,
+}
+
+impl OutboxMailer {
+    /// Appends every email to the file at `path`.
+    pub fn file(path: PathBuf) -> Self {
+        OutboxMailer {
+            destination: Destination::File(path),
+        }
+    }
+
+    pub fn stdout() -> Self {
+        OutboxMailer {
+            destination: Destination::Stdout,
+        }
+    }
+}
+
+fn format_mail(mail: &OutgoingMail) -> String {
+    format!(
+        "To: {}\nSubject: {}\nDate: {}\n\n{}\n\n",
+        mail.to,
+        mail.subject,
+        time::OffsetDateTime::now_utc(),
+        mail.html_body
+    )
+}
+
+#[async_trait]
+impl Mailer for OutboxMailer {
+    async fn send(&self, mail: OutgoingMail) -> Result<(), StudyBuddyError> {
+        let formatted = format_mail(&mail);
+        let failed = |err: std::io::Error| StudyBuddyError::MailDeliveryFailed(err.to_string());
+
+        match &self.destination {
+            Destination::Stdout => {
+                let mut stdout = tokio::io::stdout();
+                stdout
+                    .write_all(formatted.as_bytes())
+                    .await
+                    .map_err(failed)?;
+                stdout.flush().await.map_err(failed)?;
+            }
+            Destination::File(path) => {
+                let mut file = tokio::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .await
+                    .map_err(failed)?;
+                file.write_all(formatted.as_bytes()).await.map_err(failed)?;
+            }
+        }
+
+        Ok(())
+    }
+}
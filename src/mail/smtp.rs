@@ -0,0 +1,99 @@
+use super::{Mailer, OutgoingMail};
+use crate::StudyBuddyError;
+use async_trait::async_trait;
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+
+#[derive(Clone, Copy, Debug, Default)]
+pub enum TlsMode {
+    /// TLS from the start of the connection, usually on port 465
+    #[default]
+    Tls,
+    /// Plain connection upgraded with STARTTLS, usually on port 587
+    StartTls,
+    /// No encryption at all, only meant for local mail servers
+    None,
+}
+
+impl TryFrom<&str> for TlsMode {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "tls" => Ok(TlsMode::Tls),
+            "starttls" => Ok(TlsMode::StartTls),
+            "none" => Ok(TlsMode::None),
+            _ => Err(format!("SMTP TLS mode {} not supported", value)),
+        }
+    }
+}
+
+/// Delivers emails through an SMTP relay.
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    sender: Mailbox,
+}
+
+impl SmtpMailer {
+    /// Connects as `sender`, authenticating with `password` when one is given.
+    /// Without a `port` the default port of the TLS mode is used.
+    pub fn new(
+        host: &str,
+        port: Option<u16>,
+        tls: TlsMode,
+        sender: &str,
+        password: Option<String>,
+    ) -> Result<Self, StudyBuddyError> {
+        let mut builder = match tls {
+            TlsMode::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host),
+            TlsMode::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host),
+            TlsMode::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+                host,
+            )),
+        }
+        .map_err(|err| StudyBuddyError::MailDeliveryFailed(err.to_string()))?;
+
+        if let Some(port) = port {
+            builder = builder.port(port);
+        }
+
+        if let Some(password) = password {
+            builder = builder.credentials(Credentials::new(sender.to_string(), password));
+        }
+
+        Ok(SmtpMailer {
+            transport: builder.build(),
+            sender: sender
+                .parse()
+                .map_err(|_| StudyBuddyError::InvalidEmailAddress)?,
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, mail: OutgoingMail) -> Result<(), StudyBuddyError> {
+        let recipient: Mailbox = mail
+            .to
+            .parse()
+            .map_err(|_| StudyBuddyError::InvalidEmailAddress)?;
+
+        let message = Message::builder()
+            .from(self.sender.clone())
+            .to(recipient)
+            .subject(mail.subject)
+            .header(ContentType::TEXT_HTML)
+            .body(mail.html_body)
+            .map_err(|err| StudyBuddyError::MailDeliveryFailed(err.to_string()))?;
+
+        self.transport
+            .send(message)
+            .await
+            .map_err(|err| StudyBuddyError::MailDeliveryFailed(err.to_string()))?;
+
+        Ok(())
+    }
+}
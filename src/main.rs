@@ -1,27 +1,13 @@
-use axum::{
-    error_handling::HandleErrorLayer,
-    http::StatusCode,
-    middleware,
-    routing::{delete, get, post, put},
-    BoxError, Router, Server,
-};
-use std::{sync::Arc, time::Duration};
-use study_buddy::users;
-use tokio::sync::Mutex;
+use axum::{error_handling::HandleErrorLayer, http::StatusCode, BoxError, Server};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use study_buddy::database;
 use tower::{
     buffer::BufferLayer, limit::rate::RateLimitLayer, timeout::TimeoutLayer, ServiceBuilder,
 };
-use tower_cookies::CookieManagerLayer;
-use tower_http::{
-    services::{ServeDir, ServeFile},
-    trace::TraceLayer,
-};
-use tracing::{info, log::warn};
+use tower_http::{services::ServeFile, trace::TraceLayer};
+use tracing::{error, info, log::warn};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-//TODO add text search to document_titles
-//TODO syntax highlighting for code blocks
-
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
     dotenv::dotenv().ok();
@@ -37,34 +23,67 @@ async fn main() -> std::io::Result<()> {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    let app_state = Arc::new(Mutex::new(study_buddy::server::AppState::new().await));
-
-    let auth_needed_routes = Router::new()
-        .route("/log_out", post(users::log_out))
-        .route("/create_document", post(users::create_document))
-        .route("/save", put(users::save_document))
-        .route("/fetch_documents", get(users::fetch_posts))
-        .route("/fetch_content", get(users::fetch_post_content))
-        .route("/delete_document", delete(users::delete_document))
-        .layer(middleware::from_fn_with_state(
-            app_state.clone(),
-            users::mw_user_ctx_resolver,
-        ));
-
-    let router = Router::new()
-        .route_service("/", ServeFile::new("static/html/index.html"))
-        .route("/refresh", get(study_buddy::server::refresh_file))
-        .route(
-            "/download",
-            post(study_buddy::server::download_current_markdown),
-        )
-        .route("/create_user", post(users::create_user))
-        .route("/log_in", post(users::log_in))
-        .route_service("/recovery", ServeFile::new("static/html/recovery.html"))
-        .route("/send_recovery", post(users::send_password_recovery_email))
-        .route("/try_recovery_code", post(users::try_recovery_code))
-        .merge(auth_needed_routes)
-        .nest_service("/static", ServeDir::new("static"))
+    let config = match study_buddy::config::Config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            error!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    //`study_buddy migrate` only brings the database schema up to date
+    match std::env::args().nth(1).as_deref() {
+        Some("migrate") => {
+            let pool = database::connect(&config)
+                .await
+                .expect("Failed to connect to the database");
+
+            if let Err(err) = database::run_migrations(&pool).await {
+                error!("Failed to run migrations: {}", err);
+                std::process::exit(1);
+            }
+
+            return Ok(());
+        }
+        Some(command) => {
+            error!("Unknown command {}, the only command is migrate", command);
+            std::process::exit(2);
+        }
+        None => {}
+    }
+
+    let address = config.address;
+    let rate_limit_per_second = config.rate_limit_per_second;
+    let request_timeout = config.request_timeout;
+
+    let run_migrations = config.run_migrations;
+    let app_state = study_buddy::server::AppState::new(config).await;
+
+    if run_migrations {
+        if let Err(err) = database::run_migrations(&app_state.pool).await {
+            error!("Failed to run migrations: {}", err);
+            std::process::exit(1);
+        }
+    }
+
+    tokio::spawn(study_buddy::revisions::prune_revisions_periodically(
+        app_state.pool.clone(),
+        study_buddy::revisions::RetentionPolicy::default(),
+    ));
+
+    tokio::spawn(study_buddy::sessions::prune_sessions_periodically(
+        app_state.pool.clone(),
+        app_state.session_policy.clone(),
+    ));
+
+    tokio::spawn(study_buddy::trash::purge_trash_periodically(
+        app_state.pool.clone(),
+        app_state.config.trash_retention,
+    ));
+
+    let app_state = Arc::new(app_state);
+
+    let router = study_buddy::server::router(app_state)
         .layer(TraceLayer::new_for_http())
         .layer(
             ServiceBuilder::new()
@@ -75,11 +94,12 @@ async fn main() -> std::io::Result<()> {
                     )
                 }))
                 .layer(BufferLayer::new(1024))
-                .layer(RateLimitLayer::new(10, Duration::from_secs(1)))
-                .layer(TimeoutLayer::new(Duration::from_secs(60)))
-                .layer(CookieManagerLayer::new()),
+                .layer(RateLimitLayer::new(
+                    rate_limit_per_second,
+                    Duration::from_secs(1),
+                ))
+                .layer(TimeoutLayer::new(request_timeout)),
         )
-        .with_state(app_state)
         .fallback_service(ServeFile::new("static/html/not_found.html"));
 
     let quit_sig = async {
@@ -87,14 +107,10 @@ async fn main() -> std::io::Result<()> {
         warn!("Initiating graceful shutdown");
     };
 
-    let address = &"0.0.0.0:8080"
-        .parse()
-        .expect("Address is guaranteed to be valid");
-
     info!("Listening on: {:?}", address);
 
-    let server = Server::bind(address)
-        .serve(router.into_make_service())
+    let server = Server::bind(&address)
+        .serve(router.into_make_service_with_connect_info::<SocketAddr>())
         .with_graceful_shutdown(quit_sig);
 
     server.await.unwrap();
@@ -0,0 +1,296 @@
+use crate::authorization::{authorize_document, authorize_notebook};
+use crate::server::AppState;
+use crate::users::UserCtx;
+use crate::StudyBuddyError;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use std::sync::Arc;
+use tracing::info;
+
+#[derive(Serialize, FromRow)]
+pub struct Notebook {
+    notebook_id: uuid::Uuid,
+    parent_id: Option<uuid::Uuid>,
+    name: String,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: time::OffsetDateTime,
+    document_count: i64,
+}
+
+fn validate_name(name: &str) -> Result<String, StudyBuddyError> {
+    let name = name.trim();
+
+    if name.is_empty() {
+        return Err(StudyBuddyError::IncompleteRequest);
+    }
+
+    Ok(name.to_string())
+}
+
+async fn authorize_parent(
+    pool: &PgPool,
+    ctx: &UserCtx,
+    parent_id: Option<uuid::Uuid>,
+) -> Result<(), StudyBuddyError> {
+    match parent_id {
+        Some(parent_id) => authorize_notebook(pool, ctx, parent_id).await,
+        None => Ok(()),
+    }
+}
+
+/// Every notebook of the user as a flat list, `parent_id` links them into a
+/// tree and is `None` for top level notebooks.
+pub async fn fetch_notebooks(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+) -> Result<Json<Vec<Notebook>>, StudyBuddyError> {
+    let notebooks = sqlx::query_as::<_, Notebook>(
+        "SELECT notebooks.notebook_id, parent_id, name, created_at,
+            count(documents.document_id) AS document_count
+        FROM notebooks
+        LEFT JOIN documents ON documents.notebook_id = notebooks.notebook_id
+            AND documents.deleted_at IS NULL
+        WHERE notebooks.user_id = $1
+        GROUP BY notebooks.notebook_id
+        ORDER BY name",
+    )
+    .bind(ctx.user_id())
+    .fetch_all(&app_state.pool)
+    .await?;
+
+    Ok(Json(notebooks))
+}
+
+#[derive(Deserialize)]
+pub struct CreateNotebookRequest {
+    name: String,
+    parent_id: Option<uuid::Uuid>,
+}
+
+pub async fn create_notebook(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+    Json(request): Json<CreateNotebookRequest>,
+) -> Result<Json<Notebook>, StudyBuddyError> {
+    let pool = &app_state.pool;
+    let name = validate_name(&request.name)?;
+
+    authorize_parent(pool, &ctx, request.parent_id).await?;
+
+    let notebook = sqlx::query_as::<_, Notebook>(
+        "INSERT INTO notebooks (notebook_id, user_id, parent_id, name)
+        VALUES ($1, $2, $3, $4)
+        RETURNING notebook_id, parent_id, name, created_at, 0::BIGINT AS document_count",
+    )
+    .bind(uuid::Uuid::new_v4())
+    .bind(ctx.user_id())
+    .bind(request.parent_id)
+    .bind(name)
+    .fetch_one(pool)
+    .await?;
+
+    info!(
+        "Created notebook {} for user {}",
+        notebook.notebook_id,
+        ctx.user_id()
+    );
+
+    Ok(Json(notebook))
+}
+
+#[derive(Deserialize)]
+pub struct RenameNotebookRequest {
+    notebook_id: uuid::Uuid,
+    name: String,
+}
+
+pub async fn rename_notebook(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+    Json(request): Json<RenameNotebookRequest>,
+) -> Result<Response, StudyBuddyError> {
+    let pool = &app_state.pool;
+    let name = validate_name(&request.name)?;
+
+    authorize_notebook(pool, &ctx, request.notebook_id).await?;
+
+    sqlx::query!(
+        "UPDATE notebooks
+        SET name = $1
+        WHERE notebook_id = $2 AND user_id = $3",
+        name,
+        request.notebook_id,
+        ctx.user_id()
+    )
+    .execute(pool)
+    .await?;
+
+    Ok((StatusCode::OK, "Renamed notebook").into_response())
+}
+
+#[derive(Deserialize)]
+pub struct MoveNotebookRequest {
+    notebook_id: uuid::Uuid,
+    parent_id: Option<uuid::Uuid>,
+}
+
+/// Moves a notebook under another one, or to the top level without a
+/// `parent_id`. A notebook can't be moved into itself or its own subtree.
+pub async fn move_notebook(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+    Json(request): Json<MoveNotebookRequest>,
+) -> Result<Response, StudyBuddyError> {
+    let pool = &app_state.pool;
+
+    authorize_notebook(pool, &ctx, request.notebook_id).await?;
+    authorize_parent(pool, &ctx, request.parent_id).await?;
+
+    let mut transaction = pool.begin().await?;
+
+    //Moves are serialized per user so two concurrent moves can't build a cycle
+    //neither of them would have allowed on its own
+    sqlx::query!(
+        "SELECT id FROM users WHERE id = $1 FOR UPDATE",
+        ctx.user_id()
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+
+    let creates_cycle = sqlx::query_scalar!(
+        r#"WITH RECURSIVE ancestors AS (
+            SELECT notebook_id, parent_id
+            FROM notebooks
+            WHERE notebook_id = $1
+            UNION ALL
+            SELECT notebooks.notebook_id, notebooks.parent_id
+            FROM notebooks
+            JOIN ancestors ON notebooks.notebook_id = ancestors.parent_id
+        )
+        SELECT EXISTS (SELECT 1 FROM ancestors WHERE notebook_id = $2) AS "creates_cycle!""#,
+        request.parent_id,
+        request.notebook_id
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+
+    if creates_cycle {
+        return Err(StudyBuddyError::NotebookCycle);
+    }
+
+    sqlx::query!(
+        "UPDATE notebooks
+        SET parent_id = $1
+        WHERE notebook_id = $2 AND user_id = $3",
+        request.parent_id,
+        request.notebook_id,
+        ctx.user_id()
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok((StatusCode::OK, "Moved notebook").into_response())
+}
+
+#[derive(Deserialize)]
+pub struct NotebookId {
+    notebook_id: uuid::Uuid,
+}
+
+/// Deletes a notebook, the documents and notebooks inside it move up to its
+/// parent instead of being deleted with it.
+pub async fn delete_notebook(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+    Query(request): Query<NotebookId>,
+) -> Result<Response, StudyBuddyError> {
+    let pool = &app_state.pool;
+
+    authorize_notebook(pool, &ctx, request.notebook_id).await?;
+
+    let mut transaction = pool.begin().await?;
+
+    //A concurrent request may have deleted it since it was authorized
+    let parent_id = sqlx::query_scalar!(
+        "SELECT parent_id FROM notebooks WHERE notebook_id = $1 FOR UPDATE",
+        request.notebook_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    .ok_or(StudyBuddyError::NotebookNotFound)?;
+
+    sqlx::query!(
+        "UPDATE documents
+        SET notebook_id = $1
+        WHERE notebook_id = $2",
+        parent_id,
+        request.notebook_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE notebooks
+        SET parent_id = $1
+        WHERE parent_id = $2",
+        parent_id,
+        request.notebook_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    sqlx::query!(
+        "DELETE FROM notebooks
+        WHERE notebook_id = $1 AND user_id = $2",
+        request.notebook_id,
+        ctx.user_id()
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+
+    info!("Deleted notebook {}", request.notebook_id);
+
+    Ok((StatusCode::OK, "Deleted notebook").into_response())
+}
+
+#[derive(Deserialize)]
+pub struct MoveDocumentRequest {
+    document_id: uuid::Uuid,
+    notebook_id: Option<uuid::Uuid>,
+}
+
+/// Puts a document in a notebook, or outside of every notebook without a
+/// `notebook_id`.
+pub async fn move_document(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+    Json(request): Json<MoveDocumentRequest>,
+) -> Result<Response, StudyBuddyError> {
+    let pool = &app_state.pool;
+
+    authorize_document(pool, &ctx, request.document_id).await?;
+    authorize_parent(pool, &ctx, request.notebook_id).await?;
+
+    sqlx::query!(
+        "UPDATE documents
+        SET notebook_id = $1
+        WHERE document_id = $2 AND user_id = $3",
+        request.notebook_id,
+        request.document_id,
+        ctx.user_id()
+    )
+    .execute(pool)
+    .await?;
+
+    Ok((StatusCode::OK, "Moved document").into_response())
+}
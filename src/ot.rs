@@ -0,0 +1,279 @@
+use serde::{Deserialize, Serialize};
+use std::str::Chars;
+
+/// One step of an operation. Lengths count UTF-16 code units, the way browsers
+/// index strings.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Component {
+    Retain(usize),
+    Insert(String),
+    Delete(usize),
+}
+
+//On the wire a component is a positive count to retain, a negative count to
+//delete or a string to insert, the format ot.js uses
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum WireComponent {
+    Count(i64),
+    Text(String),
+}
+
+/// An edit of a whole text, walking it from start to end. Operations made on
+/// the same text concurrently are merged with `transform`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<WireComponent>", into = "Vec<WireComponent>")]
+pub struct Operation {
+    components: Vec<Component>,
+    base_len: usize,
+    target_len: usize,
+}
+
+fn utf16_len(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+impl TryFrom<Vec<WireComponent>> for Operation {
+    type Error = &'static str;
+
+    fn try_from(components: Vec<WireComponent>) -> Result<Self, Self::Error> {
+        let mut operation = Operation::default();
+
+        for component in components {
+            match component {
+                WireComponent::Count(0) => return Err("Operations can't have empty components"),
+                WireComponent::Count(count) if count > 0 => operation.retain(count as usize),
+                WireComponent::Count(count) => operation.delete(count.unsigned_abs() as usize),
+                WireComponent::Text(text) if text.is_empty() => {
+                    return Err("Operations can't have empty components")
+                }
+                WireComponent::Text(text) => operation.insert(&text),
+            };
+        }
+
+        Ok(operation)
+    }
+}
+
+impl From<Operation> for Vec<WireComponent> {
+    fn from(operation: Operation) -> Self {
+        operation
+            .components
+            .into_iter()
+            .map(|component| match component {
+                Component::Retain(count) => WireComponent::Count(count as i64),
+                Component::Insert(text) => WireComponent::Text(text),
+                Component::Delete(count) => WireComponent::Count(-(count as i64)),
+            })
+            .collect()
+    }
+}
+
+//Moves `units` code units worth of characters from `chars` into `out`,
+//`None` when that runs out of text or splits a surrogate pair
+fn take(chars: &mut Chars, units: usize, mut out: Option<&mut String>) -> Option<()> {
+    let mut remaining = units;
+
+    while remaining > 0 {
+        let c = chars.next()?;
+        remaining = remaining.checked_sub(c.len_utf16())?;
+
+        if let Some(out) = out.as_deref_mut() {
+            out.push(c);
+        }
+    }
+
+    Some(())
+}
+
+impl Operation {
+    pub fn new() -> Self {
+        Operation::default()
+    }
+
+    /// Length of the text the operation applies to.
+    pub fn base_len(&self) -> usize {
+        self.base_len
+    }
+
+    /// Length of the text after applying the operation.
+    pub fn target_len(&self) -> usize {
+        self.target_len
+    }
+
+    pub fn components(&self) -> &[Component] {
+        &self.components
+    }
+
+    pub fn retain(&mut self, count: usize) -> &mut Self {
+        if count == 0 {
+            return self;
+        }
+
+        self.base_len += count;
+        self.target_len += count;
+
+        match self.components.last_mut() {
+            Some(Component::Retain(last)) => *last += count,
+            _ => self.components.push(Component::Retain(count)),
+        }
+
+        self
+    }
+
+    pub fn insert(&mut self, text: &str) -> &mut Self {
+        if text.is_empty() {
+            return self;
+        }
+
+        self.target_len += utf16_len(text);
+
+        //Inserts go before deletes at the same position, so operations that
+        //do the same thing compare equal
+        let length = self.components.len();
+        match self.components.as_mut_slice() {
+            [.., Component::Insert(last)] => last.push_str(text),
+            [.., Component::Insert(last), Component::Delete(_)] => last.push_str(text),
+            [.., Component::Delete(_)] => self
+                .components
+                .insert(length - 1, Component::Insert(text.to_string())),
+            _ => self.components.push(Component::Insert(text.to_string())),
+        }
+
+        self
+    }
+
+    pub fn delete(&mut self, count: usize) -> &mut Self {
+        if count == 0 {
+            return self;
+        }
+
+        self.base_len += count;
+
+        match self.components.last_mut() {
+            Some(Component::Delete(last)) => *last += count,
+            _ => self.components.push(Component::Delete(count)),
+        }
+
+        self
+    }
+
+    /// `text` with the operation applied, `None` when the operation was made
+    /// for a text of another length or cuts a character in half.
+    pub fn apply(&self, text: &str) -> Option<String> {
+        if utf16_len(text) != self.base_len {
+            return None;
+        }
+
+        let mut result = String::with_capacity(text.len());
+        let mut chars = text.chars();
+
+        for component in &self.components {
+            match component {
+                Component::Retain(count) => take(&mut chars, *count, Some(&mut result))?,
+                Component::Insert(text) => result.push_str(text),
+                Component::Delete(count) => take(&mut chars, *count, None)?,
+            }
+        }
+
+        Some(result)
+    }
+
+    /// Where a position in the text before the operation ends up after it.
+    /// Text inserted right at the position goes before it.
+    pub fn transform_index(&self, index: usize) -> usize {
+        let mut remaining = index as i64;
+        let mut moved = index;
+
+        for component in &self.components {
+            match component {
+                Component::Retain(count) => remaining -= *count as i64,
+                Component::Insert(text) => moved += utf16_len(text),
+                Component::Delete(count) => {
+                    moved -= remaining.clamp(0, *count as i64) as usize;
+                    remaining -= *count as i64;
+                }
+            }
+
+            if remaining < 0 {
+                break;
+            }
+        }
+
+        moved
+    }
+}
+
+/// Turns two operations made on the same text into `(a', b')` so that applying
+/// `a` then `b'` gives the same text as `b` then `a'`. When both insert at the
+/// same position the text of `a` goes first. `None` when they weren't made on
+/// texts of the same length.
+pub fn transform(a: &Operation, b: &Operation) -> Option<(Operation, Operation)> {
+    if a.base_len != b.base_len {
+        return None;
+    }
+
+    let mut a_prime = Operation::new();
+    let mut b_prime = Operation::new();
+    let mut components_a = a.components.iter().cloned();
+    let mut components_b = b.components.iter().cloned();
+    let mut next_a = components_a.next();
+    let mut next_b = components_b.next();
+
+    loop {
+        match (next_a.take(), next_b.take()) {
+            (None, None) => break,
+            (Some(Component::Insert(text)), other) => {
+                a_prime.insert(&text);
+                b_prime.retain(utf16_len(&text));
+                next_a = components_a.next();
+                next_b = other;
+            }
+            (other, Some(Component::Insert(text))) => {
+                a_prime.retain(utf16_len(&text));
+                b_prime.insert(&text);
+                next_a = other;
+                next_b = components_b.next();
+            }
+            (None, _) | (_, None) => return None,
+            (Some(step_a), Some(step_b)) => {
+                let (Component::Retain(count_a) | Component::Delete(count_a)) = step_a else {
+                    unreachable!("Inserts are handled above")
+                };
+                let (Component::Retain(count_b) | Component::Delete(count_b)) = step_b else {
+                    unreachable!("Inserts are handled above")
+                };
+                let count = count_a.min(count_b);
+
+                match (&step_a, &step_b) {
+                    (Component::Retain(_), Component::Retain(_)) => {
+                        a_prime.retain(count);
+                        b_prime.retain(count);
+                    }
+                    (Component::Delete(_), Component::Retain(_)) => {
+                        a_prime.delete(count);
+                    }
+                    (Component::Retain(_), Component::Delete(_)) => {
+                        b_prime.delete(count);
+                    }
+                    //Both deleted the same text
+                    _ => {}
+                }
+
+                next_a = shorten(step_a, count).or_else(|| components_a.next());
+                next_b = shorten(step_b, count).or_else(|| components_b.next());
+            }
+        }
+    }
+
+    Some((a_prime, b_prime))
+}
+
+//What is left of a retain or delete after `count` code units of it
+fn shorten(component: Component, count: usize) -> Option<Component> {
+    match component {
+        Component::Retain(total) if total > count => Some(Component::Retain(total - count)),
+        Component::Delete(total) if total > count => Some(Component::Delete(total - count)),
+        _ => None,
+    }
+}
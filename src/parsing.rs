@@ -1,5 +1,181 @@
 use markdown::{to_html_with_options, Options};
+use std::sync::OnceLock;
+use syntect::{
+    html::{ClassStyle, ClassedHTMLGenerator},
+    parsing::SyntaxSet,
+    util::LinesWithEndings,
+};
+
+const CODE_BLOCK_START: &str = "<pre><code class=\"language-";
+const CODE_BLOCK_END: &str = "</code></pre>";
+
+/// Prefix of the classes put on highlighted tokens, the themes in
+/// `templates/*.css` and `static_sources/css/styles.css` target these.
+const HIGHLIGHT_CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAX_SET: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAX_SET.get_or_init(SyntaxSet::load_defaults_newlines)
+}
 
 pub fn parse_markdown(md_file: &str) -> String {
-    to_html_with_options(md_file, &Options::gfm()).expect("GFM is a safe variant")
+    let html = to_html_with_options(md_file, &Options::gfm()).expect("GFM is a safe variant");
+    highlight_code_blocks(&html)
+}
+
+/// Replaces the contents of every fenced code block with a known language by
+/// class based highlighting spans, blocks in unknown languages are left as is.
+fn highlight_code_blocks(html: &str) -> String {
+    let mut highlighted = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(block_start) = rest.find(CODE_BLOCK_START) {
+        let (before, block) = rest.split_at(block_start);
+        highlighted.push_str(before);
+
+        let Some(block_end) = block.find(CODE_BLOCK_END) else {
+            rest = block;
+            break;
+        };
+
+        let block_with_end = &block[..block_end + CODE_BLOCK_END.len()];
+        highlighted.push_str(
+            &highlight_code_block(block_with_end).unwrap_or_else(|| block_with_end.to_string()),
+        );
+        rest = &block[block_end + CODE_BLOCK_END.len()..];
+    }
+
+    highlighted.push_str(rest);
+    highlighted
+}
+
+fn highlight_code_block(block: &str) -> Option<String> {
+    let attributes_and_code = &block[CODE_BLOCK_START.len()..block.len() - CODE_BLOCK_END.len()];
+    let (language, code) = attributes_and_code.split_once("\">")?;
+
+    let syntax_set = syntax_set();
+    let syntax = syntax_set.find_syntax_by_token(&unescape_html(language))?;
+
+    let mut generator =
+        ClassedHTMLGenerator::new_with_class_style(syntax, syntax_set, HIGHLIGHT_CLASS_STYLE);
+
+    for line in LinesWithEndings::from(&unescape_html(code)) {
+        generator
+            .parse_html_for_line_which_includes_newline(line)
+            .ok()?;
+    }
+
+    Some(format!(
+        "{CODE_BLOCK_START}{language} hl-code\">{}{CODE_BLOCK_END}",
+        generator.finalize()
+    ))
+}
+
+//Inverse of the escaping markdown-rs applies to code block contents
+fn unescape_html(escaped: &str) -> String {
+    escaped
+        .replace("&quot;", "\"")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// `key: value` pairs of the YAML front-matter at the top of a document. Only
+/// the flat subset notes use is understood, plain or quoted scalars and lists
+/// written either inline (`[a, b]`) or one `- item` per line.
+#[derive(Debug, Default)]
+pub struct FrontMatter {
+    values: Vec<(String, Vec<String>)>,
+}
+
+impl FrontMatter {
+    /// Every value under `key`, a scalar is a list of one.
+    pub fn list(&self, key: &str) -> &[String] {
+        self.values
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, values)| values.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.list(key).first().map(String::as_str)
+    }
+}
+
+/// Splits a document into its front-matter, if it starts with a `---` block,
+/// and the markdown that follows it.
+pub fn split_front_matter(markdown: &str) -> (Option<FrontMatter>, &str) {
+    match front_matter_block(markdown) {
+        Some((block, body)) => (Some(parse_front_matter(block)), body),
+        None => (None, markdown),
+    }
+}
+
+/// The raw lines between the `---` markers and the markdown after them.
+pub fn front_matter_block(markdown: &str) -> Option<(&str, &str)> {
+    let rest = markdown
+        .strip_prefix("---\n")
+        .or_else(|| markdown.strip_prefix("---\r\n"))?;
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "---" {
+            return Some((&rest[..offset], &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+
+    None
+}
+
+fn parse_front_matter(block: &str) -> FrontMatter {
+    let mut front_matter = FrontMatter::default();
+
+    for line in block.lines() {
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        if let Some(item) = line.trim_start().strip_prefix("- ") {
+            //Block list items belong to the last key that had no inline value
+            if let Some((_, values)) = front_matter.values.last_mut() {
+                values.push(unquote(item));
+            }
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+
+        let value = value.trim();
+        let values = match value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+            Some(items) => items
+                .split(',')
+                .map(unquote)
+                .filter(|item| !item.is_empty())
+                .collect(),
+            None if value.is_empty() => Vec::new(),
+            None => vec![unquote(value)],
+        };
+
+        front_matter.values.push((key.trim().to_string(), values));
+    }
+
+    front_matter
+}
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+
+    if let Some(quoted) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        return quoted.replace("\\\"", "\"").replace("\\\\", "\\");
+    }
+
+    value
+        .strip_prefix('\'')
+        .and_then(|v| v.strip_suffix('\''))
+        .unwrap_or(value)
+        .to_string()
 }
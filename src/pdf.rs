@@ -0,0 +1,57 @@
+mod endpoint;
+mod local;
+
+use crate::config::PdfBackend;
+use crate::StudyBuddyError;
+use async_trait::async_trait;
+use std::sync::Arc;
+use tracing::info;
+
+pub use endpoint::PdfEndpointRenderer;
+pub use local::LocalPdfRenderer;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub enum PdfStyle {
+    Light,
+    #[default]
+    Dark,
+}
+
+impl TryFrom<&str> for PdfStyle {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "dark" => Ok(PdfStyle::Dark),
+            "light" => Ok(PdfStyle::Light),
+            _ => Err(format!("Style {} not supported", value)),
+        }
+    }
+}
+
+pub enum RenderedPdf {
+    /// The PDF is hosted somewhere else and can be downloaded from this url
+    Url(String),
+    /// The contents of the PDF file
+    Bytes(Vec<u8>),
+}
+
+/// Turns the html produced by `parse_markdown` into a PDF.
+#[async_trait]
+pub trait PdfRenderer: Send + Sync {
+    async fn render(&self, html: &str, style: PdfStyle) -> Result<RenderedPdf, StudyBuddyError>;
+}
+
+/// Builds the renderer picked in the config.
+pub fn renderer_from_config(backend: &PdfBackend) -> Arc<dyn PdfRenderer> {
+    match backend {
+        PdfBackend::PdfEndpoint { api_key } => {
+            info!("Rendering PDFs through pdfendpoint.com");
+            Arc::new(PdfEndpointRenderer::new(api_key.clone()))
+        }
+        PdfBackend::Local => {
+            info!("Rendering PDFs locally");
+            Arc::new(LocalPdfRenderer)
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use super::{PdfRenderer, PdfStyle, RenderedPdf};
+use crate::StudyBuddyError;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+const API_URL: &str = "https://api.pdfendpoint.com/v1/convert";
+
+/// Renders PDFs with the pdfendpoint.com conversion api, which hosts the
+/// resulting file and hands back its url.
+pub struct PdfEndpointRenderer {
+    client: reqwest::Client,
+    api_key: String,
+}
+
+impl PdfEndpointRenderer {
+    pub fn new(api_key: String) -> Self {
+        PdfEndpointRenderer {
+            client: reqwest::Client::new(),
+            api_key,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Default)]
+struct ApiRequest {
+    html: String,
+    css: String,
+    js: String,
+}
+
+#[derive(Deserialize)]
+struct DataFields {
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct ApiResponse {
+    data: DataFields,
+}
+
+#[async_trait]
+impl PdfRenderer for PdfEndpointRenderer {
+    async fn render(&self, html: &str, style: PdfStyle) -> Result<RenderedPdf, StudyBuddyError> {
+        let css = match style {
+            PdfStyle::Dark => include_str!("../../templates/pdf.css"),
+            PdfStyle::Light => include_str!("../../templates/lightpdf.css"),
+        }
+        .to_string();
+
+        let html = format!("<!DOCTYPE html><html lang=\"en\"><head><meta charset=\"utf-8\"><link href=\"https://pvinis.github.io/iosevka-webfont/3.4.1/iosevka.css\" rel=\"stylesheet\"/><link rel=\"stylesheet\" href=\"https://cdn.jsdelivr.net/npm/katex@0.16.7/dist/katex.min.css\" integrity=\"sha384-3UiQGuEI4TTMaFmGIZumfRPtfKQ3trwQE2JgosJxCnGmQpL/lJdjpcHkaaFwHlcI\" crossorigin=\"anonymous\"/><title>StudyBuddyDownload</title></head><body><div>{}</div></body></html>", html);
+
+        let js = include_str!("../../templates/pdf.js").to_string();
+
+        let api_request = ApiRequest { html, css, js };
+        let mut headers = reqwest::header::HeaderMap::new();
+        let auth_string = format!("Bearer {}", self.api_key);
+
+        let auth =
+            reqwest::header::HeaderValue::from_str(&auth_string).expect("Auth key is valid ASCII");
+        let content_type = reqwest::header::HeaderValue::from_static("application/json");
+        headers.insert(reqwest::header::CONTENT_TYPE, content_type);
+        headers.insert(reqwest::header::AUTHORIZATION, auth);
+
+        //Make POST request
+        let api_response = self
+            .client
+            .post(API_URL)
+            .headers(headers)
+            .json(&api_request)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<ApiResponse>()
+            .await?;
+
+        Ok(RenderedPdf::Url(api_response.data.url))
+    }
+}
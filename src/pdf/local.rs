@@ -0,0 +1,866 @@
+use super::{PdfRenderer, PdfStyle, RenderedPdf};
+use crate::StudyBuddyError;
+use async_trait::async_trait;
+use printpdf::{
+    BuiltinFont, Color, IndirectFontRef, Line, Mm, PdfDocument, PdfDocumentReference,
+    PdfLayerReference, Point, Rect, Rgb,
+};
+use tl::{Node, NodeHandle, Parser, ParserOptions};
+
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 20.0;
+const CONTENT_WIDTH: f32 = PAGE_WIDTH - 2.0 * MARGIN;
+const LIST_INDENT: f32 = 6.0;
+const QUOTE_INDENT: f32 = 5.0;
+const BODY_SIZE: f32 = 11.0;
+const CODE_SIZE: f32 = 9.0;
+const LINE_SPACING: f32 = 1.4;
+const MM_PER_PT: f32 = 0.352_778;
+
+/// Lays the document out with the PDF builtin fonts, without any network
+/// access or external tools. Math is written out as its TeX source since there
+/// is no way to typeset it here.
+pub struct LocalPdfRenderer;
+
+#[async_trait]
+impl PdfRenderer for LocalPdfRenderer {
+    async fn render(&self, html: &str, style: PdfStyle) -> Result<RenderedPdf, StudyBuddyError> {
+        let html = html.to_string();
+
+        let pdf = tokio::task::spawn_blocking(move || render_pdf(&html, style))
+            .await
+            .expect("Task cant panic")?;
+
+        Ok(RenderedPdf::Bytes(pdf))
+    }
+}
+
+fn render_pdf(html: &str, style: PdfStyle) -> Result<Vec<u8>, StudyBuddyError> {
+    let dom = tl::parse(html, ParserOptions::default())
+        .map_err(|err| StudyBuddyError::PdfRenderingFailed(err.to_string()))?;
+
+    let mut collector = BlockCollector::new(dom.parser());
+    for child in dom.children() {
+        collector.walk(*child, InlineStyle::default());
+    }
+    collector.finish_block(BlockKind::Paragraph);
+
+    let mut writer = PageWriter::new(Theme::from(style));
+    for block in &collector.blocks {
+        writer.write_block(block);
+    }
+
+    writer
+        .document
+        .save_to_bytes()
+        .map_err(|err| StudyBuddyError::PdfRenderingFailed(err.to_string()))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Font {
+    Regular,
+    Bold,
+    Italic,
+    BoldItalic,
+    Mono,
+}
+
+impl Font {
+    const ALL: [Font; 5] = [
+        Font::Regular,
+        Font::Bold,
+        Font::Italic,
+        Font::BoldItalic,
+        Font::Mono,
+    ];
+
+    fn builtin(self) -> BuiltinFont {
+        match self {
+            Font::Regular => BuiltinFont::Helvetica,
+            Font::Bold => BuiltinFont::HelveticaBold,
+            Font::Italic => BuiltinFont::HelveticaOblique,
+            Font::BoldItalic => BuiltinFont::HelveticaBoldOblique,
+            Font::Mono => BuiltinFont::Courier,
+        }
+    }
+
+    fn bold(self) -> Self {
+        match self {
+            Font::Regular => Font::Bold,
+            Font::Italic => Font::BoldItalic,
+            other => other,
+        }
+    }
+
+    fn italic(self) -> Self {
+        match self {
+            Font::Regular => Font::Italic,
+            Font::Bold => Font::BoldItalic,
+            other => other,
+        }
+    }
+
+    /// Width of `text` in mm when set at `size` points.
+    fn text_width(self, text: &str, size: f32) -> f32 {
+        let units: u32 = text.chars().map(|c| self.char_width(c)).sum();
+        units as f32 / 1000.0 * size * MM_PER_PT
+    }
+
+    //Advance widths of the printable ASCII characters from the Adobe font
+    //metrics, in thousandths of an em. The oblique variants share them.
+    fn char_width(self, character: char) -> u32 {
+        const HELVETICA: [u16; 95] = [
+            278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556,
+            556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667,
+            667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722,
+            667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500,
+            556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556, 556, 556, 333, 500, 278,
+            556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+        ];
+        const HELVETICA_BOLD: [u16; 95] = [
+            278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278, 556,
+            556, 556, 556, 556, 556, 556, 556, 556, 556, 333, 333, 584, 584, 584, 611, 975, 722,
+            722, 722, 722, 667, 611, 778, 722, 278, 556, 722, 611, 833, 722, 778, 667, 778, 722,
+            667, 611, 722, 667, 944, 667, 667, 611, 333, 278, 333, 584, 556, 333, 556, 611, 556,
+            611, 556, 333, 611, 611, 278, 278, 556, 278, 889, 611, 611, 611, 611, 389, 556, 333,
+            611, 556, 778, 556, 556, 500, 389, 280, 389, 584,
+        ];
+
+        let table = match self {
+            Font::Mono => return 600,
+            Font::Regular | Font::Italic => &HELVETICA,
+            Font::Bold | Font::BoldItalic => &HELVETICA_BOLD,
+        };
+
+        match (character as usize).checked_sub(32) {
+            Some(index) if index < table.len() => table[index] as u32,
+            _ => 556,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Rgb8(u8, u8, u8);
+
+impl From<Rgb8> for Color {
+    fn from(Rgb8(r, g, b): Rgb8) -> Self {
+        Color::Rgb(Rgb::new(
+            r as f32 / 255.0,
+            g as f32 / 255.0,
+            b as f32 / 255.0,
+            None,
+        ))
+    }
+}
+
+/// The colors of `templates/pdf.css` and `templates/lightpdf.css`.
+struct Theme {
+    background: Option<Rgb8>,
+    text: Rgb8,
+    rule: Rgb8,
+    comment: Rgb8,
+    keyword: Rgb8,
+    string: Rgb8,
+    constant: Rgb8,
+    name: Rgb8,
+    tag: Rgb8,
+    parameter: Rgb8,
+}
+
+impl From<PdfStyle> for Theme {
+    fn from(style: PdfStyle) -> Self {
+        match style {
+            PdfStyle::Dark => Theme {
+                background: Some(Rgb8(0x0D, 0x11, 0x17)),
+                text: Rgb8(0xE6, 0xED, 0xF3),
+                rule: Rgb8(0x30, 0x36, 0x3D),
+                comment: Rgb8(0x8B, 0x94, 0x9E),
+                keyword: Rgb8(0xFF, 0x7B, 0x72),
+                string: Rgb8(0xA5, 0xD6, 0xFF),
+                constant: Rgb8(0x79, 0xC0, 0xFF),
+                name: Rgb8(0xD2, 0xA8, 0xFF),
+                tag: Rgb8(0x7E, 0xE7, 0x87),
+                parameter: Rgb8(0xFF, 0xA6, 0x57),
+            },
+            PdfStyle::Light => Theme {
+                background: None,
+                text: Rgb8(0x1F, 0x23, 0x28),
+                rule: Rgb8(0xD0, 0xD7, 0xDE),
+                comment: Rgb8(0x6E, 0x77, 0x81),
+                keyword: Rgb8(0xCF, 0x22, 0x2E),
+                string: Rgb8(0x0A, 0x30, 0x69),
+                constant: Rgb8(0x05, 0x50, 0xAE),
+                name: Rgb8(0x82, 0x50, 0xDF),
+                tag: Rgb8(0x11, 0x63, 0x29),
+                parameter: Rgb8(0x95, 0x38, 0x00),
+            },
+        }
+    }
+}
+
+/// Token kinds of the `hl-` classes added by `parse_markdown`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Highlight {
+    Comment,
+    Keyword,
+    String,
+    Constant,
+    Name,
+    Tag,
+    Parameter,
+}
+
+impl Highlight {
+    //Mirrors the selectors in the pdf stylesheets, later rules win
+    fn from_classes(classes: &[&str]) -> Option<Self> {
+        let has = |class: &str| classes.contains(&class);
+
+        if has("hl-variable") && has("hl-parameter") {
+            Some(Highlight::Parameter)
+        } else if has("hl-entity") && has("hl-tag") {
+            Some(Highlight::Tag)
+        } else if has("hl-entity") && has("hl-name") {
+            Some(Highlight::Name)
+        } else if has("hl-constant") || has("hl-support") {
+            Some(Highlight::Constant)
+        } else if has("hl-string") {
+            Some(Highlight::String)
+        } else if has("hl-keyword") || has("hl-storage") {
+            Some(Highlight::Keyword)
+        } else if has("hl-comment") {
+            Some(Highlight::Comment)
+        } else {
+            None
+        }
+    }
+
+    fn color(self, theme: &Theme) -> Rgb8 {
+        match self {
+            Highlight::Comment => theme.comment,
+            Highlight::Keyword => theme.keyword,
+            Highlight::String => theme.string,
+            Highlight::Constant => theme.constant,
+            Highlight::Name => theme.name,
+            Highlight::Tag => theme.tag,
+            Highlight::Parameter => theme.parameter,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct InlineStyle {
+    bold: bool,
+    italic: bool,
+    mono: bool,
+    preformatted: bool,
+    highlight: Option<Highlight>,
+}
+
+impl InlineStyle {
+    fn font(self) -> Font {
+        let mut font = if self.mono { Font::Mono } else { Font::Regular };
+        if self.bold {
+            font = font.bold();
+        }
+        if self.italic {
+            font = font.italic();
+        }
+        font
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Span {
+    text: String,
+    font: Font,
+    highlight: Option<Highlight>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum BlockKind {
+    Heading(u8),
+    Paragraph,
+    Code,
+    Rule,
+}
+
+#[derive(Debug)]
+struct Block {
+    kind: BlockKind,
+    spans: Vec<Span>,
+    indent: f32,
+    marker: Option<String>,
+}
+
+/// Flattens the html tree into a list of blocks of styled text.
+struct BlockCollector<'p, 'a> {
+    parser: &'p Parser<'a>,
+    blocks: Vec<Block>,
+    spans: Vec<Span>,
+    indent: f32,
+    marker: Option<String>,
+}
+
+impl<'p, 'a> BlockCollector<'p, 'a> {
+    fn new(parser: &'p Parser<'a>) -> Self {
+        BlockCollector {
+            parser,
+            blocks: Vec::new(),
+            spans: Vec::new(),
+            indent: 0.0,
+            marker: None,
+        }
+    }
+
+    fn push_text(&mut self, text: &str, style: InlineStyle) {
+        let text = decode_entities(text);
+
+        let text = if style.preformatted {
+            text
+        } else {
+            let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            let starts_with_space = text.starts_with(char::is_whitespace);
+            let ends_with_space = text.ends_with(char::is_whitespace);
+
+            match (collapsed.is_empty(), starts_with_space, ends_with_space) {
+                (true, true, _) => " ".to_string(),
+                (true, false, _) => return,
+                (false, true, true) => format!(" {collapsed} "),
+                (false, true, false) => format!(" {collapsed}"),
+                (false, false, true) => format!("{collapsed} "),
+                (false, false, false) => collapsed,
+            }
+        };
+
+        self.spans.push(Span {
+            text,
+            font: style.font(),
+            highlight: style.highlight,
+        });
+    }
+
+    fn finish_block(&mut self, kind: BlockKind) {
+        let has_text = self.spans.iter().any(|span| !span.text.trim().is_empty());
+
+        if has_text || kind == BlockKind::Rule {
+            self.blocks.push(Block {
+                kind,
+                spans: std::mem::take(&mut self.spans),
+                indent: self.indent,
+                marker: self.marker.take(),
+            });
+        } else {
+            self.spans.clear();
+        }
+    }
+
+    fn walk_children(&mut self, tag: &tl::HTMLTag, style: InlineStyle) {
+        for child in tag.children().top().iter() {
+            self.walk(*child, style);
+        }
+    }
+
+    fn walk(&mut self, handle: NodeHandle, style: InlineStyle) {
+        let Some(node) = handle.get(self.parser) else {
+            return;
+        };
+
+        let tag = match node {
+            Node::Raw(text) => return self.push_text(&text.as_utf8_str(), style),
+            Node::Comment(_) => return,
+            Node::Tag(tag) => tag,
+        };
+
+        let name = tag.name().as_utf8_str().to_ascii_lowercase();
+        let classes = tag
+            .attributes()
+            .class_iter()
+            .map(|classes| classes.collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        //KaTeX keeps the source of every formula in an annotation
+        if classes.contains(&"katex") {
+            let source = tag.find_node(self.parser, &mut |node| {
+                node.as_tag()
+                    .is_some_and(|tag| tag.name().as_utf8_str() == "annotation")
+            });
+
+            if let Some(source) = source.and_then(|source| source.get(self.parser)) {
+                let tex = source.inner_text(self.parser);
+                let math_style = InlineStyle {
+                    mono: true,
+                    ..style
+                };
+                self.push_text(&tex, math_style);
+            }
+            return;
+        }
+
+        match name.as_str() {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.finish_block(BlockKind::Paragraph);
+                let level = name.as_bytes()[1] - b'0';
+                self.walk_children(
+                    tag,
+                    InlineStyle {
+                        bold: true,
+                        ..style
+                    },
+                );
+                self.finish_block(BlockKind::Heading(level));
+            }
+            "p" | "div" | "dt" | "dd" => {
+                self.finish_block(BlockKind::Paragraph);
+                self.walk_children(tag, style);
+                self.finish_block(BlockKind::Paragraph);
+            }
+            "pre" => {
+                self.finish_block(BlockKind::Paragraph);
+                let code_style = InlineStyle {
+                    mono: true,
+                    preformatted: true,
+                    ..style
+                };
+                self.walk_children(tag, code_style);
+                self.finish_block(BlockKind::Code);
+            }
+            "ul" | "ol" => {
+                self.finish_block(BlockKind::Paragraph);
+                self.indent += LIST_INDENT;
+
+                let mut number = 1;
+                for child in tag.children().top().iter() {
+                    let is_item = child
+                        .get(self.parser)
+                        .and_then(Node::as_tag)
+                        .is_some_and(|child| child.name().as_utf8_str() == "li");
+
+                    if is_item {
+                        self.marker = Some(if name == "ol" {
+                            format!("{number}.")
+                        } else {
+                            "-".to_string()
+                        });
+                        number += 1;
+                    }
+
+                    self.walk(*child, style);
+                    self.finish_block(BlockKind::Paragraph);
+                }
+
+                self.indent -= LIST_INDENT;
+            }
+            "blockquote" => {
+                self.finish_block(BlockKind::Paragraph);
+                self.indent += QUOTE_INDENT;
+                self.walk_children(
+                    tag,
+                    InlineStyle {
+                        italic: true,
+                        ..style
+                    },
+                );
+                self.finish_block(BlockKind::Paragraph);
+                self.indent -= QUOTE_INDENT;
+            }
+            "tr" => {
+                self.finish_block(BlockKind::Paragraph);
+                let children = tag.children();
+                let cells = children.top().iter().filter(|cell| {
+                    cell.get(self.parser)
+                        .and_then(Node::as_tag)
+                        .is_some_and(|cell| matches!(cell.name().as_bytes(), b"td" | b"th"))
+                });
+
+                for (position, cell) in cells.enumerate() {
+                    if position > 0 {
+                        self.push_text(
+                            " | ",
+                            InlineStyle {
+                                preformatted: true,
+                                ..style
+                            },
+                        );
+                    }
+                    self.walk(*cell, style);
+                }
+                self.finish_block(BlockKind::Paragraph);
+            }
+            "th" => self.walk_children(
+                tag,
+                InlineStyle {
+                    bold: true,
+                    ..style
+                },
+            ),
+            "hr" => {
+                self.finish_block(BlockKind::Paragraph);
+                self.finish_block(BlockKind::Rule);
+            }
+            "br" => self.push_text(
+                "\n",
+                InlineStyle {
+                    preformatted: true,
+                    ..style
+                },
+            ),
+            "strong" | "b" => self.walk_children(
+                tag,
+                InlineStyle {
+                    bold: true,
+                    ..style
+                },
+            ),
+            "em" | "i" => self.walk_children(
+                tag,
+                InlineStyle {
+                    italic: true,
+                    ..style
+                },
+            ),
+            "code" | "kbd" => self.walk_children(
+                tag,
+                InlineStyle {
+                    mono: true,
+                    ..style
+                },
+            ),
+            "span" => {
+                let highlight = Highlight::from_classes(&classes).or(style.highlight);
+                self.walk_children(tag, InlineStyle { highlight, ..style });
+            }
+            "img" => {
+                if let Some(Some(alt)) = tag.attributes().get("alt") {
+                    let alt = format!("[{}]", alt.as_utf8_str());
+                    self.push_text(
+                        &alt,
+                        InlineStyle {
+                            italic: true,
+                            ..style
+                        },
+                    );
+                }
+            }
+            "input" => {
+                let checked = tag.attributes().contains("checked");
+                let checkbox = if checked { "[x]" } else { "[ ]" };
+                self.push_text(
+                    checkbox,
+                    InlineStyle {
+                        mono: true,
+                        preformatted: true,
+                        ..style
+                    },
+                );
+            }
+            "script" | "style" | "head" | "title" => {}
+            _ => self.walk_children(tag, style),
+        }
+    }
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let entity = rest
+            .find(';')
+            .filter(|end| *end <= 10)
+            .and_then(|end| Some((decode_entity(&rest[1..end])?, end)));
+
+        match entity {
+            Some((character, end)) => {
+                decoded.push(character);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    decoded.push_str(rest);
+    decoded
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    match entity {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        _ => {
+            let code = entity.strip_prefix('#')?;
+            let code = match code.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => code.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+/// A piece of a laid out line, positioned relative to the start of the line.
+struct Run {
+    text: String,
+    font: Font,
+    highlight: Option<Highlight>,
+    x: f32,
+}
+
+//Text continuing the previous run in the same style is appended to it, so
+//the PDF has one text operation per styled stretch instead of one per word
+fn push_run(lines: &mut [Vec<Run>], text: &str, span: &Span, x: f32) {
+    let line = lines.last_mut().expect("Lines is never empty");
+
+    match line.last_mut() {
+        Some(run) if run.font == span.font && run.highlight == span.highlight => {
+            run.text.push_str(text)
+        }
+        _ => line.push(Run {
+            text: text.to_string(),
+            font: span.font,
+            highlight: span.highlight,
+            x,
+        }),
+    }
+}
+
+/// Breaks spans into lines no wider than `width` mm.
+fn wrap_spans(spans: &[Span], size: f32, width: f32, preformatted: bool) -> Vec<Vec<Run>> {
+    let mut lines = vec![Vec::new()];
+    let mut x = 0.0;
+
+    for span in spans {
+        let pieces: Vec<&str> = if preformatted {
+            span.text.split_inclusive('\n').collect()
+        } else {
+            span.text.split_inclusive(' ').collect()
+        };
+
+        for piece in pieces {
+            let newline = piece.ends_with('\n');
+            let mut piece = piece.trim_end_matches('\n');
+
+            loop {
+                let piece_width = span.font.text_width(piece, size);
+                let trimmed_width = span.font.text_width(piece.trim_end(), size);
+
+                if x + trimmed_width <= width || piece.is_empty() {
+                    if x > 0.0 || !piece.trim().is_empty() || preformatted {
+                        push_run(&mut lines, piece, span, x);
+                        x += piece_width;
+                    }
+                    break;
+                }
+
+                if x > 0.0 && trimmed_width <= width && !preformatted {
+                    lines.push(Vec::new());
+                    x = 0.0;
+                    continue;
+                }
+
+                //Doesn't fit on a line of its own, break it between characters
+                let mut fitting = 0;
+                let mut fitting_width = x;
+                for (index, character) in piece.char_indices() {
+                    let character_width =
+                        span.font.char_width(character) as f32 / 1000.0 * size * MM_PER_PT;
+                    if fitting_width + character_width > width && (index > 0 || x > 0.0) {
+                        break;
+                    }
+                    fitting_width += character_width;
+                    fitting = index + character.len_utf8();
+                }
+
+                if fitting == 0 {
+                    lines.push(Vec::new());
+                    x = 0.0;
+                    continue;
+                }
+
+                push_run(&mut lines, &piece[..fitting], span, x);
+                lines.push(Vec::new());
+                x = 0.0;
+                piece = &piece[fitting..];
+            }
+
+            if newline {
+                lines.push(Vec::new());
+                x = 0.0;
+            }
+        }
+    }
+
+    if lines.len() > 1 && lines.last().is_some_and(Vec::is_empty) {
+        lines.pop();
+    }
+
+    lines
+}
+
+struct PageWriter {
+    document: PdfDocumentReference,
+    layer: PdfLayerReference,
+    fonts: Vec<(Font, IndirectFontRef)>,
+    theme: Theme,
+    //Distance of the next line from the bottom of the page, in mm
+    cursor: f32,
+}
+
+impl PageWriter {
+    fn new(theme: Theme) -> Self {
+        let (document, page, layer) = PdfDocument::new(
+            "StudyBuddyDownload",
+            Mm(PAGE_WIDTH),
+            Mm(PAGE_HEIGHT),
+            "Layer 1",
+        );
+
+        let fonts = Font::ALL
+            .iter()
+            .map(|font| {
+                let reference = document
+                    .add_builtin_font(font.builtin())
+                    .expect("Builtin fonts are always available");
+                (*font, reference)
+            })
+            .collect();
+
+        let layer = document.get_page(page).get_layer(layer);
+
+        let writer = PageWriter {
+            document,
+            layer,
+            fonts,
+            theme,
+            cursor: PAGE_HEIGHT - MARGIN,
+        };
+        writer.paint_background();
+        writer
+    }
+
+    fn paint_background(&self) {
+        if let Some(background) = self.theme.background {
+            self.layer.set_fill_color(background.into());
+            self.layer
+                .add_rect(Rect::new(Mm(0.0), Mm(0.0), Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT)));
+        }
+    }
+
+    fn new_page(&mut self) {
+        let (page, layer) = self
+            .document
+            .add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Layer 1");
+        self.layer = self.document.get_page(page).get_layer(layer);
+        self.cursor = PAGE_HEIGHT - MARGIN;
+        self.paint_background();
+    }
+
+    fn ensure_space(&mut self, height: f32) {
+        if self.cursor - height < MARGIN {
+            self.new_page();
+        }
+    }
+
+    fn font(&self, font: Font) -> &IndirectFontRef {
+        &self
+            .fonts
+            .iter()
+            .find(|(kind, _)| *kind == font)
+            .expect("Every font kind is loaded")
+            .1
+    }
+
+    fn write_block(&mut self, block: &Block) {
+        let (size, space_before, space_after) = match block.kind {
+            BlockKind::Heading(1) => (22.0, 6.0, 3.0),
+            BlockKind::Heading(2) => (18.0, 5.0, 3.0),
+            BlockKind::Heading(3) => (15.0, 4.0, 2.0),
+            BlockKind::Heading(_) => (13.0, 4.0, 2.0),
+            BlockKind::Paragraph => (BODY_SIZE, 0.0, 3.0),
+            BlockKind::Code => (CODE_SIZE, 1.0, 4.0),
+            BlockKind::Rule => {
+                self.write_rule();
+                return;
+            }
+        };
+
+        let line_height = size * LINE_SPACING * MM_PER_PT;
+        let left = MARGIN + block.indent;
+        let preformatted = block.kind == BlockKind::Code;
+        let lines = wrap_spans(
+            &block.spans,
+            size,
+            CONTENT_WIDTH - block.indent,
+            preformatted,
+        );
+
+        self.cursor -= space_before;
+
+        for (position, line) in lines.iter().enumerate() {
+            self.ensure_space(line_height);
+            self.cursor -= line_height;
+
+            if position == 0 {
+                if let Some(marker) = &block.marker {
+                    let marker_width = Font::Regular.text_width(marker, size);
+                    self.layer.set_fill_color(self.theme.text.into());
+                    self.layer.use_text(
+                        marker.as_str(),
+                        size,
+                        Mm(left - marker_width - 2.0),
+                        Mm(self.cursor),
+                        self.font(Font::Regular),
+                    );
+                }
+            }
+
+            for run in line {
+                if run.text.trim().is_empty() {
+                    continue;
+                }
+
+                let color = run
+                    .highlight
+                    .map_or(self.theme.text, |highlight| highlight.color(&self.theme));
+                self.layer.set_fill_color(color.into());
+                self.layer.use_text(
+                    run.text.as_str(),
+                    size,
+                    Mm(left + run.x),
+                    Mm(self.cursor),
+                    self.font(run.font),
+                );
+            }
+        }
+
+        self.cursor -= space_after;
+    }
+
+    fn write_rule(&mut self) {
+        self.ensure_space(6.0);
+        self.cursor -= 3.0;
+
+        let line = Line {
+            points: vec![
+                (Point::new(Mm(MARGIN), Mm(self.cursor)), false),
+                (Point::new(Mm(PAGE_WIDTH - MARGIN), Mm(self.cursor)), false),
+            ],
+            is_closed: false,
+        };
+
+        self.layer.set_outline_color(self.theme.rule.into());
+        self.layer.set_outline_thickness(0.5);
+        self.layer.add_line(line);
+        self.cursor -= 3.0;
+    }
+}
@@ -0,0 +1,239 @@
+use crate::mail::OutgoingMail;
+use crate::server::AppState;
+use crate::sessions::{revoke_all_sessions, ClientInfo};
+use crate::throttle::Throttle;
+use crate::users::set_password;
+use crate::StudyBuddyError;
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::info;
+
+const RECOVERY_CODE_TTL: time::Duration = time::Duration::minutes(30);
+
+//Wrong codes tried for an email before its code stops working
+const MAX_FAILED_ATTEMPTS: i32 = 5;
+
+//Requests without a known address share one key, they are still throttled
+const UNKNOWN_IP: &str = "unknown";
+
+/// Limits on how often recovery emails can be requested and recovery codes
+/// tried, requests are counted both per email address and per client IP.
+#[derive(Debug)]
+pub struct RecoveryThrottle {
+    sends: Throttle,
+    attempts: Throttle,
+}
+
+impl Default for RecoveryThrottle {
+    fn default() -> Self {
+        RecoveryThrottle {
+            sends: Throttle::new(3, Duration::from_secs(15 * 60)),
+            attempts: Throttle::new(10, Duration::from_secs(15 * 60)),
+        }
+    }
+}
+
+impl RecoveryThrottle {
+    fn allow(throttle: &Throttle, email: &str, client: &ClientInfo) -> bool {
+        let ip = client.ip_address.as_deref().unwrap_or(UNKNOWN_IP);
+        let email_allowed = throttle.attempt(&format!("email:{}", email.to_lowercase()));
+        let ip_allowed = throttle.attempt(&format!("ip:{}", ip));
+
+        email_allowed && ip_allowed
+    }
+
+    fn allow_send(&self, email: &str, client: &ClientInfo) -> bool {
+        RecoveryThrottle::allow(&self.sends, email, client)
+    }
+
+    fn allow_attempt(&self, email: &str, client: &ClientInfo) -> bool {
+        RecoveryThrottle::allow(&self.attempts, email, client)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Email {
+    email: String,
+}
+
+//Codes are only ever stored hashed, a leaked table can't be used to reset passwords
+fn hash_recovery_code(code: &uuid::Uuid) -> String {
+    Sha256::digest(code.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+pub async fn send_password_recovery_email(
+    client: ClientInfo,
+    State(app_state): State<Arc<AppState>>,
+    Json(user_email): Json<Email>,
+) -> Result<Response, StudyBuddyError> {
+    if !app_state
+        .recovery_throttle
+        .allow_send(&user_email.email, &client)
+    {
+        info!("Throttled recovery email for {}", user_email.email);
+        return Err(StudyBuddyError::TooManyRequests);
+    }
+
+    let code = create_recovery_code(&app_state.pool, &user_email.email).await?;
+
+    let mail = OutgoingMail {
+        to: user_email.email,
+        subject: "StudyBuddy recovery email".to_string(),
+        html_body: include_str!("../static/html/email_content.html")
+            .replace("$recovery_code$", &code.to_string()),
+    };
+
+    app_state.mailer.send(mail).await?;
+
+    Ok((StatusCode::OK, "Email sent succesfully").into_response())
+}
+
+/// Issues a new recovery code for the user with `email`, any code issued
+/// before stops being valid.
+pub async fn create_recovery_code(
+    pool: &PgPool,
+    email: &str,
+) -> Result<uuid::Uuid, StudyBuddyError> {
+    let user_id = sqlx::query_scalar!("SELECT id FROM users WHERE email = $1", email)
+        .fetch_optional(pool)
+        .await?
+        .ok_or(StudyBuddyError::NoMatchingUserRecord)?;
+
+    let code = uuid::Uuid::new_v4();
+
+    sqlx::query!(
+        "INSERT INTO recovery_codes (user_id, code_hash, expires_at)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (user_id) DO UPDATE
+        SET code_hash = EXCLUDED.code_hash,
+            created_at = now(),
+            expires_at = EXCLUDED.expires_at,
+            failed_attempts = 0",
+        user_id,
+        hash_recovery_code(&code),
+        time::OffsetDateTime::now_utc() + RECOVERY_CODE_TTL
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(code)
+}
+
+/// Deletes every recovery code past its expiry, returns how many were deleted.
+pub async fn purge_expired_recovery_codes(pool: &PgPool) -> Result<u64, StudyBuddyError> {
+    let purged = sqlx::query!("DELETE FROM recovery_codes WHERE expires_at <= now()")
+        .execute(pool)
+        .await?
+        .rows_affected();
+
+    Ok(purged)
+}
+
+#[derive(Deserialize)]
+pub struct PasswordRecoveryRequest {
+    email: String,
+    code: uuid::Uuid,
+    password: String,
+}
+
+/// Resets the password of the user with the email the code was sent to and
+/// signs them out everywhere. The right code can only be used once, whether or
+/// not it has expired, and the code stops working after a few wrong ones.
+pub async fn try_recovery_code(
+    client: ClientInfo,
+    State(app_state): State<Arc<AppState>>,
+    Json(req): Json<PasswordRecoveryRequest>,
+) -> Result<Response, StudyBuddyError> {
+    if !app_state
+        .recovery_throttle
+        .allow_attempt(&req.email, &client)
+    {
+        info!(
+            "Throttled recovery code attempt for {} from {:?}",
+            req.email, client.ip_address
+        );
+        return Err(StudyBuddyError::TooManyRequests);
+    }
+
+    let mut transaction = app_state.pool.begin().await?;
+
+    let code = sqlx::query!(
+        "SELECT recovery_codes.user_id,
+            recovery_codes.code_hash,
+            recovery_codes.expires_at > now() AS \"valid!\"
+        FROM recovery_codes
+        JOIN users ON users.id = recovery_codes.user_id
+        WHERE users.email = $1
+        FOR UPDATE OF recovery_codes",
+        req.email
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    .ok_or(StudyBuddyError::InvalidRecoveryCode)?;
+
+    if code.code_hash != hash_recovery_code(&req.code) {
+        let failed_attempts = sqlx::query_scalar!(
+            "UPDATE recovery_codes
+            SET failed_attempts = failed_attempts + 1
+            WHERE user_id = $1
+            RETURNING failed_attempts",
+            code.user_id
+        )
+        .fetch_one(&mut *transaction)
+        .await?;
+
+        if failed_attempts >= MAX_FAILED_ATTEMPTS {
+            sqlx::query!(
+                "DELETE FROM recovery_codes WHERE user_id = $1",
+                code.user_id
+            )
+            .execute(&mut *transaction)
+            .await?;
+
+            info!(
+                "Locked out recovery code of user {} after {} wrong codes",
+                code.user_id, failed_attempts
+            );
+        }
+
+        transaction.commit().await?;
+        return Err(StudyBuddyError::InvalidRecoveryCode);
+    }
+
+    sqlx::query!(
+        "DELETE FROM recovery_codes WHERE user_id = $1",
+        code.user_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    if !code.valid {
+        transaction.commit().await?;
+        return Err(StudyBuddyError::InvalidRecoveryCode);
+    }
+
+    set_password(&mut *transaction, code.user_id, req.password).await?;
+
+    let revoked = revoke_all_sessions(&mut *transaction, code.user_id, None).await?;
+
+    transaction.commit().await?;
+
+    info!(
+        "Reset password of user {} and revoked {} sessions",
+        code.user_id, revoked
+    );
+
+    Ok((StatusCode::OK, "Successfully reset password").into_response())
+}
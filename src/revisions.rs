@@ -0,0 +1,227 @@
+use crate::authorization::{authorize_document_access, Access};
+use crate::server::AppState;
+use crate::users::{store_document_content, UserCtx};
+use crate::StudyBuddyError;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool, Postgres, Transaction};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How long revisions are kept around, every revision younger than `keep_all`
+/// survives, up to `keep_hourly` only the newest one of each hour does and past
+/// that only the newest one of each day. The latest revision of a document is
+/// never pruned.
+#[derive(Clone, Debug)]
+pub struct RetentionPolicy {
+    pub keep_all: Duration,
+    pub keep_hourly: Duration,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        RetentionPolicy {
+            keep_all: Duration::from_secs(24 * 60 * 60),
+            keep_hourly: Duration::from_secs(30 * 24 * 60 * 60),
+        }
+    }
+}
+
+#[derive(Serialize, FromRow)]
+pub struct RevisionSummary {
+    revision_id: uuid::Uuid,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: time::OffsetDateTime,
+    characters: i32,
+}
+
+#[derive(Serialize, FromRow)]
+pub struct Revision {
+    revision_id: uuid::Uuid,
+    document_id: uuid::Uuid,
+    content: String,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: time::OffsetDateTime,
+}
+
+/// Stores `content` as the newest revision of the document, unless it is the
+/// same as the current newest revision.
+pub async fn record_revision(
+    transaction: &mut Transaction<'_, Postgres>,
+    document_id: uuid::Uuid,
+    content: &str,
+) -> Result<(), StudyBuddyError> {
+    sqlx::query!(
+        "INSERT INTO document_revisions (revision_id, document_id, content)
+        SELECT $1, $2, $3
+        WHERE $3 IS DISTINCT FROM (
+            SELECT content
+            FROM document_revisions
+            WHERE document_id = $2
+            ORDER BY created_at DESC
+            LIMIT 1
+        )",
+        uuid::Uuid::new_v4(),
+        document_id,
+        content
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct RevisionsRequest {
+    document_id: uuid::Uuid,
+}
+
+pub async fn list_revisions(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+    Query(request): Query<RevisionsRequest>,
+) -> Result<Json<Vec<RevisionSummary>>, StudyBuddyError> {
+    let pool = &app_state.pool;
+
+    authorize_document_access(pool, &ctx, request.document_id, Access::Viewer).await?;
+
+    let revisions = sqlx::query_as::<_, RevisionSummary>(
+        "SELECT revision_id, created_at, char_length(content) AS characters
+        FROM document_revisions
+        WHERE document_id = $1
+        ORDER BY created_at DESC",
+    )
+    .bind(request.document_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(Json(revisions))
+}
+
+#[derive(Deserialize)]
+pub struct RevisionId {
+    revision_id: uuid::Uuid,
+}
+
+async fn fetch_revision(
+    pool: &PgPool,
+    ctx: &UserCtx,
+    revision_id: uuid::Uuid,
+    needed: Access,
+) -> Result<Revision, StudyBuddyError> {
+    let revision = sqlx::query_as::<_, Revision>(
+        "SELECT revision_id, document_id, content, created_at
+        FROM document_revisions
+        WHERE revision_id = $1",
+    )
+    .bind(revision_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or(StudyBuddyError::RevisionNotFound)?;
+
+    authorize_document_access(pool, ctx, revision.document_id, needed).await?;
+
+    Ok(revision)
+}
+
+pub async fn fetch_revision_content(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+    Query(request): Query<RevisionId>,
+) -> Result<Json<Revision>, StudyBuddyError> {
+    let pool = &app_state.pool;
+
+    Ok(Json(
+        fetch_revision(pool, &ctx, request.revision_id, Access::Viewer).await?,
+    ))
+}
+
+/// Makes the contents of a revision the current contents of its document, the
+/// restored contents become the newest revision so a restore can be undone.
+pub async fn restore_revision(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+    Json(request): Json<RevisionId>,
+) -> Result<Response, StudyBuddyError> {
+    let pool = &app_state.pool;
+
+    let revision = fetch_revision(pool, &ctx, request.revision_id, Access::Editor).await?;
+
+    info!(
+        "Restoring document {} to revision {}",
+        revision.document_id, revision.revision_id
+    );
+
+    //The room would save its own text over the restored one
+    if app_state.rooms.is_open(revision.document_id) {
+        return Err(StudyBuddyError::DocumentInRoom);
+    }
+
+    store_document_content(pool, revision.document_id, &revision.content).await?;
+
+    Ok((StatusCode::OK, "Restored document revision").into_response())
+}
+
+/// Deletes every revision the retention policy doesn't want to keep.
+pub async fn prune_revisions(
+    pool: &PgPool,
+    policy: &RetentionPolicy,
+) -> Result<u64, StudyBuddyError> {
+    let pruned = sqlx::query!(
+        "DELETE FROM document_revisions
+        WHERE revision_id IN (
+            SELECT revision_id
+            FROM (
+                SELECT revision_id,
+                    created_at,
+                    row_number() OVER (
+                        PARTITION BY document_id
+                        ORDER BY created_at DESC
+                    ) AS newest,
+                    row_number() OVER (
+                        PARTITION BY document_id, date_trunc(
+                            CASE
+                                WHEN created_at > now() - $2 * interval '1 second' THEN 'hour'
+                                ELSE 'day'
+                            END,
+                            created_at
+                        )
+                        ORDER BY created_at DESC
+                    ) AS newest_in_bucket
+                FROM document_revisions
+            ) ranked
+            WHERE newest > 1
+                AND newest_in_bucket > 1
+                AND created_at < now() - $1 * interval '1 second'
+        )",
+        policy.keep_all.as_secs_f64(),
+        policy.keep_hourly.as_secs_f64()
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(pruned)
+}
+
+/// Prunes revisions once an hour for as long as the server runs.
+pub async fn prune_revisions_periodically(pool: PgPool, policy: RetentionPolicy) {
+    let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        match prune_revisions(&pool, &policy).await {
+            Ok(pruned) => info!("Pruned {} document revisions", pruned),
+            Err(err) => warn!("Failed to prune document revisions: {:?}", err),
+        }
+    }
+}
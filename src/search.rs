@@ -0,0 +1,113 @@
+use crate::server::AppState;
+use crate::users::UserCtx;
+use crate::StudyBuddyError;
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::sync::Arc;
+use tracing::info;
+
+const DEFAULT_RESULT_LIMIT: i64 = 20;
+const MAX_RESULT_LIMIT: i64 = 100;
+
+//ts_headline doesn't escape the text it returns, so matches are marked with
+//control characters and only turned into <mark> tags after escaping
+const MATCH_START: char = '\u{2}';
+const MATCH_END: char = '\u{3}';
+
+#[derive(Deserialize)]
+pub struct SearchRequest {
+    query: String,
+    limit: Option<i64>,
+}
+
+#[derive(Serialize, FromRow)]
+pub struct SearchResult {
+    document_id: uuid::Uuid,
+    title: String,
+    rank: f32,
+    title_highlight: String,
+    snippet: String,
+}
+
+fn mark_matches(headline: &str) -> String {
+    let mut marked = String::with_capacity(headline.len());
+
+    for character in headline.chars() {
+        match character {
+            MATCH_START => marked.push_str("<mark>"),
+            MATCH_END => marked.push_str("</mark>"),
+            '&' => marked.push_str("&amp;"),
+            '<' => marked.push_str("&lt;"),
+            '>' => marked.push_str("&gt;"),
+            '"' => marked.push_str("&quot;"),
+            '\'' => marked.push_str("&#39;"),
+            other => marked.push(other),
+        }
+    }
+
+    marked
+}
+
+/// Full text search over the titles and contents of the caller's documents,
+/// best matches first.
+pub async fn search_documents(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+    Query(search_request): Query<SearchRequest>,
+) -> Result<Json<Vec<SearchResult>>, StudyBuddyError> {
+    let query = search_request.query.trim();
+
+    if query.is_empty() {
+        return Err(StudyBuddyError::IncompleteRequest);
+    }
+
+    let limit = search_request
+        .limit
+        .unwrap_or(DEFAULT_RESULT_LIMIT)
+        .clamp(1, MAX_RESULT_LIMIT);
+
+    info!(
+        "Searching documents of user {} for {:?}",
+        ctx.user_id(),
+        query
+    );
+
+    let headline_options = format!(
+        "StartSel={MATCH_START}, StopSel={MATCH_END}, MaxFragments=3, MaxWords=20, MinWords=5"
+    );
+
+    let pool = &app_state.pool;
+
+    let results = sqlx::query_as::<_, SearchResult>(
+        "SELECT document_id,
+            title,
+            ts_rank_cd(search_vector, query) AS rank,
+            ts_headline('english', title, query, $3) AS title_highlight,
+            ts_headline('english', content, query, $3) AS snippet
+        FROM documents, websearch_to_tsquery('english', $2) AS query
+        WHERE user_id = $1 AND deleted_at IS NULL AND search_vector @@ query
+        ORDER BY rank DESC, title
+        LIMIT $4",
+    )
+    .bind(ctx.user_id())
+    .bind(query)
+    .bind(headline_options)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    let results = results
+        .into_iter()
+        .map(|result| SearchResult {
+            title_highlight: mark_matches(&result.title_highlight),
+            snippet: mark_matches(&result.snippet),
+            ..result
+        })
+        .collect();
+
+    Ok(Json(results))
+}
@@ -1,54 +1,131 @@
+use crate::collaboration::Rooms;
+use crate::config::Config;
+use crate::database;
+use crate::mail::{self, Mailer};
+use crate::pdf::{self, PdfRenderer, PdfStyle, RenderedPdf};
+use crate::recovery::RecoveryThrottle;
+use crate::sessions::SessionPolicy;
+use crate::{
+    export, import, live, notebooks, recovery, request_id, revisions, search, sessions, sharing,
+    tags, trash, users,
+};
 use axum::{
-    extract::{
-        ws::{Message, WebSocket},
-        WebSocketUpgrade,
-    },
-    response::{Json, Response},
+    extract::{DefaultBodyLimit, State},
+    middleware,
+    response::Json,
+    routing::{delete, get, post, put},
+    Router,
 };
+use base64::{engine::general_purpose::STANDARD, Engine};
 use serde::{Deserialize, Serialize};
-use sqlx::{postgres::PgPoolOptions, PgPool};
+use sqlx::PgPool;
+use std::sync::Arc;
+use tower_cookies::CookieManagerLayer;
+use tower_http::services::{ServeDir, ServeFile};
 use tracing::info;
 
+/// Services shared by every request. It is handed out behind an `Arc` without
+/// a lock, anything in here that changes at runtime synchronizes itself.
 pub struct AppState {
+    pub config: Config,
     pub pool: PgPool,
+    pub pdf_renderer: Arc<dyn PdfRenderer>,
+    pub mailer: Arc<dyn Mailer>,
+    pub session_policy: SessionPolicy,
+    pub recovery_throttle: RecoveryThrottle,
+    pub rooms: Rooms,
 }
 
 impl AppState {
-    pub async fn new() -> Self {
+    pub async fn new(config: Config) -> Self {
         AppState {
-            pool: PgPoolOptions::new()
-                .max_connections(8)
-                .connect(&std::env::var("DATABASE_URL").expect("DATABASE_URL must be set"))
+            pool: database::connect(&config)
                 .await
                 .expect("Failure of creation of AppState is reason enough to crash"),
+            pdf_renderer: pdf::renderer_from_config(&config.pdf),
+            mailer: mail::mailer_from_config(&config.mail)
+                .expect("Failure of creation of AppState is reason enough to crash"),
+            session_policy: SessionPolicy::default(),
+            recovery_throttle: RecoveryThrottle::default(),
+            rooms: Rooms::default(),
+            config,
         }
     }
 }
 
-pub async fn refresh_file(ws: WebSocketUpgrade) -> Response {
-    info!("Connecting to refresh socket");
-    ws.on_upgrade(modify_md_file_state)
-}
-
-pub async fn modify_md_file_state(mut socket: WebSocket) {
-    while let Some(new_md_file_state) = socket.recv().await {
-        let new_md_file_state = if let Ok(file_state) = new_md_file_state {
-            file_state
-        } else {
-            return;
-        };
-
-        if let Message::Text(file_state) = new_md_file_state {
-            let parse_result =
-                tokio::task::spawn_blocking(move || crate::parse_markdown(&file_state))
-                    .await
-                    .expect("Task cant panic");
-
-            if socket.send(Message::Text(parse_result)).await.is_err() {
-                return;
-            }
-        }
-    }
+/// Every route the application serves, without the transport level layers
+/// (tracing, rate limiting, timeouts) that `main` wraps around it.
+pub fn router(app_state: Arc<AppState>) -> Router {
+    let auth_needed_routes = Router::new()
+        .route("/log_out", post(users::log_out))
+        .route("/create_document", post(users::create_document))
+        .route("/save", put(users::save_document))
+        .route("/fetch_documents", get(users::fetch_posts))
+        .route("/rename_document", put(users::rename_document))
+        .route("/duplicate_document", post(users::duplicate_document))
+        .route("/pin_document", put(users::pin_document))
+        .route("/reorder_documents", put(users::reorder_documents))
+        .route("/fetch_content", get(users::fetch_post_content))
+        .route("/delete_document", delete(users::delete_document))
+        .route("/export_documents", get(export::export_documents))
+        .route("/fetch_trash", get(trash::fetch_trash))
+        .route(
+            "/import_documents",
+            post(import::import_documents).layer(DefaultBodyLimit::max(import::MAX_UPLOAD_SIZE)),
+        )
+        .route("/restore_document", post(trash::restore_document))
+        .route("/purge_document", delete(trash::purge_document))
+        .route("/empty_trash", delete(trash::empty_trash))
+        .route("/search", get(search::search_documents))
+        .route("/revisions", get(revisions::list_revisions))
+        .route("/revision", get(revisions::fetch_revision_content))
+        .route("/restore_revision", post(revisions::restore_revision))
+        .route("/fetch_sessions", get(sessions::fetch_sessions))
+        .route("/revoke_session", delete(sessions::delete_session))
+        .route(
+            "/revoke_all_sessions",
+            delete(sessions::delete_all_sessions),
+        )
+        .route("/fetch_notebooks", get(notebooks::fetch_notebooks))
+        .route("/create_notebook", post(notebooks::create_notebook))
+        .route("/rename_notebook", put(notebooks::rename_notebook))
+        .route("/move_notebook", put(notebooks::move_notebook))
+        .route("/delete_notebook", delete(notebooks::delete_notebook))
+        .route("/move_document", put(notebooks::move_document))
+        .route("/fetch_tags", get(tags::fetch_tags))
+        .route("/add_tag", post(tags::add_tag))
+        .route("/remove_tag", delete(tags::remove_tag))
+        .route("/share_document", post(sharing::share_document))
+        .route("/fetch_shares", get(sharing::fetch_shares))
+        .route("/unshare_document", delete(sharing::unshare_document))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            users::mw_user_ctx_resolver,
+        ));
+
+    Router::new()
+        .route_service("/", ServeFile::new("static/html/index.html"))
+        .route(
+            "/refresh",
+            get(live::refresh_file).layer(middleware::from_fn_with_state(
+                app_state.clone(),
+                users::mw_user_ctx_resolver,
+            )),
+        )
+        .route("/download", post(download_current_markdown))
+        .route("/create_user", post(users::create_user))
+        .route("/log_in", post(users::log_in))
+        .route_service("/recovery", ServeFile::new("static/html/recovery.html"))
+        .route(
+            "/send_recovery",
+            post(recovery::send_password_recovery_email),
+        )
+        .route("/try_recovery_code", post(recovery::try_recovery_code))
+        .merge(auth_needed_routes)
+        .nest_service("/static", ServeDir::new("static"))
+        .layer(CookieManagerLayer::new())
+        .layer(middleware::from_fn(request_id::mw_request_id))
+        .with_state(app_state)
 }
 
 #[derive(Deserialize, Debug)]
@@ -70,73 +147,26 @@ pub struct ApiResponse {
 
 #[axum_macros::debug_handler]
 pub async fn download_current_markdown(
+    State(app_state): State<Arc<AppState>>,
     Json(html_json_payload): Json<PDFDownloadRequest>,
 ) -> Result<Json<ApiResponse>, crate::StudyBuddyError> {
     info!("Fullfilling download pdf request");
 
-    #[derive(Serialize, Debug, Default)]
-    struct ApiRequest {
-        html: String,
-        css: String,
-        js: String,
-    }
-
-    enum StyleType {
-        Light,
-        Dark,
-    }
-
-    impl TryFrom<&str> for StyleType {
-        type Error = String;
+    let style = PdfStyle::try_from(html_json_payload.css.as_str()).unwrap_or_default();
 
-        fn try_from(value: &str) -> Result<Self, Self::Error> {
-            match value {
-                "dark" => Ok(StyleType::Dark),
-                "light" => Ok(StyleType::Light),
-                _ => Err(format!("Style {} not supported", value)),
-            }
-        }
-    }
-
-    let css = StyleType::try_from(html_json_payload.css.as_str());
-
-    let css = if let Ok(style) = css {
-        match style {
-            StyleType::Dark => include_str!("../templates/pdf.css"),
-            StyleType::Light => include_str!("../templates/lightpdf.css"),
-        }
-    } else {
-        include_str!("../templates/pdf.css")
-    }
-    .to_string();
-
-    let html = format!("<!DOCTYPE html><html lang=\"en\"><head><meta charset=\"utf-8\"><link href=\"https://pvinis.github.io/iosevka-webfont/3.4.1/iosevka.css\" rel=\"stylesheet\"/><link rel=\"stylesheet\" href=\"https://cdn.jsdelivr.net/npm/katex@0.16.7/dist/katex.min.css\" integrity=\"sha384-3UiQGuEI4TTMaFmGIZumfRPtfKQ3trwQE2JgosJxCnGmQpL/lJdjpcHkaaFwHlcI\" crossorigin=\"anonymous\"/><title>StudyBuddyDownload</title></head><body><div>{}</div></body></html>",html_json_payload.html);
-
-    let js = include_str!("../templates/pdf.js").to_string();
-
-    let api_request = ApiRequest { html, css, js };
-    let api_url = "https://api.pdfendpoint.com/v1/convert";
-    let mut headers = reqwest::header::HeaderMap::new();
-    let auth_key = std::env::var("PDF_API_KEY").expect("PDF_API_KEY must be set");
-    let auth_string = format!("Bearer {}", auth_key);
-
-    let auth =
-        reqwest::header::HeaderValue::from_str(&auth_string).expect("Auth key is valid ASCII");
-    let content_type = reqwest::header::HeaderValue::from_static("application/json");
-    headers.insert(reqwest::header::CONTENT_TYPE, content_type);
-    headers.insert(reqwest::header::AUTHORIZATION, auth);
-
-    //Make POST request
-    let client = reqwest::Client::new();
-    let api_response = client
-        .post(api_url)
-        .headers(headers)
-        .json(&api_request)
-        .send()
+    //Locally rendered files are handed back inline so the client can keep
+    //treating every download as a link
+    let url = match app_state
+        .pdf_renderer
+        .render(&html_json_payload.html, style)
         .await?
-        .error_for_status()?
-        .json::<ApiResponse>()
-        .await?;
-
-    Ok(Json(api_response))
+    {
+        RenderedPdf::Url(url) => url,
+        RenderedPdf::Bytes(pdf) => format!("data:application/pdf;base64,{}", STANDARD.encode(pdf)),
+    };
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: DataFields { url },
+    }))
 }
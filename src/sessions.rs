@@ -0,0 +1,360 @@
+use crate::server::AppState;
+use crate::users::UserCtx;
+use crate::StudyBuddyError;
+use async_trait::async_trait;
+use axum::{
+    extract::{ConnectInfo, FromRequestParts, Query, State},
+    http::{header, request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgExecutor, PgPool};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use time::{Duration, OffsetDateTime};
+use tower_cookies::{Cookie, Cookies};
+use tracing::{info, warn};
+
+pub const SESSION_COOKIE: &str = "session_id";
+
+const PRUNE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15 * 60);
+
+/// How long sessions stay valid. A session expires `lifetime` after it was
+/// created or once it hasn't been used for `idle_timeout`, whichever comes
+/// first, remembered sessions use the longer `remembered_*` limits.
+#[derive(Clone, Debug)]
+pub struct SessionPolicy {
+    pub lifetime: Duration,
+    pub idle_timeout: Duration,
+    pub remembered_lifetime: Duration,
+    pub remembered_idle_timeout: Duration,
+}
+
+impl Default for SessionPolicy {
+    fn default() -> Self {
+        SessionPolicy {
+            lifetime: Duration::days(1),
+            idle_timeout: Duration::hours(2),
+            remembered_lifetime: Duration::days(365),
+            remembered_idle_timeout: Duration::days(30),
+        }
+    }
+}
+
+/// Where a session was started from, shown to the user when listing sessions.
+#[derive(Clone, Debug, Default)]
+pub struct ClientInfo {
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for ClientInfo {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        app_state: &Arc<AppState>,
+    ) -> Result<Self, Infallible> {
+        let header = |name| {
+            parts
+                .headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+
+        //Behind a proxy the peer address is the proxy's, the client is the
+        //first entry of X-Forwarded-For. Without one the header is whatever
+        //the client made up
+        let forwarded_for = header(header::HeaderName::from_static("x-forwarded-for"))
+            .filter(|_| app_state.config.trust_forwarded_for)
+            .and_then(|addresses| addresses.split(',').next().map(|ip| ip.trim().to_string()));
+
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(address)| address.ip().to_string());
+
+        Ok(ClientInfo {
+            user_agent: header(header::USER_AGENT),
+            ip_address: forwarded_for.or(peer),
+        })
+    }
+}
+
+/// A session that is known to be valid right now.
+pub struct ActiveSession {
+    pub session_id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    pub remembered: bool,
+    pub expires_at: OffsetDateTime,
+}
+
+pub async fn create_session(
+    pool: &PgPool,
+    policy: &SessionPolicy,
+    user_id: uuid::Uuid,
+    client: &ClientInfo,
+    remember: bool,
+) -> Result<ActiveSession, StudyBuddyError> {
+    let lifetime = if remember {
+        policy.remembered_lifetime
+    } else {
+        policy.lifetime
+    };
+
+    let session = ActiveSession {
+        session_id: uuid::Uuid::new_v4(),
+        user_id,
+        remembered: remember,
+        expires_at: OffsetDateTime::now_utc() + lifetime,
+    };
+
+    sqlx::query!(
+        "INSERT INTO sessions (session_id, user_id, user_agent, ip_address, remembered, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6)",
+        session.session_id,
+        user_id,
+        client.user_agent,
+        client.ip_address,
+        session.remembered,
+        session.expires_at
+    )
+    .execute(pool)
+    .await?;
+
+    info!("Created session for user {}", user_id);
+
+    Ok(session)
+}
+
+/// Marks the session as used and returns it, or `None` if the session doesn't
+/// exist, has outlived its lifetime or has been idle for too long.
+pub async fn touch_session(
+    pool: &PgPool,
+    policy: &SessionPolicy,
+    session_id: uuid::Uuid,
+) -> Result<Option<ActiveSession>, StudyBuddyError> {
+    let session = sqlx::query!(
+        "UPDATE sessions
+        SET last_seen_at = now()
+        WHERE session_id = $1
+            AND expires_at > now()
+            AND last_seen_at > now() - CASE WHEN remembered THEN $2::float8 ELSE $3::float8 END * interval '1 second'
+        RETURNING user_id, remembered, expires_at",
+        session_id,
+        policy.remembered_idle_timeout.as_seconds_f64(),
+        policy.idle_timeout.as_seconds_f64()
+    )
+    .fetch_optional(pool)
+    .await?
+    .map(|row| ActiveSession {
+        session_id,
+        user_id: row.user_id,
+        remembered: row.remembered,
+        expires_at: row.expires_at,
+    });
+
+    Ok(session)
+}
+
+pub async fn revoke_session(
+    pool: &PgPool,
+    user_id: uuid::Uuid,
+    session_id: uuid::Uuid,
+) -> Result<bool, StudyBuddyError> {
+    let revoked = sqlx::query!(
+        "DELETE FROM sessions
+        WHERE session_id = $1 AND user_id = $2",
+        session_id,
+        user_id
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(revoked > 0)
+}
+
+/// Revokes every session of the user except `keep`, returns how many were revoked.
+pub async fn revoke_all_sessions(
+    executor: impl PgExecutor<'_>,
+    user_id: uuid::Uuid,
+    keep: Option<uuid::Uuid>,
+) -> Result<u64, StudyBuddyError> {
+    let revoked = sqlx::query!(
+        "DELETE FROM sessions
+        WHERE user_id = $1 AND session_id IS DISTINCT FROM $2",
+        user_id,
+        keep
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+
+    Ok(revoked)
+}
+
+/// Deletes every session that has expired or has been idle for too long.
+pub async fn prune_sessions(pool: &PgPool, policy: &SessionPolicy) -> Result<u64, StudyBuddyError> {
+    let pruned = sqlx::query!(
+        "DELETE FROM sessions
+        WHERE expires_at <= now()
+            OR last_seen_at <= now() - CASE WHEN remembered THEN $1::float8 ELSE $2::float8 END * interval '1 second'",
+        policy.remembered_idle_timeout.as_seconds_f64(),
+        policy.idle_timeout.as_seconds_f64()
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(pruned)
+}
+
+/// Prunes sessions every 15 minutes for as long as the server runs.
+pub async fn prune_sessions_periodically(pool: PgPool, policy: SessionPolicy) {
+    let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        match prune_sessions(&pool, &policy).await {
+            Ok(pruned) => info!("Pruned {} expired sessions", pruned),
+            Err(err) => warn!("Failed to prune expired sessions: {:?}", err),
+        }
+    }
+}
+
+/// Cookie carrying the session id. Remembered sessions get a persistent cookie
+/// whose Max-Age runs until the session would go idle, so every use slides it
+/// forward, other sessions get a cookie that lives as long as the browser
+/// session.
+pub fn session_cookie(session: &ActiveSession, policy: &SessionPolicy) -> Cookie<'static> {
+    let mut cookie = Cookie::new(SESSION_COOKIE, session.session_id.to_string());
+
+    if session.remembered {
+        let remaining = session.expires_at - OffsetDateTime::now_utc();
+        cookie.set_max_age(policy.remembered_idle_timeout.min(remaining));
+    }
+
+    cookie
+}
+
+#[derive(FromRow)]
+struct SessionRow {
+    session_id: uuid::Uuid,
+    created_at: OffsetDateTime,
+    last_seen_at: OffsetDateTime,
+    expires_at: OffsetDateTime,
+    user_agent: Option<String>,
+    ip_address: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct SessionInfo {
+    session_id: uuid::Uuid,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    last_seen_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    expires_at: OffsetDateTime,
+    user_agent: Option<String>,
+    ip_address: Option<String>,
+    current: bool,
+}
+
+pub async fn fetch_sessions(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+) -> Result<Json<Vec<SessionInfo>>, StudyBuddyError> {
+    let policy = &app_state.session_policy;
+
+    let sessions = sqlx::query_as::<_, SessionRow>(
+        "SELECT session_id, created_at, last_seen_at, expires_at, user_agent, ip_address
+        FROM sessions
+        WHERE user_id = $1
+            AND expires_at > now()
+            AND last_seen_at > now() - CASE WHEN remembered THEN $2::float8 ELSE $3::float8 END * interval '1 second'
+        ORDER BY last_seen_at DESC",
+    )
+    .bind(ctx.user_id())
+    .bind(policy.remembered_idle_timeout.as_seconds_f64())
+    .bind(policy.idle_timeout.as_seconds_f64())
+    .fetch_all(&app_state.pool)
+    .await?;
+
+    let sessions = sessions
+        .into_iter()
+        .map(|session| SessionInfo {
+            current: session.session_id == ctx.session_id(),
+            session_id: session.session_id,
+            created_at: session.created_at,
+            last_seen_at: session.last_seen_at,
+            expires_at: session.expires_at,
+            user_agent: session.user_agent,
+            ip_address: session.ip_address,
+        })
+        .collect();
+
+    Ok(Json(sessions))
+}
+
+#[derive(Deserialize)]
+pub struct SessionId {
+    session_id: uuid::Uuid,
+}
+
+pub async fn delete_session(
+    cookies: Cookies,
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+    Query(request): Query<SessionId>,
+) -> Result<Response, StudyBuddyError> {
+    let pool = &app_state.pool;
+
+    if !revoke_session(pool, ctx.user_id(), request.session_id).await? {
+        return Err(StudyBuddyError::SessionNotFound);
+    }
+
+    if request.session_id == ctx.session_id() {
+        cookies.remove(Cookie::named(SESSION_COOKIE));
+    }
+
+    info!(
+        "User {} revoked session {}",
+        ctx.user_id(),
+        request.session_id
+    );
+
+    Ok((StatusCode::OK, "Revoked session").into_response())
+}
+
+#[derive(Deserialize)]
+pub struct RevokeAllRequest {
+    #[serde(default)]
+    keep_current: bool,
+}
+
+pub async fn delete_all_sessions(
+    cookies: Cookies,
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+    Query(request): Query<RevokeAllRequest>,
+) -> Result<Response, StudyBuddyError> {
+    let pool = &app_state.pool;
+
+    let keep = request.keep_current.then(|| ctx.session_id());
+    let revoked = revoke_all_sessions(pool, ctx.user_id(), keep).await?;
+
+    if !request.keep_current {
+        cookies.remove(Cookie::named(SESSION_COOKIE));
+    }
+
+    info!("User {} revoked {} sessions", ctx.user_id(), revoked);
+
+    Ok((StatusCode::OK, format!("Revoked {} sessions", revoked)).into_response())
+}
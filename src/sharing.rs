@@ -0,0 +1,194 @@
+use crate::authorization::{authorize_document, authorize_document_access, Access};
+use crate::server::AppState;
+use crate::users::{find_user_id, UserCtx};
+use crate::StudyBuddyError;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use std::sync::Arc;
+use tracing::info;
+
+#[derive(Deserialize)]
+pub struct ShareDocumentRequest {
+    document_id: uuid::Uuid,
+    email: String,
+    role: Access,
+}
+
+/// Gives another registered user access to a document, sharing it again with
+/// the same user changes their role. Only the owner can share a document.
+pub async fn share_document(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+    Json(request): Json<ShareDocumentRequest>,
+) -> Result<Response, StudyBuddyError> {
+    let pool = &app_state.pool;
+
+    authorize_document(pool, &ctx, request.document_id).await?;
+
+    let user_id = find_user_id(pool, request.email.trim()).await?;
+
+    if request.role == Access::Owner || user_id == ctx.user_id() {
+        return Err(StudyBuddyError::InvalidShare);
+    }
+
+    sqlx::query(
+        "INSERT INTO document_shares (document_id, user_id, role)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (document_id, user_id) DO UPDATE
+        SET role = excluded.role",
+    )
+    .bind(request.document_id)
+    .bind(user_id)
+    .bind(request.role)
+    .execute(pool)
+    .await?;
+
+    app_state
+        .rooms
+        .change_access(request.document_id, user_id, Some(request.role));
+
+    info!(
+        "Shared document {} with user {} as {:?}",
+        request.document_id, user_id, request.role
+    );
+
+    Ok((StatusCode::OK, "Shared document").into_response())
+}
+
+#[derive(Deserialize)]
+pub struct SharesRequest {
+    document_id: uuid::Uuid,
+}
+
+#[derive(Serialize, FromRow)]
+pub struct ShareRecord {
+    pub user_id: uuid::Uuid,
+    pub email: String,
+    pub role: Access,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: time::OffsetDateTime,
+}
+
+/// Everyone with access to a document, the owner first. Anyone with access can
+/// see the list.
+pub async fn fetch_shares(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+    Query(request): Query<SharesRequest>,
+) -> Result<Json<Vec<ShareRecord>>, StudyBuddyError> {
+    let pool = &app_state.pool;
+
+    authorize_document_access(pool, &ctx, request.document_id, Access::Viewer).await?;
+
+    let shares = sqlx::query_as::<_, ShareRecord>(
+        "SELECT user_id, email, role, created_at
+        FROM (
+            SELECT users.id AS user_id, users.email, 'owner' AS role, documents.created_at
+            FROM documents
+            JOIN users ON users.id = documents.user_id
+            WHERE documents.document_id = $1
+            UNION ALL
+            SELECT users.id, users.email, document_shares.role, document_shares.created_at
+            FROM document_shares
+            JOIN users ON users.id = document_shares.user_id
+            WHERE document_shares.document_id = $1
+        ) AS access
+        ORDER BY role = 'owner' DESC, created_at",
+    )
+    .bind(request.document_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(Json(shares))
+}
+
+#[derive(Deserialize)]
+pub struct UnshareRequest {
+    document_id: uuid::Uuid,
+    user_id: uuid::Uuid,
+}
+
+/// Takes away a user's access to a document, the owner can take it from anyone
+/// and everyone else can give up their own. Whoever lost access is removed
+/// from the document's live room right away.
+pub async fn unshare_document(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+    Query(request): Query<UnshareRequest>,
+) -> Result<Response, StudyBuddyError> {
+    let pool = &app_state.pool;
+
+    if request.user_id == ctx.user_id() {
+        authorize_document_access(pool, &ctx, request.document_id, Access::Viewer).await?;
+    } else {
+        authorize_document(pool, &ctx, request.document_id).await?;
+    }
+
+    let removed = sqlx::query!(
+        "DELETE FROM document_shares
+        WHERE document_id = $1 AND user_id = $2",
+        request.document_id,
+        request.user_id
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    if removed == 0 {
+        return Err(StudyBuddyError::ShareNotFound);
+    }
+
+    app_state
+        .rooms
+        .change_access(request.document_id, request.user_id, None);
+
+    info!(
+        "Removed access of user {} to document {}",
+        request.user_id, request.document_id
+    );
+
+    Ok((StatusCode::OK, "Removed access").into_response())
+}
+
+#[derive(Serialize, FromRow)]
+pub struct SharedDocumentRecord {
+    pub document_id: uuid::Uuid,
+    pub title: String,
+    pub owner_email: String,
+    pub role: Access,
+    pub tags: Vec<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: time::OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub updated_at: time::OffsetDateTime,
+}
+
+/// Documents other users shared with `user_id`, most recently updated first.
+pub async fn list_shared_documents(
+    pool: &PgPool,
+    user_id: uuid::Uuid,
+) -> Result<Vec<SharedDocumentRecord>, StudyBuddyError> {
+    Ok(sqlx::query_as::<_, SharedDocumentRecord>(
+        "SELECT documents.document_id, documents.title, users.email AS owner_email,
+            document_shares.role, documents.created_at, documents.updated_at,
+            ARRAY(
+                SELECT tag FROM document_tags
+                WHERE document_tags.document_id = documents.document_id
+                ORDER BY tag
+            ) AS tags
+        FROM document_shares
+        JOIN documents ON documents.document_id = document_shares.document_id
+        JOIN users ON users.id = documents.user_id
+        WHERE document_shares.user_id = $1 AND documents.deleted_at IS NULL
+        ORDER BY documents.updated_at DESC",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?)
+}
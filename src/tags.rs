@@ -0,0 +1,230 @@
+use crate::authorization::{authorize_document_access, Access};
+use crate::parsing::split_front_matter;
+use crate::server::AppState;
+use crate::users::UserCtx;
+use crate::StudyBuddyError;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, Transaction};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+const MAX_TAG_LENGTH: usize = 64;
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '-' | '_' | '/')
+}
+
+/// Lowercases a tag and drops a leading `#`, `None` if what is left isn't a
+/// valid tag. Tags need at least one letter so `#1` isn't picked up as one.
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let tag = tag.trim();
+    let tag = tag.strip_prefix('#').unwrap_or(tag).to_lowercase();
+
+    let valid = !tag.is_empty()
+        && tag.chars().count() <= MAX_TAG_LENGTH
+        && tag.chars().all(is_tag_char)
+        && tag.chars().any(char::is_alphabetic);
+
+    valid.then_some(tag)
+}
+
+/// Tags written in the markdown itself, both the `tags:` entry of the
+/// front-matter and inline `#tag`s outside of code. Colours like `#fff` are
+/// skipped where CSS would have them, after a `:` or in a `style` attribute.
+pub fn extract_tags(markdown: &str) -> BTreeSet<String> {
+    let (front_matter, body) = split_front_matter(markdown);
+    let mut tags = BTreeSet::new();
+
+    if let Some(front_matter) = front_matter {
+        //`tags: a, b` is a single YAML string but is meant as a list
+        tags.extend(
+            front_matter
+                .list("tags")
+                .iter()
+                .flat_map(|value| value.split(','))
+                .filter_map(normalize_tag),
+        );
+    }
+
+    let mut fence: Option<&str> = None;
+    for line in body.lines() {
+        let trimmed = line.trim_start();
+
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+            continue;
+        }
+
+        if let Some(marker) = ["```", "~~~"].into_iter().find(|m| trimmed.starts_with(m)) {
+            fence = Some(marker);
+            continue;
+        }
+
+        extract_inline_tags(line, &mut tags);
+    }
+
+    tags
+}
+
+//The hex forms of CSS colours, `#rgb`, `#rgba`, `#rrggbb` and `#rrggbbaa`
+fn is_colour(candidate: &str) -> bool {
+    matches!(candidate.len(), 3 | 4 | 6 | 8) && candidate.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+//Colours follow a property, like `color: #fff`, or sit in the `style`
+//attribute of inline HTML that is still open at the end of `before`
+fn in_colour_context(before: &str) -> bool {
+    if before.trim_end().ends_with(':') {
+        return true;
+    }
+
+    [("style=\"", '"'), ("style='", '\'')]
+        .into_iter()
+        .any(|(opening, quote)| {
+            before
+                .rfind(opening)
+                .is_some_and(|start| !before[start + opening.len()..].contains(quote))
+        })
+}
+
+fn extract_inline_tags(line: &str, tags: &mut BTreeSet<String>) {
+    let mut in_code = false;
+    let mut previous = ' ';
+    for (index, c) in line.char_indices() {
+        if c == '`' {
+            in_code = !in_code;
+        } else if c == '#' && !in_code && previous.is_whitespace() {
+            let rest = &line[index + 1..];
+            let end = rest.find(|c: char| !is_tag_char(c)).unwrap_or(rest.len());
+            let candidate = &rest[..end];
+
+            let colour = is_colour(candidate) && in_colour_context(&line[..index]);
+
+            if let Some(tag) = normalize_tag(candidate).filter(|_| !colour) {
+                tags.insert(tag);
+            }
+        }
+
+        previous = c;
+    }
+}
+
+/// Brings the tags that came from a document's content in line with `content`,
+/// tags added by hand are left alone.
+pub async fn sync_content_tags(
+    transaction: &mut Transaction<'_, Postgres>,
+    document_id: uuid::Uuid,
+    content: &str,
+) -> Result<(), StudyBuddyError> {
+    let tags: Vec<String> = extract_tags(content).into_iter().collect();
+
+    sqlx::query!(
+        "DELETE FROM document_tags
+        WHERE document_id = $1 AND from_content AND NOT (tag = ANY($2))",
+        document_id,
+        &tags
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    sqlx::query!(
+        "INSERT INTO document_tags (document_id, tag, from_content)
+        SELECT $1, tag, TRUE FROM unnest($2::text[]) AS tag
+        ON CONFLICT (document_id, tag) DO NOTHING",
+        document_id,
+        &tags
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
+
+#[derive(Serialize, FromRow)]
+pub struct TagCount {
+    tag: String,
+    document_count: i64,
+}
+
+pub async fn fetch_tags(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+) -> Result<Json<Vec<TagCount>>, StudyBuddyError> {
+    let tags = sqlx::query_as::<_, TagCount>(
+        "SELECT tag, count(*) AS document_count
+        FROM document_tags
+        JOIN documents ON documents.document_id = document_tags.document_id
+        WHERE documents.user_id = $1 AND documents.deleted_at IS NULL
+        GROUP BY tag
+        ORDER BY tag",
+    )
+    .bind(ctx.user_id())
+    .fetch_all(&app_state.pool)
+    .await?;
+
+    Ok(Json(tags))
+}
+
+#[derive(Deserialize)]
+pub struct DocumentTag {
+    document_id: uuid::Uuid,
+    tag: String,
+}
+
+/// Tags a document by hand, such a tag stays until it is removed by hand even
+/// if the content never mentions it.
+pub async fn add_tag(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+    Json(request): Json<DocumentTag>,
+) -> Result<Response, StudyBuddyError> {
+    let pool = &app_state.pool;
+    let tag = normalize_tag(&request.tag).ok_or(StudyBuddyError::InvalidTag)?;
+
+    authorize_document_access(pool, &ctx, request.document_id, Access::Editor).await?;
+
+    sqlx::query!(
+        "INSERT INTO document_tags (document_id, tag, from_content)
+        VALUES ($1, $2, FALSE)
+        ON CONFLICT (document_id, tag) DO UPDATE
+        SET from_content = FALSE",
+        request.document_id,
+        tag
+    )
+    .execute(pool)
+    .await?;
+
+    Ok((StatusCode::OK, "Added tag").into_response())
+}
+
+/// Removes a tag from a document. A tag that is still written in the content
+/// comes back the next time the document is saved.
+pub async fn remove_tag(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+    Query(request): Query<DocumentTag>,
+) -> Result<Response, StudyBuddyError> {
+    let pool = &app_state.pool;
+    let tag = normalize_tag(&request.tag).ok_or(StudyBuddyError::InvalidTag)?;
+
+    authorize_document_access(pool, &ctx, request.document_id, Access::Editor).await?;
+
+    sqlx::query!(
+        "DELETE FROM document_tags
+        WHERE document_id = $1 AND tag = $2",
+        request.document_id,
+        tag
+    )
+    .execute(pool)
+    .await?;
+
+    Ok((StatusCode::OK, "Removed tag").into_response())
+}
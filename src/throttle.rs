@@ -0,0 +1,59 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+//Past this many tracked keys idle ones get dropped on the next attempt
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// In memory sliding window limiter, allows at most `limit` attempts per key
+/// within `window`.
+#[derive(Debug)]
+pub struct Throttle {
+    limit: usize,
+    window: Duration,
+    attempts: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl Throttle {
+    pub fn new(limit: usize, window: Duration) -> Self {
+        Throttle {
+            limit,
+            window,
+            attempts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records an attempt for `key`, returns false if the key has already used
+    /// up its attempts for the current window.
+    pub fn attempt(&self, key: &str) -> bool {
+        let now = Instant::now();
+        let mut attempts = self
+            .attempts
+            .lock()
+            .expect("Throttle lock is never poisoned");
+
+        if attempts.len() > PRUNE_THRESHOLD {
+            attempts.retain(|_, times| {
+                times
+                    .back()
+                    .is_some_and(|last| now.duration_since(*last) < self.window)
+            });
+        }
+
+        let times = attempts.entry(key.to_string()).or_default();
+
+        while times
+            .front()
+            .is_some_and(|first| now.duration_since(*first) >= self.window)
+        {
+            times.pop_front();
+        }
+
+        if times.len() >= self.limit {
+            return false;
+        }
+
+        times.push_back(now);
+        true
+    }
+}
@@ -0,0 +1,165 @@
+use crate::authorization::authorize_trashed_document;
+use crate::server::AppState;
+use crate::users::{remove_document, UserCtx};
+use crate::StudyBuddyError;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Serialize, FromRow)]
+pub struct TrashedDocument {
+    document_id: uuid::Uuid,
+    title: String,
+    #[serde(with = "time::serde::rfc3339")]
+    deleted_at: time::OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    purge_at: time::OffsetDateTime,
+}
+
+#[derive(Deserialize)]
+pub struct TrashedDocumentId {
+    document_id: uuid::Uuid,
+}
+
+/// Moves the document to the trash if `user_id` owns it and it isn't there
+/// already, returns whether it did.
+pub async fn trash_document(
+    pool: &PgPool,
+    user_id: uuid::Uuid,
+    document_id: uuid::Uuid,
+) -> Result<bool, StudyBuddyError> {
+    let trashed = sqlx::query!(
+        "UPDATE documents
+        SET deleted_at = now()
+        WHERE document_id = $1 AND user_id = $2 AND deleted_at IS NULL",
+        document_id,
+        user_id
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(trashed > 0)
+}
+
+/// Documents in the caller's trash, most recently deleted first, with the time
+/// each one will be purged at.
+pub async fn fetch_trash(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+) -> Result<Json<Vec<TrashedDocument>>, StudyBuddyError> {
+    let documents = sqlx::query_as::<_, TrashedDocument>(
+        "SELECT document_id, title, deleted_at,
+            deleted_at + $2 * interval '1 second' AS purge_at
+        FROM documents
+        WHERE user_id = $1 AND deleted_at IS NOT NULL
+        ORDER BY deleted_at DESC",
+    )
+    .bind(ctx.user_id())
+    .bind(app_state.config.trash_retention.as_secs_f64())
+    .fetch_all(&app_state.pool)
+    .await?;
+
+    Ok(Json(documents))
+}
+
+pub async fn restore_document(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+    Json(request): Json<TrashedDocumentId>,
+) -> Result<Response, StudyBuddyError> {
+    let pool = &app_state.pool;
+
+    authorize_trashed_document(pool, &ctx, request.document_id).await?;
+
+    sqlx::query!(
+        "UPDATE documents
+        SET deleted_at = NULL
+        WHERE document_id = $1 AND user_id = $2",
+        request.document_id,
+        ctx.user_id()
+    )
+    .execute(pool)
+    .await?;
+
+    info!("Restored document {} from the trash", request.document_id);
+
+    Ok((StatusCode::OK, "Restored document").into_response())
+}
+
+/// Deletes a document in the trash for good, along with its history.
+pub async fn purge_document(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+    Query(request): Query<TrashedDocumentId>,
+) -> Result<Response, StudyBuddyError> {
+    let pool = &app_state.pool;
+
+    authorize_trashed_document(pool, &ctx, request.document_id).await?;
+    remove_document(pool, ctx.user_id(), request.document_id).await?;
+
+    info!("Permanently deleted document {}", request.document_id);
+
+    Ok((StatusCode::OK, "Permanently deleted document").into_response())
+}
+
+pub async fn empty_trash(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+) -> Result<Response, StudyBuddyError> {
+    let purged = sqlx::query!(
+        "DELETE FROM documents
+        WHERE user_id = $1 AND deleted_at IS NOT NULL",
+        ctx.user_id()
+    )
+    .execute(&app_state.pool)
+    .await?
+    .rows_affected();
+
+    info!(
+        "Emptied the trash of user {}, {} documents",
+        ctx.user_id(),
+        purged
+    );
+
+    Ok((StatusCode::OK, "Emptied trash").into_response())
+}
+
+/// Permanently deletes every document that has been in the trash for longer
+/// than `retention`, returns how many were deleted.
+pub async fn purge_trash(pool: &PgPool, retention: Duration) -> Result<u64, StudyBuddyError> {
+    let purged = sqlx::query!(
+        "DELETE FROM documents
+        WHERE deleted_at < now() - $1 * interval '1 second'",
+        retention.as_secs_f64()
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(purged)
+}
+
+/// Purges the trash once an hour for as long as the server runs.
+pub async fn purge_trash_periodically(pool: PgPool, retention: Duration) {
+    let mut interval = tokio::time::interval(PURGE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        match purge_trash(&pool, retention).await {
+            Ok(purged) => info!("Purged {} documents from the trash", purged),
+            Err(err) => warn!("Failed to purge the trash: {:?}", err),
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use crate::revisions::record_revision;
+use crate::tags::{normalize_tag, sync_content_tags};
+use crate::StudyBuddyError;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use std::collections::HashSet;
+use time::OffsetDateTime;
+
+/// Everything stored for a user besides credentials and sessions, in a form
+/// that can be written out and imported again. Revisions, shares and the
+/// trash aren't part of it.
+#[derive(Serialize, Deserialize)]
+pub struct UserExport {
+    pub email: String,
+    /// Parents come before their children.
+    #[serde(default)]
+    pub notebooks: Vec<ExportedNotebook>,
+    pub documents: Vec<ExportedDocument>,
+}
+
+#[derive(Serialize, Deserialize, FromRow)]
+pub struct ExportedNotebook {
+    pub notebook_id: uuid::Uuid,
+    pub parent_id: Option<uuid::Uuid>,
+    pub name: String,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub created_at: Option<OffsetDateTime>,
+}
+
+/// A document with its notebook, tags added by hand and place in the list.
+/// Exports written before these were kept only have the first three fields.
+#[derive(Serialize, Deserialize, FromRow)]
+pub struct ExportedDocument {
+    pub document_id: uuid::Uuid,
+    pub title: String,
+    pub content: String,
+    #[serde(default)]
+    pub notebook_id: Option<uuid::Uuid>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub position: Option<i32>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub created_at: Option<OffsetDateTime>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub updated_at: Option<OffsetDateTime>,
+}
+
+#[derive(Default)]
+pub struct ImportSummary {
+    pub imported: u64,
+    pub imported_notebooks: u64,
+    /// Documents whose id is taken, by an earlier import or another user.
+    pub skipped: Vec<uuid::Uuid>,
+    /// Notebooks whose id another user has, their documents are imported
+    /// without a notebook.
+    pub skipped_notebooks: Vec<uuid::Uuid>,
+}
+
+pub async fn export_user_data(
+    pool: &PgPool,
+    user_id: uuid::Uuid,
+) -> Result<UserExport, StudyBuddyError> {
+    let email = sqlx::query_scalar!("SELECT email FROM users WHERE id = $1", user_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or(StudyBuddyError::NoMatchingUserRecord)?;
+
+    let notebooks = sqlx::query_as::<_, ExportedNotebook>(
+        "WITH RECURSIVE tree AS (
+            SELECT notebook_id, parent_id, name, created_at, 0 AS depth
+            FROM notebooks
+            WHERE user_id = $1 AND parent_id IS NULL
+            UNION ALL
+            SELECT notebooks.notebook_id, notebooks.parent_id, notebooks.name,
+                notebooks.created_at, tree.depth + 1
+            FROM notebooks
+            JOIN tree ON notebooks.parent_id = tree.notebook_id
+        )
+        SELECT notebook_id, parent_id, name, created_at
+        FROM tree
+        ORDER BY depth, name",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    let documents = sqlx::query_as::<_, ExportedDocument>(
+        "SELECT document_id, title, content, notebook_id, pinned, position, created_at,
+            updated_at,
+            ARRAY(
+                SELECT tag FROM document_tags
+                WHERE document_tags.document_id = documents.document_id
+                    AND NOT from_content
+                ORDER BY tag
+            ) AS tags
+        FROM documents
+        WHERE user_id = $1 AND deleted_at IS NULL
+        ORDER BY title, document_id",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(UserExport {
+        email,
+        notebooks,
+        documents,
+    })
+}
+
+/// Gives the notebooks and documents of an export to `user_id`. They keep
+/// their ids so importing the same export twice is harmless, ids that are
+/// taken are skipped and listed in the summary.
+pub async fn import_user_data(
+    pool: &PgPool,
+    user_id: uuid::Uuid,
+    export: &UserExport,
+) -> Result<ImportSummary, StudyBuddyError> {
+    let mut summary = ImportSummary::default();
+
+    let mut transaction = pool.begin().await?;
+
+    //Notebooks of the user the documents can go in
+    let mut notebooks = HashSet::new();
+
+    for notebook in &export.notebooks {
+        let parent_id = notebook.parent_id.filter(|id| notebooks.contains(id));
+
+        let inserted = sqlx::query!(
+            "INSERT INTO notebooks (notebook_id, user_id, parent_id, name, created_at)
+            VALUES ($1, $2, $3, $4, COALESCE($5, now()))
+            ON CONFLICT (notebook_id) DO NOTHING",
+            notebook.notebook_id,
+            user_id,
+            parent_id,
+            notebook.name,
+            notebook.created_at
+        )
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected();
+
+        if inserted == 0 {
+            //Imported into this user before
+            let owned = sqlx::query_scalar!(
+                r#"SELECT EXISTS (
+                    SELECT 1 FROM notebooks WHERE notebook_id = $1 AND user_id = $2
+                ) AS "owned!""#,
+                notebook.notebook_id,
+                user_id
+            )
+            .fetch_one(&mut *transaction)
+            .await?;
+
+            if !owned {
+                summary.skipped_notebooks.push(notebook.notebook_id);
+                continue;
+            }
+        } else {
+            summary.imported_notebooks += 1;
+        }
+
+        notebooks.insert(notebook.notebook_id);
+    }
+
+    for document in &export.documents {
+        let notebook_id = document.notebook_id.filter(|id| notebooks.contains(id));
+
+        let inserted = sqlx::query!(
+            "INSERT INTO documents (user_id, title, content, document_id, notebook_id, pinned,
+                position, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, COALESCE($8, now()), COALESCE($9, now()))
+            ON CONFLICT (document_id) DO NOTHING",
+            user_id,
+            document.title,
+            document.content,
+            document.document_id,
+            notebook_id,
+            document.pinned,
+            document.position,
+            document.created_at,
+            document.updated_at
+        )
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected();
+
+        if inserted == 0 {
+            summary.skipped.push(document.document_id);
+            continue;
+        }
+
+        //Tags added by hand go first so the content doesn't claim them
+        let tags: Vec<String> = document
+            .tags
+            .iter()
+            .filter_map(|tag| normalize_tag(tag))
+            .collect();
+        sqlx::query!(
+            "INSERT INTO document_tags (document_id, tag)
+            SELECT $1, tag FROM unnest($2::text[]) AS tag
+            ON CONFLICT (document_id, tag) DO NOTHING",
+            document.document_id,
+            &tags
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        record_revision(&mut transaction, document.document_id, &document.content).await?;
+        sync_content_tags(&mut transaction, document.document_id, &document.content).await?;
+        summary.imported += 1;
+    }
+
+    transaction.commit().await?;
+
+    Ok(summary)
+}
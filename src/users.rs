@@ -1,4 +1,14 @@
+use crate::authorization::{
+    authorize_document, authorize_document_access, authorize_notebook, Access,
+};
+use crate::revisions::record_revision;
 use crate::server::AppState;
+use crate::sessions::{
+    create_session, revoke_session, session_cookie, touch_session, ClientInfo, SESSION_COOKIE,
+};
+use crate::sharing::{list_shared_documents, SharedDocumentRecord};
+use crate::tags::{normalize_tag, sync_content_tags};
+use crate::trash::trash_document;
 use crate::{StudyBuddyError, StudyBuddySessionError};
 use async_trait::async_trait;
 use axum::{
@@ -14,20 +24,12 @@ use axum::{
 };
 use bcrypt::{hash, verify, DEFAULT_COST};
 use check_if_email_exists::{check_email, CheckEmailInput, Reachable};
-use lettre::{
-    message::{header::ContentType, Mailbox},
-    transport::smtp::authentication::Credentials,
-    Address, AsyncSmtpTransport, AsyncTransport, Message,
-};
 use serde::{Deserialize, Serialize};
-use sqlx::{postgres::PgPool, FromRow};
+use sqlx::{postgres::PgPool, FromRow, PgExecutor, Postgres, QueryBuilder};
+use std::collections::BTreeSet;
 use std::str::FromStr;
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tower_cookies::{
-    cookie::time::{Duration, OffsetDateTime},
-    Cookie, Cookies,
-};
+use tower_cookies::{Cookie, Cookies};
 use tracing::info;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -54,7 +56,6 @@ pub struct User {
     id: uuid::Uuid,
     email: String,
     password: String,
-    session_id: Option<uuid::Uuid>,
 }
 
 #[derive(Serialize, Clone, Debug)]
@@ -83,7 +84,6 @@ impl User {
             id: uuid::Uuid::new_v4(),
             email,
             password,
-            session_id: Some(uuid::Uuid::new_v4()),
         }
     }
 
@@ -126,42 +126,37 @@ impl From<User> for String {
 #[derive(Clone)]
 pub struct UserCtx {
     user_id: uuid::Uuid,
+    session_id: uuid::Uuid,
 }
 
 impl UserCtx {
-    pub fn new(user_id: uuid::Uuid) -> Self {
-        UserCtx { user_id }
+    pub fn new(user_id: uuid::Uuid, session_id: uuid::Uuid) -> Self {
+        UserCtx {
+            user_id,
+            session_id,
+        }
+    }
+
+    pub fn user_id(&self) -> uuid::Uuid {
+        self.user_id
+    }
+
+    pub fn session_id(&self) -> uuid::Uuid {
+        self.session_id
     }
 }
 
 pub async fn mw_user_ctx_resolver<B>(
-    State(app_state): State<Arc<Mutex<AppState>>>,
+    State(app_state): State<Arc<AppState>>,
     cookies: Cookies,
     mut req: Request<B>,
     next: Next<B>,
 ) -> Result<Response, StudyBuddySessionError> {
     info!("Attempting to extract UserCtx");
-    let session_id = cookies.get("session_id").map(|c| c.value().to_string());
-
-    let ctx_result = match session_id.ok_or(StudyBuddySessionError::NoSessionId) {
-        Ok(session_id) => {
-            let query_string = User::create_validate_user_string("session_id");
-            let session_id = uuid::Uuid::from_str(&session_id)
-                .map_err(|_| StudyBuddySessionError::InvalidUserSession)?;
-            let pool = &app_state.lock().await.pool;
-            User::validate_user::<uuid::Uuid>(pool, &query_string, session_id)
-                .await
-                .map_err(|_| StudyBuddySessionError::LookupFailed)?
-                .ok_or(StudyBuddySessionError::InvalidUserSession)
-        }
-
-        Err(error) => Err(error),
-    };
-
-    let ctx_result = ctx_result.map(|user| UserCtx { user_id: user.id });
+    let ctx_result = resolve_user_ctx(&app_state, &cookies).await;
 
     if ctx_result.is_err() && !matches!(ctx_result, Err(StudyBuddySessionError::NoSessionId)) {
-        cookies.remove(Cookie::named("session_id"));
+        cookies.remove(Cookie::named(SESSION_COOKIE));
         info!("Failed to extract UserCtx");
     }
 
@@ -170,6 +165,30 @@ pub async fn mw_user_ctx_resolver<B>(
     Ok(next.run(req).await)
 }
 
+async fn resolve_user_ctx(
+    app_state: &AppState,
+    cookies: &Cookies,
+) -> Result<UserCtx, StudyBuddySessionError> {
+    let session_id = cookies
+        .get(SESSION_COOKIE)
+        .ok_or(StudyBuddySessionError::NoSessionId)?;
+    let session_id = uuid::Uuid::from_str(session_id.value())
+        .map_err(|_| StudyBuddySessionError::InvalidUserSession)?;
+
+    let policy = &app_state.session_policy;
+    let session = touch_session(&app_state.pool, policy, session_id)
+        .await
+        .map_err(|_| StudyBuddySessionError::LookupFailed)?
+        .ok_or(StudyBuddySessionError::InvalidUserSession)?;
+
+    //Slide the expiry of remembered cookies along with the idle timeout
+    if session.remembered {
+        cookies.add(session_cookie(&session, policy));
+    }
+
+    Ok(UserCtx::new(session.user_id, session_id))
+}
+
 #[async_trait]
 impl<S: Send + Sync> FromRequestParts<S> for UserCtx {
     type Rejection = StudyBuddySessionError;
@@ -186,52 +205,86 @@ impl<S: Send + Sync> FromRequestParts<S> for UserCtx {
     }
 }
 
-#[axum_macros::debug_handler]
-pub async fn create_user(
-    cookies: Cookies,
-    State(app_state): State<Arc<Mutex<AppState>>>,
-    Json(user_payload): Json<SentUser>,
-) -> Result<Response, StudyBuddyError> {
-    info!("Creating user with credentials {:?}", user_payload);
-    let email_to_check = CheckEmailInput::new(user_payload.email.clone());
-    let result = check_email(&email_to_check).await;
-
-    if !matches!(result.is_reachable, Reachable::Safe | Reachable::Risky) {
-        info!("Invalid email address : {}", user_payload.email);
-        return Err(StudyBuddyError::InvalidEmailAddress);
-    }
-
-    let pool = &app_state.lock().await.pool;
-
+/// Stores a new user with a hashed password and returns its id.
+pub async fn insert_user(
+    pool: &PgPool,
+    email: &str,
+    password: String,
+) -> Result<uuid::Uuid, StudyBuddyError> {
     let query_string = User::create_validate_user_string("email");
-    if User::validate_user(pool, &query_string, &user_payload.email)
+    if User::validate_user(pool, &query_string, email)
         .await?
         .is_some()
     {
-        info!("email address already in use: {}", user_payload.email);
+        info!("email address already in use: {}", email);
         return Err(StudyBuddyError::EmailAlreadyInUse);
     }
 
-    let new_user = User::new(user_payload.email, user_payload.password);
-    let session_id = new_user
-        .session_id
-        .expect("Newly created user has guaranteed session_id");
-
-    let session_id_string = session_id.to_string().clone();
+    let new_user = User::new(email.to_string(), password);
 
     sqlx::query!(
-        "INSERT INTO users (id, email, password, session_id)
-            VALUES ($1, $2, $3, $4)
+        "INSERT INTO users (id, email, password)
+            VALUES ($1, $2, $3)
         ",
         new_user.id,
         new_user.email,
-        new_user.password,
-        session_id
+        new_user.password
     )
     .execute(pool)
     .await?;
 
-    cookies.add(Cookie::new("session_id", session_id_string));
+    Ok(new_user.id)
+}
+
+pub async fn find_user_id(pool: &PgPool, email: &str) -> Result<uuid::Uuid, StudyBuddyError> {
+    sqlx::query_scalar!("SELECT id FROM users WHERE email = $1", email)
+        .fetch_optional(pool)
+        .await?
+        .ok_or(StudyBuddyError::NoMatchingUserRecord)
+}
+
+pub async fn set_password(
+    executor: impl PgExecutor<'_>,
+    user_id: uuid::Uuid,
+    password: String,
+) -> Result<(), StudyBuddyError> {
+    let hashed = hash(password, DEFAULT_COST).expect("All passwords should hash");
+
+    sqlx::query!(
+        "UPDATE users
+        SET password = $1
+        WHERE id = $2",
+        hashed,
+        user_id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+#[axum_macros::debug_handler]
+pub async fn create_user(
+    cookies: Cookies,
+    client: ClientInfo,
+    State(app_state): State<Arc<AppState>>,
+    Json(user_payload): Json<SentUser>,
+) -> Result<Response, StudyBuddyError> {
+    info!("Creating user with credentials {:?}", user_payload);
+    let email_to_check = CheckEmailInput::new(user_payload.email.clone());
+    let result = check_email(&email_to_check).await;
+
+    if !matches!(result.is_reachable, Reachable::Safe | Reachable::Risky) {
+        info!("Invalid email address : {}", user_payload.email);
+        return Err(StudyBuddyError::InvalidEmailAddress);
+    }
+
+    let pool = &app_state.pool;
+    let user_id = insert_user(pool, &user_payload.email, user_payload.password).await?;
+
+    let policy = &app_state.session_policy;
+    let session = create_session(pool, policy, user_id, &client, false).await?;
+    cookies.add(session_cookie(&session, policy));
 
     Ok((
         StatusCode::CREATED,
@@ -243,14 +296,15 @@ pub async fn create_user(
 #[axum_macros::debug_handler]
 pub async fn log_in(
     cookies: Cookies,
-    State(app_state): State<Arc<Mutex<AppState>>>,
+    client: ClientInfo,
+    State(app_state): State<Arc<AppState>>,
     Json(user_payload): Json<LogInRequest>,
 ) -> Result<Response, StudyBuddyError> {
     info!("Logging in user {:?}", user_payload);
 
-    let pool = &app_state.lock().await.pool;
+    let pool = &app_state.pool;
     let query_string = User::create_validate_user_string("email");
-    let mut user_with_email = User::validate_user(pool, &query_string, &user_payload.email)
+    let user_with_email = User::validate_user(pool, &query_string, &user_payload.email)
         .await?
         .ok_or_else(|| StudyBuddyError::NoMatchingUserRecord)?;
 
@@ -259,53 +313,26 @@ pub async fn log_in(
         return Err(StudyBuddyError::WrongEmailOrPassword);
     }
 
-    let new_session_id = uuid::Uuid::new_v4();
-    user_with_email.session_id = Some(new_session_id);
-
-    sqlx::query!(
-        "UPDATE users
-            SET session_id = $1
-            WHERE id = $2
-        ",
-        new_session_id,
-        user_with_email.id
-    )
-    .execute(pool)
-    .await?;
+    let policy = &app_state.session_policy;
+    let remember = user_payload.wants_to_be_remembered;
+    let session = create_session(pool, policy, user_with_email.id, &client, remember).await?;
 
-    let mut session_cookie = Cookie::new("session_id", new_session_id.to_string());
-
-    if user_payload.wants_to_be_remembered {
-        info!("Added 365 day duration on log in cookie");
-        session_cookie.set_expires(OffsetDateTime::now_utc() + Duration::days(365));
-    }
-
-    cookies.add(session_cookie);
+    cookies.add(session_cookie(&session, policy));
 
     Ok((StatusCode::OK, "Created user and instantied user session").into_response())
 }
 
 pub async fn log_out(
     cookies: Cookies,
-    State(app_state): State<Arc<Mutex<AppState>>>,
+    State(app_state): State<Arc<AppState>>,
     ctx: UserCtx,
 ) -> Result<Response, StudyBuddyError> {
-    //Unique id in this case is the session id to invalidate in the database
     {
-        let pool = &app_state.lock().await.pool;
-
-        sqlx::query!(
-            "UPDATE users
-            SET session_id = NULL
-            WHERE id = $1
-            ",
-            ctx.user_id
-        )
-        .execute(pool)
-        .await?;
+        let pool = &app_state.pool;
+        revoke_session(pool, ctx.user_id, ctx.session_id).await?;
     }
 
-    cookies.remove(Cookie::named("session_id"));
+    cookies.remove(Cookie::named(SESSION_COOKIE));
     info!("Logged out user with id {}", ctx.user_id);
 
     Ok((StatusCode::OK, "Logged out and invalidated user session").into_response())
@@ -314,26 +341,32 @@ pub async fn log_out(
 #[derive(Deserialize)]
 pub struct CreateDocumentRequest {
     title: String,
+    notebook_id: Option<uuid::Uuid>,
 }
 
 pub async fn create_document(
-    State(app_state): State<Arc<Mutex<AppState>>>,
+    State(app_state): State<Arc<AppState>>,
     ctx: UserCtx,
     Json(user_request_info): Json<CreateDocumentRequest>,
 ) -> Result<Json<SentDocument>, StudyBuddyError> {
     let new_document = Document::new(ctx.user_id, user_request_info.title);
 
     {
-        let pool = &app_state.lock().await.pool;
+        let pool = &app_state.pool;
+
+        if let Some(notebook_id) = user_request_info.notebook_id {
+            authorize_notebook(pool, &ctx, notebook_id).await?;
+        }
 
         sqlx::query!(
-            "INSERT INTO documents (user_id, title, content, document_id)
-             VALUES ($1, $2, $3, $4)
+            "INSERT INTO documents (user_id, title, content, document_id, notebook_id)
+             VALUES ($1, $2, $3, $4, $5)
             ",
             ctx.user_id,
             new_document.title,
             new_document.content,
-            new_document.document_id
+            new_document.document_id,
+            user_request_info.notebook_id
         )
         .execute(pool)
         .await?;
@@ -352,247 +385,456 @@ pub async fn create_document(
 
 #[derive(Deserialize, Serialize, FromRow)]
 pub struct DatabaseDocumentRecords {
-    document_id: uuid::Uuid,
-    title: String,
+    pub document_id: uuid::Uuid,
+    pub title: String,
+    pub notebook_id: Option<uuid::Uuid>,
+    pub tags: Vec<String>,
+    pub pinned: bool,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: time::OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub updated_at: time::OffsetDateTime,
+}
+
+/// Whether a document needs every tag of a filter or just one of them.
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TagMatch {
+    #[default]
+    All,
+    Any,
+}
+
+/// Order of a document listing, pinned documents always come first. `Manual`
+/// follows the order set through `/reorder_documents`, documents that were
+/// never placed go after the ones that were.
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum DocumentSort {
+    #[default]
+    Manual,
+    Title,
+    Created,
+    Updated,
+}
+
+impl DocumentSort {
+    fn order_by(self) -> &'static str {
+        match self {
+            DocumentSort::Manual => "position NULLS LAST, created_at",
+            DocumentSort::Title => "lower(title), created_at",
+            DocumentSort::Created => "created_at DESC",
+            DocumentSort::Updated => "updated_at DESC",
+        }
+    }
+}
+
+/// Narrows a document listing down to one notebook, `include_nested` also
+/// keeps the documents of every notebook below it. `tags` is a comma separated
+/// list, documents need all of them or any of them depending on `tag_match`.
+#[derive(Deserialize, Default)]
+pub struct DocumentFilter {
+    pub notebook_id: Option<uuid::Uuid>,
+    #[serde(default)]
+    pub include_nested: bool,
+    pub tags: Option<String>,
+    #[serde(default)]
+    pub tag_match: TagMatch,
+    #[serde(default)]
+    pub sort: DocumentSort,
+}
+
+pub async fn list_documents(
+    pool: &PgPool,
+    user_id: uuid::Uuid,
+    filter: &DocumentFilter,
+) -> Result<Vec<DatabaseDocumentRecords>, StudyBuddyError> {
+    let mut query = QueryBuilder::<Postgres>::new("");
+
+    if let (Some(notebook_id), true) = (filter.notebook_id, filter.include_nested) {
+        query
+            .push(
+                "WITH RECURSIVE subtree AS (
+                SELECT notebook_id FROM notebooks WHERE notebook_id = ",
+            )
+            .push_bind(notebook_id)
+            .push(
+                " UNION ALL
+                SELECT notebooks.notebook_id
+                FROM notebooks
+                JOIN subtree ON notebooks.parent_id = subtree.notebook_id
+            ) ",
+            );
+    }
+
+    query
+        .push(
+            "SELECT title, document_id, notebook_id, pinned, created_at, updated_at,
+            ARRAY(
+                SELECT tag FROM document_tags
+                WHERE document_tags.document_id = documents.document_id
+                ORDER BY tag
+            ) AS tags
+        FROM documents
+        WHERE deleted_at IS NULL AND user_id = ",
+        )
+        .push_bind(user_id);
+
+    match (filter.notebook_id, filter.include_nested) {
+        (Some(_), true) => {
+            query.push(" AND notebook_id IN (SELECT notebook_id FROM subtree)");
+        }
+        (Some(notebook_id), false) => {
+            query.push(" AND notebook_id = ").push_bind(notebook_id);
+        }
+        (None, _) => {}
+    }
+
+    let tags = filter
+        .tags
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .filter(|tag| !tag.trim().is_empty())
+        .map(|tag| normalize_tag(tag).ok_or(StudyBuddyError::InvalidTag))
+        .collect::<Result<BTreeSet<_>, _>>()?;
+
+    if !tags.is_empty() {
+        let tag_count = tags.len() as i64;
+        query
+            .push(
+                " AND document_id IN (
+                SELECT document_id FROM document_tags WHERE tag = ANY(",
+            )
+            .push_bind(tags.into_iter().collect::<Vec<_>>())
+            .push(") GROUP BY document_id");
+
+        if filter.tag_match == TagMatch::All {
+            query.push(" HAVING count(*) = ").push_bind(tag_count);
+        }
+
+        query.push(")");
+    }
+
+    query
+        .push(" ORDER BY pinned DESC, ")
+        .push(filter.sort.order_by());
+
+    Ok(query
+        .build_query_as::<DatabaseDocumentRecords>()
+        .fetch_all(pool)
+        .await?)
+}
+
+/// The user's own documents next to the ones others shared with them, the
+/// filter only applies to the user's own.
+#[derive(Serialize)]
+pub struct DocumentListing {
+    documents: Vec<DatabaseDocumentRecords>,
+    shared_with_me: Vec<SharedDocumentRecord>,
 }
 
 pub async fn fetch_posts(
-    State(app_state): State<Arc<Mutex<AppState>>>,
+    State(app_state): State<Arc<AppState>>,
     ctx: UserCtx,
-) -> Result<Json<Vec<DatabaseDocumentRecords>>, StudyBuddyError> {
-    let pool = &app_state.lock().await.pool;
+    Query(filter): Query<DocumentFilter>,
+) -> Result<Json<DocumentListing>, StudyBuddyError> {
     info!("Fetching posts for user {}", ctx.user_id);
 
-    let user_posts = sqlx::query_as::<_, DatabaseDocumentRecords>(
-        "SELECT title, document_id
-        FROM documents
-        WHERE user_id = $1",
-    )
-    .bind(ctx.user_id)
-    .fetch_all(pool)
-    .await?;
+    let pool = &app_state.pool;
+
+    if let Some(notebook_id) = filter.notebook_id {
+        authorize_notebook(pool, &ctx, notebook_id).await?;
+    }
 
-    Ok(Json(user_posts))
+    Ok(Json(DocumentListing {
+        documents: list_documents(pool, ctx.user_id, &filter).await?,
+        shared_with_me: list_shared_documents(pool, ctx.user_id).await?,
+    }))
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct SavePostRequest {
+#[derive(Deserialize)]
+pub struct RenameDocumentRequest {
     document_id: uuid::Uuid,
-    text: String,
+    title: String,
 }
 
-pub async fn save_document(
-    State(app_state): State<Arc<Mutex<AppState>>>,
-    _ctx: UserCtx,
-    Json(user_save_request): Json<SavePostRequest>,
+pub async fn rename_document(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+    Json(request): Json<RenameDocumentRequest>,
 ) -> Result<Response, StudyBuddyError> {
-    let pool = &app_state.lock().await.pool;
-    info!("Saving document with id {}", user_save_request.document_id);
+    let pool = &app_state.pool;
+    let title = request.title.trim();
+
+    if title.is_empty() {
+        return Err(StudyBuddyError::IncompleteRequest);
+    }
+
+    authorize_document_access(pool, &ctx, request.document_id, Access::Editor).await?;
 
     sqlx::query!(
         "UPDATE documents
-         SET content = $1
-         WHERE document_id = $2",
-        user_save_request.text,
-        user_save_request.document_id
+        SET title = $1, updated_at = now()
+        WHERE document_id = $2",
+        title,
+        request.document_id
     )
     .execute(pool)
     .await?;
 
-    Ok((StatusCode::OK, "Post contents saved succesfully").into_response())
+    Ok((StatusCode::OK, "Renamed document").into_response())
 }
 
 #[derive(Deserialize)]
-pub struct DocumentId {
-    document_id: String,
+pub struct DuplicateDocumentRequest {
+    document_id: uuid::Uuid,
 }
 
-#[derive(Deserialize, Serialize, FromRow)]
-pub struct DocumentContent {
-    content: String,
-}
+/// Copies a document with its content, notebook and tags, the copy starts
+/// with a history of its own. Anyone who can view a document can copy it, the
+/// copy of a shared document belongs to whoever made it and has no notebook.
+pub async fn duplicate_document(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+    Json(request): Json<DuplicateDocumentRequest>,
+) -> Result<Json<SentDocument>, StudyBuddyError> {
+    let pool = &app_state.pool;
 
-pub async fn fetch_post_content(
-    State(app_state): State<Arc<Mutex<AppState>>>,
-    _ctx: UserCtx,
-    Query(document_id): Query<DocumentId>,
-) -> Result<Json<String>, StudyBuddyError> {
-    let doc_id = uuid::Uuid::from_str(&document_id.document_id)
-        .map_err(|_| StudyBuddyError::DocumentNotFound)?;
+    authorize_document_access(pool, &ctx, request.document_id, Access::Viewer).await?;
 
-    let pool = &app_state.lock().await.pool;
+    let copy_id = uuid::Uuid::new_v4();
+    let mut transaction = pool.begin().await?;
 
-    let doc_contents = sqlx::query_as::<_, DocumentContent>(
-        "SELECT content
+    let copy = sqlx::query!(
+        "INSERT INTO documents (document_id, user_id, title, content, notebook_id)
+        SELECT $1, $3, title || ' (copy)', content,
+            CASE WHEN user_id = $3 THEN notebook_id END
         FROM documents
-        WHERE document_id = $1
-        ",
+        WHERE document_id = $2
+        RETURNING title, content",
+        copy_id,
+        request.document_id,
+        ctx.user_id
     )
-    .bind(doc_id)
-    .fetch_optional(pool)
+    .fetch_one(&mut *transaction)
     .await?;
 
-    if let Some(document) = doc_contents {
-        Ok(Json(document.content))
-    } else {
-        Err(StudyBuddyError::DocumentNotFound)
-    }
+    sqlx::query!(
+        "INSERT INTO document_tags (document_id, tag, from_content)
+        SELECT $1, tag, from_content
+        FROM document_tags
+        WHERE document_id = $2",
+        copy_id,
+        request.document_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    record_revision(&mut transaction, copy_id, &copy.content).await?;
+
+    transaction.commit().await?;
+
+    info!("Duplicated document {} as {}", request.document_id, copy_id);
+
+    Ok(Json(SentDocument {
+        unique_id: copy_id,
+        text: copy.title,
+    }))
 }
 
-#[derive(Deserialize, Clone)]
-pub struct Email {
-    email: String,
+#[derive(Deserialize)]
+pub struct PinDocumentRequest {
+    document_id: uuid::Uuid,
+    pinned: bool,
 }
 
-impl Email {
-    fn new(email: &str) -> Self {
-        Email {
-            email: email.to_string(),
-        }
-    }
+pub async fn pin_document(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+    Json(request): Json<PinDocumentRequest>,
+) -> Result<Response, StudyBuddyError> {
+    let pool = &app_state.pool;
 
-    fn into_mailbox(self) -> Result<Mailbox, StudyBuddyError> {
-        let mut email_iterator = self.email.split('@');
+    authorize_document(pool, &ctx, request.document_id).await?;
 
-        let (Some(name), Some(email)) = (email_iterator.next(), email_iterator.next()) else {
-            return Err(StudyBuddyError::InvalidEmailAddress);
-        };
+    sqlx::query!(
+        "UPDATE documents
+        SET pinned = $1
+        WHERE document_id = $2 AND user_id = $3",
+        request.pinned,
+        request.document_id,
+        ctx.user_id
+    )
+    .execute(pool)
+    .await?;
 
-        let address =
-            Address::new(name, email).map_err(|_| StudyBuddyError::InvalidEmailAddress)?;
+    Ok((StatusCode::OK, "Updated pin").into_response())
+}
 
-        Ok(Mailbox::new(None, address))
-    }
+#[derive(Deserialize)]
+pub struct ReorderDocumentsRequest {
+    document_ids: Vec<uuid::Uuid>,
 }
 
-pub async fn send_password_recovery_email(
-    State(app_state): State<Arc<Mutex<AppState>>>,
-    Json(user_email): Json<Email>,
+/// Stores the manual order of the listed documents, first to last. Documents
+/// left out keep the place they had, so a single notebook can be reordered by
+/// sending just its documents.
+pub async fn reorder_documents(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+    Json(request): Json<ReorderDocumentsRequest>,
 ) -> Result<Response, StudyBuddyError> {
-    let pool = &app_state.lock().await.pool;
-
-    let uuid = create_temp_password_in_database(pool, &user_email.email).await?;
-    let recovery_email_address = std::env::var("EMAIL").expect("EMAIL should be set");
-
-    let message = Message::builder()
-        .from(Email::new(&recovery_email_address).into_mailbox()?)
-        .to(user_email.into_mailbox()?)
-        .subject("StudyBuddy recovery email")
-        .header(ContentType::TEXT_HTML)
-        .body(
-            include_str!("../static/html/email_content.html")
-                .to_string()
-                .replace("$recovery_code$", &uuid.to_string()),
-        )
-        .expect("All email fields should be valid");
+    let unique: BTreeSet<_> = request.document_ids.iter().collect();
+    if unique.len() != request.document_ids.len() {
+        return Err(StudyBuddyError::IncompleteRequest);
+    }
 
-    let credentials = Credentials::new(
-        recovery_email_address,
-        std::env::var("EMAIL_APP_PASSWORD").expect("EMAIL_APP_PASSWORD should be set"),
-    );
+    let mut transaction = app_state.pool.begin().await?;
 
-    let mailer = AsyncSmtpTransport::<lettre::Tokio1Executor>::relay("smtp.gmail.com")
-        .unwrap()
-        .credentials(credentials)
-        .build();
+    let reordered = sqlx::query!(
+        "UPDATE documents
+        SET position = ordered.position
+        FROM unnest($1::uuid[]) WITH ORDINALITY AS ordered (document_id, position)
+        WHERE documents.document_id = ordered.document_id AND documents.user_id = $2",
+        &request.document_ids,
+        ctx.user_id
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
 
-    match mailer.send(message).await {
-        Ok(_) => Ok((StatusCode::OK, "Email sent succesfully".to_string()).into_response()),
-        Err(err) => Ok((
-            StatusCode::BAD_REQUEST,
-            format!("Email failed to send {:?}", err),
-        )
-            .into_response()),
+    //Nothing is reordered if any of the documents isn't the user's
+    if reordered != request.document_ids.len() as u64 {
+        return Err(StudyBuddyError::DocumentNotFound);
     }
+
+    transaction.commit().await?;
+
+    Ok((StatusCode::OK, "Reordered documents").into_response())
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavePostRequest {
+    document_id: uuid::Uuid,
+    text: String,
 }
 
-pub async fn create_temp_password_in_database(
+/// Replaces the content of a document the caller was authorized to edit, along
+/// with the revision and tags that follow from it.
+pub async fn store_document_content(
     pool: &PgPool,
-    sender: &String,
-) -> Result<uuid::Uuid, StudyBuddyError> {
-    let query_string = User::create_validate_user_string("email");
+    document_id: uuid::Uuid,
+    text: &str,
+) -> Result<(), StudyBuddyError> {
+    let mut transaction = pool.begin().await?;
 
-    let Some(user_email) = User::validate_user(pool, &query_string, &sender).await? else {
-        return Err(StudyBuddyError::NoMatchingUserRecord);
-    };
+    let updated = sqlx::query!(
+        "UPDATE documents
+         SET content = $1, updated_at = now()
+         WHERE document_id = $2 AND deleted_at IS NULL",
+        text,
+        document_id
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
 
-    let user_email = user_email.email;
+    //Trashed or purged meanwhile
+    if updated == 0 {
+        return Err(StudyBuddyError::DocumentNotFound);
+    }
 
-    let temp_password = uuid::Uuid::new_v4();
+    record_revision(&mut transaction, document_id, text).await?;
+    sync_content_tags(&mut transaction, document_id, text).await?;
 
-    sqlx::query!(
-        "INSERT INTO temporary
-        VALUES ($1, $2)",
-        temp_password,
-        user_email
-    )
-    .execute(pool)
-    .await?;
+    transaction.commit().await?;
 
-    Ok(temp_password)
+    Ok(())
 }
 
-#[derive(Deserialize)]
-pub struct PasswordRecoveryRequest {
-    code: uuid::Uuid,
-    password: String,
+pub async fn save_document(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+    Json(user_save_request): Json<SavePostRequest>,
+) -> Result<Response, StudyBuddyError> {
+    let pool = &app_state.pool;
+    info!("Saving document with id {}", user_save_request.document_id);
+
+    authorize_document_access(pool, &ctx, user_save_request.document_id, Access::Editor).await?;
+
+    //The room would save its own text over this one
+    if app_state.rooms.is_open(user_save_request.document_id) {
+        return Err(StudyBuddyError::DocumentInRoom);
+    }
+
+    store_document_content(pool, user_save_request.document_id, &user_save_request.text).await?;
+
+    Ok((StatusCode::OK, "Post contents saved succesfully").into_response())
 }
 
-#[derive(FromRow)]
-pub struct TemporaryCodeRow {
-    corresponding_email: String,
+#[derive(Deserialize)]
+pub struct DocumentId {
+    document_id: String,
 }
 
-pub async fn try_recovery_code(
-    State(app_state): State<Arc<Mutex<AppState>>>,
-    Json(req): Json<PasswordRecoveryRequest>,
-) -> Result<Response, StudyBuddyError> {
-    let pool = &app_state.lock().await.pool;
-    let email = sqlx::query_as::<_, TemporaryCodeRow>(
-        "SELECT corresponding_email
-        FROM temporary
-        WHERE temp_password = $1",
-    )
-    .bind(req.code)
-    .fetch_optional(pool)
-    .await?;
+#[derive(Deserialize, Serialize, FromRow)]
+pub struct DocumentContent {
+    content: String,
+}
 
-    let Some(valid_email) = email else {
-        return Err(StudyBuddyError::InvalidRecoveryCode);
-    };
+pub async fn fetch_post_content(
+    State(app_state): State<Arc<AppState>>,
+    ctx: UserCtx,
+    Query(document_id): Query<DocumentId>,
+) -> Result<Json<String>, StudyBuddyError> {
+    let doc_id = uuid::Uuid::from_str(&document_id.document_id)
+        .map_err(|_| StudyBuddyError::DocumentNotFound)?;
 
-    let valid_email = valid_email.corresponding_email;
+    let pool = &app_state.pool;
 
-    let hashed = hash(req.password, DEFAULT_COST).expect("All passwords should hash");
+    authorize_document_access(pool, &ctx, doc_id, Access::Viewer).await?;
 
-    sqlx::query!(
-        "
-        UPDATE users
-        SET password = $1
-        WHERE email = $2
+    let doc_contents = sqlx::query_as::<_, DocumentContent>(
+        "SELECT content
+        FROM documents
+        WHERE document_id = $1 AND deleted_at IS NULL
         ",
-        hashed,
-        valid_email
     )
-    .execute(pool)
+    .bind(doc_id)
+    .fetch_optional(pool)
     .await?;
 
-    sqlx::query!(
-        "
-        DELETE FROM temporary
-        WHERE temp_password = $1 
-        ",
-        req.code
+    if let Some(document) = doc_contents {
+        Ok(Json(document.content))
+    } else {
+        Err(StudyBuddyError::DocumentNotFound)
+    }
+}
+
+/// Permanently deletes the document if `user_id` owns it, trashed or not,
+/// returns whether it did.
+pub async fn remove_document(
+    pool: &PgPool,
+    user_id: uuid::Uuid,
+    document_id: uuid::Uuid,
+) -> Result<bool, StudyBuddyError> {
+    let deleted = sqlx::query!(
+        "DELETE FROM documents
+        WHERE document_id = $1 AND user_id = $2",
+        document_id,
+        user_id
     )
     .execute(pool)
-    .await?;
+    .await?
+    .rows_affected();
 
-    Ok((StatusCode::OK, "Successfully reset password").into_response())
+    Ok(deleted > 0)
 }
 
 pub async fn delete_document(
-    _ctx: UserCtx,
-    State(app_state): State<Arc<Mutex<AppState>>>,
+    ctx: UserCtx,
+    State(app_state): State<Arc<AppState>>,
     Query(document_id): Query<DocumentId>,
 ) -> Result<Response, StudyBuddyError> {
     let Ok(id) = uuid::Uuid::from_str(&document_id.document_id) else {
@@ -601,15 +843,12 @@ pub async fn delete_document(
 
     info!("Attempting to delete document {}", &id);
 
-    sqlx::query!(
-        "DELETE FROM documents
-        WHERE document_id = $1",
-        id
-    )
-    .execute(&app_state.lock().await.pool)
-    .await?;
+    let pool = &app_state.pool;
+
+    authorize_document(pool, &ctx, id).await?;
+    trash_document(pool, ctx.user_id, id).await?;
 
-    info!("Successfully deleted document {}", &id);
+    info!("Moved document {} to the trash", &id);
 
-    Ok((StatusCode::OK, "Successfully deleted document").into_response())
+    Ok((StatusCode::OK, "Moved document to the trash").into_response())
 }
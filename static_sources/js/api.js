@@ -247,7 +247,7 @@ export async function fetchUserDocuments() {
       return;
     }
 
-    return await response.json();
+    return (await response.json()).documents;
   } catch (error) {
     open_external_error_modal(null, error);
   }
@@ -283,5 +283,14 @@ function open_external_error_modal(serverResponse, text) {
   errorModal.show();
 
   document.getElementById("error-message").textContent =
-    `Error code : ${serverResponse.status} - ${text}`;
+    `Error code : ${serverResponse.status} - ${errorMessage(text)}`;
+}
+
+//Error responses are JSON envelopes, anything else is shown as is
+function errorMessage(text) {
+  try {
+    return JSON.parse(text).message ?? text;
+  } catch {
+    return text;
+  }
 }
@@ -63,6 +63,7 @@ async function tryRecoveryCode(recoveryCode, password, password_confirm) {
         "Content-type": "application/json",
       },
       body: JSON.stringify({
+        email: document.getElementById("recovery-email-field").value,
         code: recoveryCode,
         password: password,
       }),
@@ -117,13 +118,14 @@ document.addEventListener("DOMContentLoaded", () => {
   codeRecoverySubmitButton.onclick = async () => {
     disableButtonAndShowSpinner(codeRecoverySubmitButton);
     document.getElementById("modal-error").textContent = "";
+    const email = document.getElementById("recovery-email-field").value;
     const code = document.getElementById("code-field").value;
     const password = document.getElementById("password").value;
     const password_confirm = document.getElementById(
       "password-confirmation",
     ).value;
 
-    if (!code || !password || !password_confirm) {
+    if (!email || !code || !password || !password_confirm) {
       document.getElementById("modal-error").textContent =
         "All fields are required to be filled";
       enableButtonAndRemoveSpinner(codeRecoverySubmitButton);
@@ -0,0 +1,138 @@
+mod common;
+
+use common::{insert_document, insert_user};
+use serde_json::Value;
+use sqlx::PgPool;
+use std::path::Path;
+use std::process::Command;
+
+//Runs the admin tool against the database of the test, returns its stdout
+async fn admin(pool: &PgPool, args: &[&str]) -> String {
+    let database: String = sqlx::query_scalar("SELECT current_database()")
+        .fetch_one(pool)
+        .await
+        .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_study_buddy-admin"))
+        .args(args)
+        .env_remove("STUDY_BUDDY_CONFIG")
+        .env(
+            "DATABASE_URL",
+            format!("postgres://postgres@localhost/{database}"),
+        )
+        .env("RUN_MIGRATIONS", "false")
+        .env("PDF_BACKEND", "local")
+        .env("MAIL_BACKEND", "stdout")
+        .output()
+        .unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{args:?} failed: {stderr}");
+
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn read_export(path: &Path) -> Value {
+    serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+}
+
+#[sqlx::test]
+async fn exports_import_back_with_everything_kept(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    insert_user(&pool, "copy@example.com").await;
+
+    let biology = uuid::Uuid::new_v4();
+    let cells = uuid::Uuid::new_v4();
+    for (notebook_id, parent_id, name) in
+        [(biology, None, "Biology"), (cells, Some(biology), "Cells")]
+    {
+        sqlx::query(
+            "INSERT INTO notebooks (notebook_id, user_id, parent_id, name, created_at)
+            VALUES ($1, $2, $3, $4, now() - interval '20 days')",
+        )
+        .bind(notebook_id)
+        .bind(owner.id)
+        .bind(parent_id)
+        .bind(name)
+        .execute(&pool)
+        .await
+        .unwrap();
+    }
+
+    let pinned = insert_document(&pool, &owner, "# Mitochondria #organelles").await;
+    sqlx::query(
+        "UPDATE documents
+        SET title = 'Mitochondria', notebook_id = $2, pinned = true, position = 2,
+            created_at = now() - interval '10 days', updated_at = now() - interval '5 days'
+        WHERE document_id = $1",
+    )
+    .bind(pinned)
+    .bind(cells)
+    .execute(&pool)
+    .await
+    .unwrap();
+    sqlx::query("INSERT INTO document_tags (document_id, tag) VALUES ($1, 'exam')")
+        .bind(pinned)
+        .execute(&pool)
+        .await
+        .unwrap();
+    insert_document(&pool, &owner, "# Loose notes").await;
+
+    let directory = std::env::temp_dir().join(format!("study_buddy-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir(&directory).unwrap();
+    let exported = directory.join("owner.json");
+    let reexported = directory.join("copy.json");
+    let exported_arg = exported.to_str().unwrap();
+
+    admin(
+        &pool,
+        &["export", "owner@example.com", "--output", exported_arg],
+    )
+    .await;
+    let export = read_export(&exported);
+    assert_eq!(export["notebooks"][1]["parent_id"], biology.to_string());
+    let document = &export["documents"][0];
+    assert_eq!(document["document_id"], pinned.to_string());
+    assert_eq!(document["notebook_id"], cells.to_string());
+    assert_eq!(document["tags"], serde_json::json!(["exam"]));
+    assert_eq!(document["pinned"], true);
+    assert_eq!(document["position"], 2);
+
+    //Every id is still the owner's, nothing can be imported and that is said
+    let report = admin(&pool, &["import", "copy@example.com", exported_arg]).await;
+    assert!(report.contains("Imported 0 documents and 0 notebooks"));
+    assert!(report.contains(&format!("Skipped document {pinned}, the id is taken")));
+    assert!(report.contains(&format!("Skipped notebook {cells}")));
+
+    sqlx::query("DELETE FROM users WHERE id = $1")
+        .bind(owner.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let report = admin(&pool, &["import", "copy@example.com", exported_arg]).await;
+    assert!(report.contains("Imported 2 documents and 2 notebooks"));
+    assert!(!report.contains("Skipped"));
+
+    admin(
+        &pool,
+        &[
+            "export",
+            "copy@example.com",
+            "--output",
+            reexported.to_str().unwrap(),
+        ],
+    )
+    .await;
+    let copy = read_export(&reexported);
+    assert_eq!(copy["notebooks"], export["notebooks"]);
+    assert_eq!(copy["documents"], export["documents"]);
+
+    //Importing again skips the documents, the notebooks are the user's own
+    let report = admin(&pool, &["import", "copy@example.com", exported_arg]).await;
+    assert!(report.contains("Imported 0 documents and 0 notebooks"));
+    assert!(report.contains(&format!("Skipped document {pinned}")));
+    assert!(!report.contains("Skipped notebook"));
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
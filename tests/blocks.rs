@@ -0,0 +1,102 @@
+use std::time::Instant;
+use study_buddy::blocks::{split_blocks, BlockPatch, BlockRenderer};
+use study_buddy::parse_markdown;
+
+const NOTES: &str = "# Lecture 4
+
+Intro paragraph
+over two lines.
+
+```rust
+fn main() {
+
+    println!(\"hi\");
+}
+```
+
+- first
+
+- second
+  continued
+
+  still the second item
+
+1. one
+2. two
+
+> quoted
+
+| a | b |
+| - | - |
+| 1 | 2 |
+
+Paragraph before code
+
+    indented code
+
+<!-- a comment
+
+with a blank line -->
+
+Last paragraph
+";
+
+#[test]
+fn blocks_render_like_the_whole_document() {
+    let mut renderer = BlockRenderer::default();
+    renderer.update(NOTES);
+
+    assert_eq!(split_blocks(NOTES).concat(), NOTES);
+    assert_eq!(split_blocks(NOTES).len(), 9);
+    assert_eq!(renderer.html(), parse_markdown(NOTES));
+}
+
+#[test]
+fn only_changed_blocks_are_rendered() {
+    let mut renderer = BlockRenderer::default();
+    renderer.update(NOTES);
+
+    let edited = NOTES.replace("> quoted", "> quoted *again*");
+    let patch = renderer.update(&edited);
+    assert_eq!(
+        patch,
+        BlockPatch {
+            start: 4,
+            delete_count: 1,
+            blocks: vec!["<blockquote>\n<p>quoted <em>again</em></p>\n</blockquote>\n".to_string()],
+        }
+    );
+
+    let inserted = edited.replace("Last paragraph", "New paragraph\n\nLast paragraph");
+    let patch = renderer.update(&inserted);
+    assert_eq!(patch.start, 8);
+    assert_eq!(patch.delete_count, 0);
+    assert_eq!(patch.blocks, ["<p>New paragraph</p>\n"]);
+
+    assert!(renderer.update(&inserted).is_empty());
+    assert_eq!(renderer.html(), parse_markdown(&inserted));
+}
+
+#[test]
+fn reference_definitions_keep_the_document_whole() {
+    let markdown = "See [the notes][notes].\n\nMore text\n\n[notes]: https://example.com\n";
+    let mut renderer = BlockRenderer::default();
+    renderer.update(markdown);
+
+    assert_eq!(split_blocks(markdown), [markdown]);
+    assert_eq!(renderer.html(), parse_markdown(markdown));
+}
+
+#[test]
+fn render_past_its_deadline_keeps_the_last_one() {
+    let mut renderer = BlockRenderer::default();
+    renderer.update("# Before\n");
+
+    let deadline = Instant::now();
+    assert_eq!(renderer.update_until("# After\n\nMore\n", deadline), None);
+    assert_eq!(renderer.html(), "<h1>Before</h1>\n");
+
+    let patch = renderer.update("# After\n\nMore\n");
+    assert_eq!(patch.delete_count, 1);
+    assert_eq!(patch.blocks.len(), 2);
+}
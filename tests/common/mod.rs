@@ -0,0 +1,163 @@
+#![allow(dead_code)]
+
+use axum::{
+    body::{Body, HttpBody},
+    http::{header, Method, Request, StatusCode},
+    Router,
+};
+use sqlx::PgPool;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use study_buddy::collaboration::Rooms;
+use study_buddy::config::{Config, MailBackend, PdfBackend};
+use study_buddy::mail::MemoryMailer;
+use study_buddy::pdf::renderer_from_config;
+use study_buddy::recovery::RecoveryThrottle;
+use study_buddy::server::{self, AppState};
+use study_buddy::sessions::SessionPolicy;
+use tower::ServiceExt;
+
+#[derive(Clone, Copy)]
+pub struct TestUser {
+    pub id: uuid::Uuid,
+    pub session_id: uuid::Uuid,
+}
+
+pub fn app(pool: PgPool) -> Router {
+    app_with_mailer(pool, Arc::new(MemoryMailer::default()))
+}
+
+pub fn app_with_mailer(pool: PgPool, mailer: Arc<MemoryMailer>) -> Router {
+    let config = Config {
+        address: SocketAddr::from(([127, 0, 0, 1], 8080)),
+        trust_forwarded_for: false,
+        rate_limit_per_second: 10,
+        request_timeout: Duration::from_secs(60),
+        database_url: String::new(),
+        database_max_connections: 8,
+        run_migrations: false,
+        trash_retention: Duration::from_secs(30 * 24 * 60 * 60),
+        pdf: PdfBackend::Local,
+        mail: MailBackend::Stdout,
+    };
+
+    server::router(Arc::new(AppState {
+        pdf_renderer: renderer_from_config(&config.pdf),
+        config,
+        pool,
+        mailer,
+        session_policy: SessionPolicy::default(),
+        recovery_throttle: RecoveryThrottle::default(),
+        rooms: Rooms::default(),
+    }))
+}
+
+pub async fn insert_user(pool: &PgPool, email: &str) -> TestUser {
+    let user = TestUser {
+        id: uuid::Uuid::new_v4(),
+        session_id: uuid::Uuid::new_v4(),
+    };
+
+    sqlx::query(
+        "INSERT INTO users (id, email, password)
+        VALUES ($1, $2, $3)",
+    )
+    .bind(user.id)
+    .bind(email)
+    .bind(bcrypt::hash("password123", 4).unwrap())
+    .execute(pool)
+    .await
+    .unwrap();
+
+    sqlx::query(
+        "INSERT INTO sessions (session_id, user_id, expires_at)
+        VALUES ($1, $2, now() + interval '1 day')",
+    )
+    .bind(user.session_id)
+    .bind(user.id)
+    .execute(pool)
+    .await
+    .unwrap();
+
+    user
+}
+
+pub async fn insert_document(pool: &PgPool, owner: &TestUser, content: &str) -> uuid::Uuid {
+    let document_id = uuid::Uuid::new_v4();
+
+    sqlx::query(
+        "INSERT INTO documents (user_id, title, content, document_id)
+        VALUES ($1, $2, $3, $4)",
+    )
+    .bind(owner.id)
+    .bind("Notes")
+    .bind(content)
+    .bind(document_id)
+    .execute(pool)
+    .await
+    .unwrap();
+
+    document_id
+}
+
+pub async fn document_content(pool: &PgPool, document_id: uuid::Uuid) -> Option<String> {
+    sqlx::query_scalar("SELECT content FROM documents WHERE document_id = $1")
+        .bind(document_id)
+        .fetch_optional(pool)
+        .await
+        .unwrap()
+}
+
+pub async fn send(
+    app: &Router,
+    method: Method,
+    uri: &str,
+    user: &TestUser,
+    body: Option<serde_json::Value>,
+) -> StatusCode {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::COOKIE, format!("session_id={}", user.session_id));
+
+    let request = match body {
+        Some(json) => request
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(json.to_string())),
+        None => request.body(Body::empty()),
+    }
+    .unwrap();
+
+    app.clone().oneshot(request).await.unwrap().status()
+}
+
+pub async fn fetch_json(app: &Router, uri: &str, user: &TestUser) -> serde_json::Value {
+    let request = Request::builder()
+        .uri(uri)
+        .header(header::COOKIE, format!("session_id={}", user.session_id))
+        .body(Body::empty())
+        .unwrap();
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let mut body = response.into_body();
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        bytes.extend_from_slice(&chunk.unwrap());
+    }
+
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+pub async fn post_json(app: &Router, uri: &str, body: serde_json::Value) -> StatusCode {
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+
+    app.clone().oneshot(request).await.unwrap().status()
+}
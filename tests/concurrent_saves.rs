@@ -0,0 +1,83 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use common::{app, document_content, insert_document, insert_user, send};
+use futures::future::join_all;
+use serde_json::json;
+use sqlx::PgPool;
+use std::time::Duration;
+
+const SAVES: usize = 32;
+
+#[sqlx::test]
+async fn blocked_save_does_not_hold_up_other_saves(pool: PgPool) {
+    let user = insert_user(&pool, "owner@example.com").await;
+    let locked_document = insert_document(&pool, &user, "# Locked").await;
+    let free_document = insert_document(&pool, &user, "# Free").await;
+    let app = app(pool.clone());
+
+    //Hold a row lock so the save to the locked document can't finish
+    let mut lock = pool.begin().await.unwrap();
+    sqlx::query("SELECT 1 FROM documents WHERE document_id = $1 FOR UPDATE")
+        .bind(locked_document)
+        .execute(&mut *lock)
+        .await
+        .unwrap();
+
+    let body = json!({ "document_id": locked_document, "text": "# Locked, saved" });
+    let blocked_save = send(&app, Method::PUT, "/save", &user, Some(body));
+    tokio::pin!(blocked_save);
+
+    //Drive the save until it is waiting on the row lock, midway through its
+    //transaction
+    let pending = tokio::time::timeout(Duration::from_millis(200), &mut blocked_save).await;
+    assert!(pending.is_err());
+
+    let body = json!({ "document_id": free_document, "text": "# Free, saved" });
+    let status = tokio::time::timeout(
+        Duration::from_secs(5),
+        send(&app, Method::PUT, "/save", &user, Some(body)),
+    )
+    .await
+    .expect("Save to an unlocked document shouldn't wait for the locked one");
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        document_content(&pool, free_document).await.as_deref(),
+        Some("# Free, saved")
+    );
+
+    lock.rollback().await.unwrap();
+
+    assert_eq!(blocked_save.await, StatusCode::OK);
+    assert_eq!(
+        document_content(&pool, locked_document).await.as_deref(),
+        Some("# Locked, saved")
+    );
+}
+
+#[sqlx::test]
+async fn concurrent_saves_all_go_through(pool: PgPool) {
+    let user = insert_user(&pool, "owner@example.com").await;
+    let app = app(pool.clone());
+
+    let mut documents = Vec::with_capacity(SAVES);
+    for _ in 0..SAVES {
+        documents.push(insert_document(&pool, &user, "").await);
+    }
+
+    let saves = documents.iter().enumerate().map(|(index, document_id)| {
+        let body = json!({ "document_id": document_id, "text": format!("# Save {index}") });
+        send(&app, Method::PUT, "/save", &user, Some(body))
+    });
+
+    let statuses = join_all(saves).await;
+    assert!(statuses.iter().all(|status| *status == StatusCode::OK));
+
+    for (index, document_id) in documents.iter().enumerate() {
+        assert_eq!(
+            document_content(&pool, *document_id).await,
+            Some(format!("# Save {index}"))
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::path::Path;
+use study_buddy::config::{Config, ConfigError, MailBackend, PdfBackend};
+
+const MINIMAL: &str = r#"
+[database]
+url = "postgres://localhost/study_buddy"
+
+[pdf]
+backend = "local"
+
+[mail]
+backend = "stdout"
+"#;
+
+fn load(contents: &str, env: &[(&str, &str)]) -> Result<Config, ConfigError> {
+    let env: HashMap<String, String> = env
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+
+    Config::from_sources(Path::new("study_buddy.toml"), contents, |name| {
+        env.get(name).cloned()
+    })
+}
+
+#[test]
+fn environment_overrides_the_file() {
+    let config = load(
+        MINIMAL,
+        &[
+            ("DATABASE_URL", "postgres://db/other"),
+            ("ADDRESS", "127.0.0.1:9000"),
+            ("MAIL_BACKEND", "smtp"),
+            ("EMAIL", "study.buddy@example.com"),
+        ],
+    )
+    .unwrap();
+
+    assert_eq!(config.database_url, "postgres://db/other");
+    assert_eq!(config.address.to_string(), "127.0.0.1:9000");
+    assert!(!config.trust_forwarded_for);
+    assert!(matches!(config.pdf, PdfBackend::Local));
+    assert!(matches!(
+        config.mail,
+        MailBackend::Smtp(smtp) if smtp.sender == "study.buddy@example.com"
+    ));
+
+    let config = load(MINIMAL, &[("PDF_API_KEY", "secret")]).unwrap();
+    assert!(matches!(config.pdf, PdfBackend::Local));
+}
+
+#[test]
+fn missing_backends_fail_instead_of_falling_back() {
+    let contents = r#"
+        [database]
+        url = "postgres://localhost/study_buddy"
+    "#;
+
+    assert!(matches!(
+        load(contents, &[("MAIL_BACKEND", "stdout")]),
+        Err(ConfigError::Missing {
+            key: "pdf.api_key",
+            ..
+        })
+    ));
+    assert!(matches!(
+        load(contents, &[("PDF_BACKEND", "local")]),
+        Err(ConfigError::Missing {
+            key: "mail.sender",
+            ..
+        })
+    ));
+    assert!(matches!(
+        load("", &[("PDF_BACKEND", "local"), ("MAIL_BACKEND", "stdout")]),
+        Err(ConfigError::Missing {
+            key: "database.url",
+            ..
+        })
+    ));
+}
+
+#[test]
+fn invalid_values_are_rejected() {
+    assert!(matches!(
+        load(
+            MINIMAL,
+            &[
+                ("PDF_BACKEND", "pdfendpoint"),
+                ("PDF_API_KEY", "line\nbreak")
+            ]
+        ),
+        Err(ConfigError::Invalid {
+            key: "pdf.api_key",
+            ..
+        })
+    ));
+    assert!(matches!(
+        load(MINIMAL, &[("DATABASE_MAX_CONNECTIONS", "0")]),
+        Err(ConfigError::Invalid {
+            key: "database.max_connections",
+            ..
+        })
+    ));
+    assert!(matches!(
+        load(MINIMAL, &[("RATE_LIMIT_PER_SECOND", "many")]),
+        Err(ConfigError::Invalid {
+            key: "RATE_LIMIT_PER_SECOND",
+            ..
+        })
+    ));
+    assert!(matches!(
+        load(&format!("{MINIMAL}\n[unknown]\n"), &[]),
+        Err(ConfigError::ParseFailed { .. })
+    ));
+}
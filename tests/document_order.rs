@@ -0,0 +1,149 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use common::{app, document_content, insert_document, insert_user, send};
+use serde_json::json;
+use sqlx::PgPool;
+use study_buddy::users::{list_documents, DocumentFilter, DocumentSort};
+
+async fn titles(pool: &PgPool, user_id: uuid::Uuid, sort: DocumentSort) -> Vec<String> {
+    let filter = DocumentFilter {
+        sort,
+        ..Default::default()
+    };
+
+    list_documents(pool, user_id, &filter)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|document| document.title)
+        .collect()
+}
+
+async fn set_title(pool: &PgPool, document_id: uuid::Uuid, title: &str) {
+    sqlx::query("UPDATE documents SET title = $1 WHERE document_id = $2")
+        .bind(title)
+        .bind(document_id)
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+#[sqlx::test]
+async fn rename_and_duplicate(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let document_id = insert_document(&pool, &owner, "# Cells #biology").await;
+    let app = app(pool.clone());
+
+    let body = json!({ "document_id": document_id, "title": "  Cells  " });
+    assert_eq!(
+        send(&app, Method::PUT, "/rename_document", &owner, Some(body)).await,
+        StatusCode::OK
+    );
+
+    let body = json!({ "document_id": document_id, "tag": "exam" });
+    assert_eq!(
+        send(&app, Method::POST, "/add_tag", &owner, Some(body)).await,
+        StatusCode::OK
+    );
+
+    let body = json!({ "document_id": document_id });
+    assert_eq!(
+        send(
+            &app,
+            Method::POST,
+            "/duplicate_document",
+            &owner,
+            Some(body)
+        )
+        .await,
+        StatusCode::OK
+    );
+
+    let documents = list_documents(&pool, owner.id, &DocumentFilter::default())
+        .await
+        .unwrap();
+    let copy = documents
+        .iter()
+        .find(|document| document.document_id != document_id)
+        .unwrap();
+
+    assert_eq!(copy.title, "Cells (copy)");
+    assert_eq!(copy.tags, ["exam"]);
+    assert_eq!(
+        document_content(&pool, copy.document_id).await.as_deref(),
+        Some("# Cells #biology")
+    );
+
+    let body = json!({ "document_id": document_id, "title": " " });
+    assert_eq!(
+        send(&app, Method::PUT, "/rename_document", &owner, Some(body)).await,
+        StatusCode::BAD_REQUEST
+    );
+}
+
+#[sqlx::test]
+async fn pinned_documents_come_first_in_manual_order(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let first = insert_document(&pool, &owner, "").await;
+    let second = insert_document(&pool, &owner, "").await;
+    let third = insert_document(&pool, &owner, "").await;
+    set_title(&pool, first, "A").await;
+    set_title(&pool, second, "B").await;
+    set_title(&pool, third, "C").await;
+    let app = app(pool.clone());
+
+    let body = json!({ "document_ids": [third, first] });
+    assert_eq!(
+        send(&app, Method::PUT, "/reorder_documents", &owner, Some(body)).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        titles(&pool, owner.id, DocumentSort::Manual).await,
+        ["C", "A", "B"]
+    );
+
+    let body = json!({ "document_id": second, "pinned": true });
+    assert_eq!(
+        send(&app, Method::PUT, "/pin_document", &owner, Some(body)).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        titles(&pool, owner.id, DocumentSort::Manual).await,
+        ["B", "C", "A"]
+    );
+    assert_eq!(
+        titles(&pool, owner.id, DocumentSort::Title).await,
+        ["B", "A", "C"]
+    );
+}
+
+#[sqlx::test]
+async fn reorder_rejects_other_users_documents(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let intruder = insert_user(&pool, "intruder@example.com").await;
+    let theirs = insert_document(&pool, &owner, "").await;
+    let mine = insert_document(&pool, &intruder, "").await;
+    let app = app(pool.clone());
+
+    let body = json!({ "document_ids": [mine, theirs] });
+    assert_eq!(
+        send(
+            &app,
+            Method::PUT,
+            "/reorder_documents",
+            &intruder,
+            Some(body)
+        )
+        .await,
+        StatusCode::NOT_FOUND
+    );
+
+    let position: Option<i32> =
+        sqlx::query_scalar("SELECT position FROM documents WHERE document_id = $1")
+            .bind(mine)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(position, None);
+}
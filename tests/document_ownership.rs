@@ -0,0 +1,121 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use common::{app, document_content, insert_document, insert_user, send};
+use serde_json::json;
+use sqlx::PgPool;
+
+#[sqlx::test]
+async fn owner_can_fetch_save_and_delete(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let document_id = insert_document(&pool, &owner, "# Mine").await;
+    let app = app(pool.clone());
+
+    let uri = format!("/fetch_content?document_id={document_id}");
+    assert_eq!(
+        send(&app, Method::GET, &uri, &owner, None).await,
+        StatusCode::OK
+    );
+
+    let body = json!({ "document_id": document_id, "text": "# Still mine" });
+    assert_eq!(
+        send(&app, Method::PUT, "/save", &owner, Some(body)).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        document_content(&pool, document_id).await.as_deref(),
+        Some("# Still mine")
+    );
+
+    let uri = format!("/delete_document?document_id={document_id}");
+    assert_eq!(
+        send(&app, Method::DELETE, &uri, &owner, None).await,
+        StatusCode::OK
+    );
+
+    let uri = format!("/fetch_content?document_id={document_id}");
+    assert_eq!(
+        send(&app, Method::GET, &uri, &owner, None).await,
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[sqlx::test]
+async fn other_user_cannot_fetch(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let intruder = insert_user(&pool, "intruder@example.com").await;
+    let document_id = insert_document(&pool, &owner, "# Secret").await;
+    let app = app(pool);
+
+    let uri = format!("/fetch_content?document_id={document_id}");
+    assert_eq!(
+        send(&app, Method::GET, &uri, &intruder, None).await,
+        StatusCode::FORBIDDEN
+    );
+}
+
+#[sqlx::test]
+async fn other_user_cannot_save(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let intruder = insert_user(&pool, "intruder@example.com").await;
+    let document_id = insert_document(&pool, &owner, "# Secret").await;
+    let app = app(pool.clone());
+
+    let body = json!({ "document_id": document_id, "text": "overwritten" });
+    assert_eq!(
+        send(&app, Method::PUT, "/save", &intruder, Some(body)).await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        document_content(&pool, document_id).await.as_deref(),
+        Some("# Secret")
+    );
+}
+
+#[sqlx::test]
+async fn other_user_cannot_delete(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let intruder = insert_user(&pool, "intruder@example.com").await;
+    let document_id = insert_document(&pool, &owner, "# Secret").await;
+    let app = app(pool.clone());
+
+    let uri = format!("/delete_document?document_id={document_id}");
+    assert_eq!(
+        send(&app, Method::DELETE, &uri, &intruder, None).await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        document_content(&pool, document_id).await.as_deref(),
+        Some("# Secret")
+    );
+}
+
+#[sqlx::test]
+async fn unknown_session_is_rejected(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let document_id = insert_document(&pool, &owner, "# Secret").await;
+    let app = app(pool);
+
+    let stranger = common::TestUser {
+        id: uuid::Uuid::new_v4(),
+        session_id: uuid::Uuid::new_v4(),
+    };
+
+    let uri = format!("/fetch_content?document_id={document_id}");
+    assert_eq!(
+        send(&app, Method::GET, &uri, &stranger, None).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[sqlx::test]
+async fn missing_document_is_not_found(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let app = app(pool);
+
+    let uri = format!("/fetch_content?document_id={}", uuid::Uuid::new_v4());
+    assert_eq!(
+        send(&app, Method::GET, &uri, &owner, None).await,
+        StatusCode::NOT_FOUND
+    );
+}
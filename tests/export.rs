@@ -0,0 +1,125 @@
+mod common;
+
+use axum::{
+    body::{Body, HttpBody},
+    http::{header, Method, Request, StatusCode},
+    Router,
+};
+use common::{app, insert_document, insert_user, TestUser};
+use sqlx::PgPool;
+use std::io::{Cursor, Read};
+use tower::ServiceExt;
+
+async fn download(app: &Router, user: &TestUser, uri: &str) -> zip::ZipArchive<Cursor<Vec<u8>>> {
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri(uri)
+        .header(header::COOKIE, format!("session_id={}", user.session_id))
+        .body(Body::empty())
+        .unwrap();
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "application/zip");
+
+    let mut body = response.into_body();
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        bytes.extend_from_slice(&chunk.unwrap());
+    }
+
+    zip::ZipArchive::new(Cursor::new(bytes)).unwrap()
+}
+
+fn entry(archive: &mut zip::ZipArchive<Cursor<Vec<u8>>>, name: &str) -> String {
+    let mut contents = String::new();
+    archive
+        .by_name(name)
+        .unwrap()
+        .read_to_string(&mut contents)
+        .unwrap();
+    contents
+}
+
+#[sqlx::test]
+async fn exports_documents_into_notebook_folders(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let other = insert_user(&pool, "other@example.com").await;
+    let first = insert_document(
+        &pool,
+        &owner,
+        "---\nauthor: Me\ntitle: Old\nfolder: Exams\n---\n# First",
+    )
+    .await;
+    let second = insert_document(&pool, &owner, "# Second").await;
+    let trashed = insert_document(&pool, &owner, "# Trashed").await;
+    insert_document(&pool, &other, "# Not mine").await;
+
+    let notebook_id = uuid::Uuid::new_v4();
+    sqlx::query("INSERT INTO notebooks (notebook_id, user_id, name) VALUES ($1, $2, 'Biology')")
+        .bind(notebook_id)
+        .bind(owner.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    sqlx::query("UPDATE documents SET notebook_id = $1 WHERE document_id = $2")
+        .bind(notebook_id)
+        .bind(second)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    sqlx::query("UPDATE documents SET deleted_at = now() WHERE document_id = $1")
+        .bind(trashed)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    sqlx::query(
+        "INSERT INTO document_tags (document_id, tag, from_content) VALUES ($1, 'exam', false)",
+    )
+    .bind(first)
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let app = app(pool);
+    let mut archive = download(&app, &owner, "/export_documents").await;
+
+    let mut names: Vec<&str> = archive.file_names().collect();
+    names.sort();
+    assert_eq!(names, ["Biology/Notes.md", "Notes.md"]);
+
+    let markdown = entry(&mut archive, "Notes.md");
+    assert!(markdown.starts_with("---\ntitle: \"Notes\"\n"));
+    assert!(markdown.contains(&format!("id: {first}\n")));
+    assert!(markdown.contains("tags: [exam]\n"));
+    assert!(markdown.contains("author: Me\nfolder: Exams\n"));
+    assert!(!markdown.contains("title: Old"));
+    assert!(markdown.ends_with("---\n\n# First"));
+
+    let markdown = entry(&mut archive, "Biology/Notes.md");
+    assert!(markdown.contains(&format!("id: {second}\n")));
+}
+
+#[sqlx::test]
+async fn exports_rendered_html_and_dedups_titles(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    insert_document(&pool, &owner, "# First").await;
+    insert_document(&pool, &owner, "# Second").await;
+
+    let app = app(pool);
+    let mut archive = download(&app, &owner, "/export_documents?html=true").await;
+
+    let mut names: Vec<&str> = archive.file_names().collect();
+    names.sort();
+    assert_eq!(
+        names,
+        ["Notes (2).html", "Notes (2).md", "Notes.html", "Notes.md"]
+    );
+
+    let html = entry(&mut archive, "Notes.html");
+    assert!(html.contains("<title>Notes</title>"));
+    assert!(html.contains("<h1>"));
+}
@@ -0,0 +1,18 @@
+CREATE TABLE users (
+    id UUID PRIMARY KEY,
+    email TEXT NOT NULL UNIQUE,
+    password TEXT NOT NULL,
+    session_id UUID
+);
+
+CREATE TABLE documents (
+    user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    title TEXT NOT NULL,
+    content TEXT NOT NULL,
+    document_id UUID PRIMARY KEY
+);
+
+CREATE TABLE temporary (
+    temp_password UUID PRIMARY KEY,
+    corresponding_email TEXT NOT NULL
+);
@@ -0,0 +1,33 @@
+use study_buddy::parse_markdown;
+
+#[test]
+fn known_languages_get_highlighting_spans() {
+    let html = parse_markdown("```rust\nfn main() {}\n```\n");
+
+    assert!(html.starts_with("<pre><code class=\"language-rust hl-code\">"));
+    assert!(html.contains("<span class=\"hl-"));
+    assert!(html.contains(">fn</span>"));
+    assert!(html.trim_end().ends_with("</code></pre>"));
+}
+
+#[test]
+fn unknown_languages_stay_plain() {
+    let html = parse_markdown("```nonsense\nif a < b && c\n```\n");
+
+    assert_eq!(
+        html,
+        "<pre><code class=\"language-nonsense\">if a &lt; b &amp;&amp; c\n</code></pre>\n"
+    );
+}
+
+#[test]
+fn highlighted_code_stays_escaped() {
+    let html = parse_markdown("```rust\nlet s = \"<b>\" & x < y;\n```\n");
+
+    assert!(html.contains("hl-code"));
+    assert!(html.contains("&lt;b&gt;"));
+    assert!(html.contains(">&amp;</span>"));
+    assert!(html.contains(">&lt;</span>"));
+    assert!(!html.contains("<b>"));
+    assert!(!html.contains("& "));
+}
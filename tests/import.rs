@@ -0,0 +1,167 @@
+mod common;
+
+use axum::{
+    body::{Body, HttpBody},
+    http::{header, Method, Request, StatusCode},
+    Router,
+};
+use common::{app, insert_user, TestUser};
+use serde_json::Value;
+use sqlx::PgPool;
+use std::io::{Cursor, Write};
+use study_buddy::import::read_archive;
+use study_buddy::users::{list_documents, DocumentFilter};
+use tower::ServiceExt;
+
+const BOUNDARY: &str = "study-buddy-boundary";
+
+fn zip(files: &[(&str, &str)]) -> Vec<u8> {
+    let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+
+    for (path, contents) in files {
+        writer
+            .start_file(*path, zip::write::SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(contents.as_bytes()).unwrap();
+    }
+
+    writer.finish().unwrap().into_inner()
+}
+
+async fn upload(app: &Router, user: &TestUser, files: &[(&str, Vec<u8>)]) -> (StatusCode, Value) {
+    let mut body = Vec::new();
+
+    for (file_name, contents) in files {
+        write!(
+            body,
+            "--{BOUNDARY}\r\n\
+            Content-Disposition: form-data; name=\"files\"; filename=\"{file_name}\"\r\n\
+            Content-Type: application/octet-stream\r\n\r\n"
+        )
+        .unwrap();
+        body.extend_from_slice(contents);
+        body.extend_from_slice(b"\r\n");
+    }
+    write!(body, "--{BOUNDARY}--\r\n").unwrap();
+
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/import_documents")
+        .header(header::COOKIE, format!("session_id={}", user.session_id))
+        .header(
+            header::CONTENT_TYPE,
+            format!("multipart/form-data; boundary={BOUNDARY}"),
+        )
+        .body(Body::from(body))
+        .unwrap();
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let mut response_body = response.into_body();
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response_body.data().await {
+        bytes.extend_from_slice(&chunk.unwrap());
+    }
+
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+#[sqlx::test]
+async fn imports_markdown_files_and_archives(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let app = app(pool.clone());
+
+    let archive = zip(&[
+        ("notes/biology/cells.md", "# Cells\n\n#mitosis"),
+        (
+            "notes/loose.md",
+            "---\ntitle: From front-matter\nfolder: Chemistry/Organic\ntags: [exam]\n---\nText",
+        ),
+        ("notes/image.png", "not markdown"),
+        ("__MACOSX/notes/._cells.md", "resource fork"),
+    ]);
+
+    let (status, report) = upload(
+        &app,
+        &owner,
+        &[
+            ("Plain note.md", b"No heading here".to_vec()),
+            ("notes.zip", archive),
+            ("broken.md", vec![0xff, 0xfe]),
+        ],
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report["imported"], 3);
+    assert_eq!(report["failed"], 1);
+
+    let documents = list_documents(&pool, owner.id, &DocumentFilter::default())
+        .await
+        .unwrap();
+    let mut titles: Vec<&str> = documents
+        .iter()
+        .map(|document| document.title.as_str())
+        .collect();
+    titles.sort();
+    assert_eq!(titles, ["Cells", "From front-matter", "Plain note"]);
+
+    let cells = documents.iter().find(|d| d.title == "Cells").unwrap();
+    assert_eq!(cells.tags, ["mitosis"]);
+
+    let front_matter = documents
+        .iter()
+        .find(|d| d.title == "From front-matter")
+        .unwrap();
+    assert_eq!(front_matter.tags, ["exam"]);
+
+    let notebook_path: Vec<String> = sqlx::query_scalar(
+        "WITH RECURSIVE path AS (
+            SELECT notebook_id, parent_id, name, 0 AS depth FROM notebooks WHERE notebook_id = $1
+            UNION ALL
+            SELECT notebooks.notebook_id, notebooks.parent_id, notebooks.name, path.depth + 1
+            FROM notebooks JOIN path ON notebooks.notebook_id = path.parent_id
+        )
+        SELECT name FROM path ORDER BY depth DESC",
+    )
+    .bind(cells.notebook_id)
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    assert_eq!(notebook_path, ["notes", "biology"]);
+
+    let notebooks: i64 = sqlx::query_scalar("SELECT count(*) FROM notebooks WHERE user_id = $1")
+        .bind(owner.id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(notebooks, 4);
+}
+
+#[sqlx::test]
+async fn invalid_archive_is_reported(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let app = app(pool);
+
+    let (status, report) = upload(&app, &owner, &[("notes.zip", b"not a zip".to_vec())]).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report["imported"], 0);
+    assert_eq!(report["files"][0]["path"], "notes.zip");
+    assert!(report["files"][0]["error"].is_string());
+}
+
+#[test]
+fn archives_stop_expanding_past_the_limit() {
+    let large = "#".repeat(600);
+    let archive = zip(&[("one.md", &large), ("two.md", &large)]);
+
+    assert_eq!(read_archive(&archive, 2_000).unwrap().len(), 2);
+    assert!(read_archive(&archive, 1_000)
+        .err()
+        .unwrap()
+        .contains("more than 1000 bytes"));
+}
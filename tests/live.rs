@@ -0,0 +1,797 @@
+mod common;
+
+use axum::{
+    http::{header, Method, StatusCode},
+    Router,
+};
+use common::send as send_request;
+use common::{app, document_content, insert_document, insert_user, TestUser};
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use std::net::SocketAddr;
+use study_buddy::live::{MAX_DOCUMENT_SIZE, PROTOCOL_V1, PROTOCOL_V2};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{client::IntoClientRequest, handshake::client::Request, Error, Message},
+    MaybeTlsStream, WebSocketStream,
+};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn serve(app: Router) -> SocketAddr {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+
+    tokio::spawn(
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service()),
+    );
+
+    address
+}
+
+//An upgrade request for the socket, legacy clients pass no protocols
+fn upgrade_request(address: SocketAddr, user: Option<&TestUser>, protocols: &str) -> Request {
+    let mut request = format!("ws://{address}/refresh")
+        .into_client_request()
+        .unwrap();
+    let headers = request.headers_mut();
+
+    if !protocols.is_empty() {
+        headers.insert(header::SEC_WEBSOCKET_PROTOCOL, protocols.parse().unwrap());
+    }
+
+    if let Some(user) = user {
+        let cookie = format!("session_id={}", user.session_id);
+        headers.insert(header::COOKIE, cookie.parse().unwrap());
+    }
+
+    request
+}
+
+async fn connect(
+    address: SocketAddr,
+    user: Option<&TestUser>,
+    protocols: &str,
+) -> Result<Socket, Error> {
+    let request = upgrade_request(address, user, protocols);
+    connect_async(request).await.map(|(socket, _)| socket)
+}
+
+fn assert_refused(result: Result<Socket, Error>, status: u16) {
+    match result {
+        Err(Error::Http(response)) => assert_eq!(response.status(), status),
+        other => panic!("Expected the upgrade to be refused, got {other:?}"),
+    }
+}
+
+async fn send(socket: &mut Socket, message: Value) {
+    socket
+        .send(Message::Text(message.to_string()))
+        .await
+        .unwrap();
+}
+
+async fn receive(socket: &mut Socket) -> Value {
+    loop {
+        match socket.next().await.unwrap().unwrap() {
+            Message::Text(text) => return serde_json::from_str(&text).unwrap(),
+            Message::Ping(_) | Message::Pong(_) => continue,
+            other => panic!("Unexpected message {other:?}"),
+        }
+    }
+}
+
+#[sqlx::test]
+async fn legacy_clients_get_raw_renders(pool: PgPool) {
+    let address = serve(app(pool)).await;
+
+    //The preview on `/` works before logging in
+    let mut socket = connect(address, None, "").await.unwrap();
+    socket
+        .send(Message::Text("# Hi".to_string()))
+        .await
+        .unwrap();
+
+    let rendered = socket.next().await.unwrap().unwrap();
+    assert_eq!(rendered, Message::Text("<h1>Hi</h1>".to_string()));
+}
+
+#[sqlx::test]
+async fn protocol_needs_a_session(pool: PgPool) {
+    let address = serve(app(pool)).await;
+
+    assert_refused(connect(address, None, PROTOCOL_V1).await, 401);
+}
+
+#[sqlx::test]
+async fn other_sites_cannot_open_the_socket(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let address = serve(app(pool)).await;
+
+    let connect_from = |origin: &str, user, protocols| {
+        let mut request = upgrade_request(address, user, protocols);
+        request
+            .headers_mut()
+            .insert(header::ORIGIN, origin.parse().unwrap());
+        async { connect_async(request).await.map(|(socket, _)| socket) }
+    };
+
+    for origin in ["https://evil.example.com", "null"] {
+        assert_refused(connect_from(origin, Some(&owner), PROTOCOL_V1).await, 403);
+        assert_refused(connect_from(origin, None, "").await, 403);
+    }
+
+    let origin = format!("http://{address}");
+    connect_from(&origin, Some(&owner), PROTOCOL_V1)
+        .await
+        .unwrap();
+    connect_from(&origin, None, "").await.unwrap();
+}
+
+#[sqlx::test]
+async fn edits_are_rendered_in_order_and_saved(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let document_id = insert_document(&pool, &owner, "# Stored").await;
+    let address = serve(app(pool.clone())).await;
+    let mut socket = connect(address, Some(&owner), PROTOCOL_V1).await.unwrap();
+
+    send(
+        &mut socket,
+        json!({ "type": "open", "document_id": document_id }),
+    )
+    .await;
+
+    let opened = receive(&mut socket).await;
+    assert_eq!(opened["type"], "opened");
+    assert_eq!(opened["content"], "# Stored");
+    assert_eq!(opened["seq"], 0);
+
+    let render = receive(&mut socket).await;
+    assert_eq!(render["type"], "render");
+    assert_eq!(render["html"], "<h1>Stored</h1>");
+
+    let edit = json!({ "type": "edit", "document_id": document_id, "seq": 2, "text": "# New" });
+    send(&mut socket, edit).await;
+
+    let render = receive(&mut socket).await;
+    assert_eq!(render["seq"], 2);
+    assert_eq!(render["html"], "<h1>New</h1>");
+
+    let late = json!({ "type": "edit", "document_id": document_id, "seq": 1, "text": "# Old" });
+    send(&mut socket, late).await;
+
+    let stale = receive(&mut socket).await;
+    assert_eq!(stale["code"], "stale_edit");
+    assert_eq!(stale["seq"], 1);
+
+    send(
+        &mut socket,
+        json!({ "type": "save", "document_id": document_id }),
+    )
+    .await;
+
+    let saved = receive(&mut socket).await;
+    assert_eq!(saved["type"], "saved");
+    assert_eq!(saved["seq"], 2);
+    assert_eq!(
+        document_content(&pool, document_id).await.as_deref(),
+        Some("# New")
+    );
+}
+
+#[sqlx::test]
+async fn documents_are_checked_against_the_session(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let intruder = insert_user(&pool, "intruder@example.com").await;
+    let document_id = insert_document(&pool, &owner, "# Private").await;
+    let address = serve(app(pool.clone())).await;
+    let mut socket = connect(address, Some(&intruder), PROTOCOL_V1)
+        .await
+        .unwrap();
+
+    send(
+        &mut socket,
+        json!({ "type": "open", "document_id": document_id }),
+    )
+    .await;
+    let error = receive(&mut socket).await;
+    assert_eq!(error["type"], "error");
+    assert_eq!(error["code"], "forbidden");
+
+    let edit = json!({ "type": "edit", "document_id": document_id, "seq": 1, "text": "# Mine" });
+    send(&mut socket, edit).await;
+    assert_eq!(receive(&mut socket).await["code"], "document_not_open");
+
+    send(&mut socket, json!({ "type": "rename" })).await;
+    assert_eq!(receive(&mut socket).await["code"], "invalid_message");
+
+    sqlx::query("DELETE FROM sessions WHERE session_id = $1")
+        .bind(intruder.session_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    send(
+        &mut socket,
+        json!({ "type": "open", "document_id": document_id }),
+    )
+    .await;
+    assert_eq!(receive(&mut socket).await["code"], "invalid_session");
+    assert!(socket
+        .next()
+        .await
+        .is_none_or(|message| message.is_err() || matches!(message, Ok(Message::Close(_)))));
+}
+
+#[sqlx::test]
+async fn patches_render_only_changed_blocks(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let document_id = insert_document(&pool, &owner, "# Cells 🧫\n\nMitosis\n\nMeiosis\n").await;
+    let address = serve(app(pool.clone())).await;
+    let protocols = format!("{PROTOCOL_V1}, {PROTOCOL_V2}");
+    let mut socket = connect(address, Some(&owner), &protocols).await.unwrap();
+
+    send(
+        &mut socket,
+        json!({ "type": "open", "document_id": document_id }),
+    )
+    .await;
+    assert_eq!(receive(&mut socket).await["type"], "opened");
+
+    let render = receive(&mut socket).await;
+    assert_eq!(render["type"], "render_blocks");
+    assert_eq!(render["start"], 0);
+    assert_eq!(render["delete_count"], 0);
+    assert_eq!(render["blocks"].as_array().unwrap().len(), 3);
+
+    //"# Cells 🧫\n\n" is 12 UTF-16 code units, the emoji takes two
+    let patch = json!({
+        "type": "patch",
+        "document_id": document_id,
+        "seq": 1,
+        "base_seq": 0,
+        "changes": [{ "start": 12, "end": 19, "text": "Mitosis and cytokinesis" }],
+    });
+    send(&mut socket, patch).await;
+
+    let render = receive(&mut socket).await;
+    assert_eq!(render["type"], "render_blocks");
+    assert_eq!(render["seq"], 1);
+    assert_eq!(render["start"], 1);
+    assert_eq!(render["delete_count"], 1);
+    assert_eq!(
+        render["blocks"],
+        json!(["<p>Mitosis and cytokinesis</p>\n"])
+    );
+
+    let behind = json!({
+        "type": "patch",
+        "document_id": document_id,
+        "seq": 3,
+        "base_seq": 2,
+        "changes": [],
+    });
+    send(&mut socket, behind).await;
+    assert_eq!(receive(&mut socket).await["code"], "out_of_sync");
+
+    let split_emoji = json!({
+        "type": "patch",
+        "document_id": document_id,
+        "seq": 3,
+        "base_seq": 1,
+        "changes": [{ "start": 9, "end": 9, "text": "x" }],
+    });
+    send(&mut socket, split_emoji).await;
+    assert_eq!(receive(&mut socket).await["code"], "invalid_patch");
+
+    send(
+        &mut socket,
+        json!({ "type": "save", "document_id": document_id }),
+    )
+    .await;
+    assert_eq!(receive(&mut socket).await["seq"], 1);
+    assert_eq!(
+        document_content(&pool, document_id).await.as_deref(),
+        Some("# Cells 🧫\n\nMitosis and cytokinesis\n\nMeiosis\n")
+    );
+}
+
+#[sqlx::test]
+async fn edits_during_a_render_are_coalesced(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let document_id = insert_document(&pool, &owner, "# Draft").await;
+    let address = serve(app(pool)).await;
+    let mut socket = connect(address, Some(&owner), PROTOCOL_V1).await.unwrap();
+
+    send(
+        &mut socket,
+        json!({ "type": "open", "document_id": document_id }),
+    )
+    .await;
+    assert_eq!(receive(&mut socket).await["type"], "opened");
+    assert_eq!(receive(&mut socket).await["type"], "render");
+
+    for seq in 1..=50 {
+        let text = format!("# Draft {seq}\n\n```rust\nfn draft() -> u32 {{ {seq} }}\n```\n");
+        let edit = json!({ "type": "edit", "document_id": document_id, "seq": seq, "text": text });
+        send(&mut socket, edit).await;
+    }
+
+    let mut rendered = Vec::new();
+    while rendered.last() != Some(&50) {
+        let render = receive(&mut socket).await;
+        assert_eq!(render["type"], "render");
+        rendered.push(render["seq"].as_u64().unwrap());
+    }
+
+    assert!(rendered.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(rendered.len() <= 50);
+}
+
+#[sqlx::test]
+async fn oversized_documents_are_rejected(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let document_id = insert_document(&pool, &owner, "# Small").await;
+    let address = serve(app(pool)).await;
+    let mut socket = connect(address, Some(&owner), PROTOCOL_V1).await.unwrap();
+
+    send(
+        &mut socket,
+        json!({ "type": "open", "document_id": document_id }),
+    )
+    .await;
+    receive(&mut socket).await;
+    receive(&mut socket).await;
+
+    let large = "a".repeat(MAX_DOCUMENT_SIZE + 1);
+    let edit = json!({ "type": "edit", "document_id": document_id, "seq": 1, "text": large });
+    send(&mut socket, edit).await;
+
+    let rejected = receive(&mut socket).await;
+    assert_eq!(rejected["code"], "document_too_large");
+    assert_eq!(rejected["seq"], 1);
+
+    //Frames past the message limit end the connection before being read whole
+    let huge = "a".repeat(5 * MAX_DOCUMENT_SIZE);
+    let _ = socket.send(Message::Text(huge)).await;
+    assert!(socket
+        .next()
+        .await
+        .is_none_or(|message| message.is_err() || matches!(message, Ok(Message::Close(_)))));
+}
+
+#[sqlx::test]
+async fn legacy_clients_get_the_latest_render(pool: PgPool) {
+    let address = serve(app(pool)).await;
+    let mut socket = connect(address, None, "").await.unwrap();
+
+    for version in 1..=20 {
+        let markdown = format!("# Version {version}");
+        socket.send(Message::Text(markdown)).await.unwrap();
+    }
+
+    loop {
+        let Message::Text(html) = socket.next().await.unwrap().unwrap() else {
+            continue;
+        };
+
+        if html == "<h1>Version 20</h1>" {
+            break;
+        }
+    }
+
+    let large = "a".repeat(MAX_DOCUMENT_SIZE + 1);
+    socket.send(Message::Text(large)).await.unwrap();
+
+    let Message::Text(notice) = socket.next().await.unwrap().unwrap() else {
+        panic!("Expected a notice");
+    };
+    assert!(notice.contains("too large"));
+}
+
+//Renders of joined documents come whenever the render task gets to them
+async fn receive_update(socket: &mut Socket) -> Value {
+    loop {
+        let message = receive(socket).await;
+        if message["type"] != "render" {
+            return message;
+        }
+    }
+}
+
+#[sqlx::test]
+async fn joined_documents_merge_concurrent_operations(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let document_id = insert_document(&pool, &owner, "# Notes\n").await;
+    let address = serve(app(pool.clone())).await;
+    let mut first = connect(address, Some(&owner), PROTOCOL_V1).await.unwrap();
+    let mut second = connect(address, Some(&owner), PROTOCOL_V1).await.unwrap();
+    let join = json!({ "type": "join", "document_id": document_id });
+
+    send(&mut first, join.clone()).await;
+    let joined = receive_update(&mut first).await;
+    assert_eq!(joined["type"], "joined");
+    assert_eq!(joined["content"], "# Notes\n");
+    assert_eq!(joined["version"], 0);
+    assert_eq!(joined["participants"], json!([]));
+
+    send(&mut second, join).await;
+    let joined = receive_update(&mut second).await;
+    assert_eq!(joined["participants"][0]["email"], "owner@example.com");
+    let second_id = joined["connection_id"].clone();
+
+    let presence = receive_update(&mut first).await;
+    assert_eq!(presence["type"], "presence");
+    assert_eq!(presence["participant"]["connection_id"], second_id);
+
+    //Both edit version 0 without having seen the other's operation
+    let append = json!({ "type": "operation", "document_id": document_id, "version": 0, "operation": [8, "Cells\n"] });
+    let prepend = json!({ "type": "operation", "document_id": document_id, "version": 0, "operation": ["Intro\n", 8] });
+    send(&mut first, append).await;
+    send(&mut second, prepend).await;
+
+    for socket in [&mut first, &mut second] {
+        let mut types = Vec::new();
+        for version in 1..=2 {
+            let update = receive_update(socket).await;
+            assert_eq!(update["version"], version);
+            types.push(update["type"].as_str().unwrap().to_string());
+        }
+        types.sort();
+        assert_eq!(types, ["ack", "operation"]);
+    }
+
+    let mut render = receive(&mut first).await;
+    while render["seq"] != 2 {
+        render = receive(&mut first).await;
+    }
+    assert_eq!(
+        render["html"],
+        "<p>Intro</p>\n<h1>Notes</h1>\n<p>Cells</p>\n"
+    );
+
+    let select = json!({
+        "type": "select",
+        "document_id": document_id,
+        "version": 0,
+        "selection": { "anchor": 2, "head": 7 },
+    });
+    send(&mut second, select).await;
+
+    let presence = receive_update(&mut first).await;
+    assert_eq!(presence["type"], "presence");
+    let selection = &presence["participant"]["selection"];
+    //"Notes" as of version 0, moved past the prepended line
+    assert_eq!(selection, &json!({ "anchor": 8, "head": 13 }));
+
+    let stale =
+        json!({ "type": "operation", "document_id": document_id, "version": 3, "operation": [20] });
+    send(&mut second, stale).await;
+    assert_eq!(receive_update(&mut second).await["code"], "out_of_sync");
+
+    send(
+        &mut first,
+        json!({ "type": "save", "document_id": document_id }),
+    )
+    .await;
+    assert_eq!(receive_update(&mut first).await["seq"], 2);
+    assert_eq!(
+        document_content(&pool, document_id).await.as_deref(),
+        Some("Intro\n# Notes\nCells\n")
+    );
+
+    second.close(None).await.unwrap();
+    let left = receive_update(&mut first).await;
+    assert_eq!(left["type"], "left");
+    assert_eq!(left["connection_id"], second_id);
+}
+
+#[sqlx::test]
+async fn rooms_are_saved_once_everyone_leaves(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let intruder = insert_user(&pool, "intruder@example.com").await;
+    let document_id = insert_document(&pool, &owner, "# Draft").await;
+    let address = serve(app(pool.clone())).await;
+    let join = json!({ "type": "join", "document_id": document_id });
+
+    let mut socket = connect(address, Some(&intruder), PROTOCOL_V1)
+        .await
+        .unwrap();
+    send(&mut socket, join.clone()).await;
+    assert_eq!(receive(&mut socket).await["code"], "forbidden");
+
+    let mut socket = connect(address, Some(&owner), PROTOCOL_V1).await.unwrap();
+    send(&mut socket, join).await;
+    assert_eq!(receive_update(&mut socket).await["type"], "joined");
+
+    let edit = json!({ "type": "edit", "document_id": document_id, "seq": 1, "text": "# Mine" });
+    send(&mut socket, edit).await;
+    assert_eq!(receive_update(&mut socket).await["code"], "joined_document");
+
+    let operation = json!({ "type": "operation", "document_id": document_id, "version": 0, "operation": [7, "!"] });
+    send(&mut socket, operation).await;
+    assert_eq!(receive_update(&mut socket).await["type"], "ack");
+    socket.close(None).await.unwrap();
+
+    for _ in 0..50 {
+        if document_content(&pool, document_id).await.as_deref() == Some("# Draft!") {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+
+    panic!("The room wasn't saved after everyone left");
+}
+
+#[sqlx::test]
+async fn rooms_of_deleted_documents_are_closed(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let address = serve(app(pool.clone())).await;
+
+    //Trashed, then purged for good
+    for deletion in [
+        "UPDATE documents SET deleted_at = now() WHERE document_id = $1",
+        "DELETE FROM documents WHERE document_id = $1",
+    ] {
+        let document_id = insert_document(&pool, &owner, "# Gone").await;
+        let mut socket = connect(address, Some(&owner), PROTOCOL_V1).await.unwrap();
+        send(
+            &mut socket,
+            json!({ "type": "join", "document_id": document_id }),
+        )
+        .await;
+        assert_eq!(receive_update(&mut socket).await["type"], "joined");
+
+        sqlx::query(deletion)
+            .bind(document_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let operation = json!({ "type": "operation", "document_id": document_id, "version": 0, "operation": [6, "!"] });
+        send(&mut socket, operation.clone()).await;
+        assert_eq!(receive_update(&mut socket).await["type"], "ack");
+
+        //The autosave finds the document gone
+        let closed = receive_update(&mut socket).await;
+        assert_eq!(closed["code"], "document_not_found");
+
+        send(&mut socket, operation).await;
+        assert_eq!(
+            receive_update(&mut socket).await["code"],
+            "document_not_joined"
+        );
+
+        let revisions: i64 =
+            sqlx::query_scalar("SELECT count(*) FROM document_revisions WHERE document_id = $1")
+                .bind(document_id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(revisions, 0);
+    }
+}
+
+#[sqlx::test]
+async fn opened_documents_cannot_overwrite_a_room(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let document_id = insert_document(&pool, &owner, "# Shared").await;
+    let address = serve(app(pool.clone())).await;
+    let mut joined = connect(address, Some(&owner), PROTOCOL_V1).await.unwrap();
+    let mut opened = connect(address, Some(&owner), PROTOCOL_V1).await.unwrap();
+
+    send(
+        &mut joined,
+        json!({ "type": "join", "document_id": document_id }),
+    )
+    .await;
+    assert_eq!(receive_update(&mut joined).await["type"], "joined");
+
+    send(
+        &mut opened,
+        json!({ "type": "open", "document_id": document_id }),
+    )
+    .await;
+    assert_eq!(receive_update(&mut opened).await["type"], "opened");
+
+    let edit = json!({ "type": "edit", "document_id": document_id, "seq": 1, "text": "# Mine" });
+    send(&mut opened, edit).await;
+    send(
+        &mut opened,
+        json!({ "type": "save", "document_id": document_id }),
+    )
+    .await;
+    assert_eq!(
+        receive_update(&mut opened).await["code"],
+        "document_in_room"
+    );
+    assert_eq!(
+        document_content(&pool, document_id).await.as_deref(),
+        Some("# Shared")
+    );
+
+    joined.close(None).await.unwrap();
+    for _ in 0..50 {
+        send(
+            &mut opened,
+            json!({ "type": "save", "document_id": document_id }),
+        )
+        .await;
+        if receive_update(&mut opened).await["type"] == "saved" {
+            assert_eq!(
+                document_content(&pool, document_id).await.as_deref(),
+                Some("# Mine")
+            );
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+
+    panic!("The document couldn't be saved after the room closed");
+}
+
+#[sqlx::test]
+async fn http_writes_wait_for_the_room_to_close(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let document_id = insert_document(&pool, &owner, "# Draft").await;
+    let app = app(pool.clone());
+    let address = serve(app.clone()).await;
+
+    let save = |text: &str| json!({ "document_id": document_id, "text": text });
+    assert_eq!(
+        send_request(&app, Method::PUT, "/save", &owner, Some(save("# First"))).await,
+        StatusCode::OK
+    );
+    let revision_id: uuid::Uuid =
+        sqlx::query_scalar("SELECT revision_id FROM document_revisions WHERE document_id = $1")
+            .bind(document_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    let restore = json!({ "revision_id": revision_id });
+
+    let mut socket = connect(address, Some(&owner), PROTOCOL_V1).await.unwrap();
+    send(
+        &mut socket,
+        json!({ "type": "join", "document_id": document_id }),
+    )
+    .await;
+    assert_eq!(receive_update(&mut socket).await["type"], "joined");
+    let operation = json!({ "type": "operation", "document_id": document_id, "version": 0, "operation": [7, "!"] });
+    send(&mut socket, operation).await;
+    assert_eq!(receive_update(&mut socket).await["type"], "ack");
+
+    assert_eq!(
+        send_request(&app, Method::PUT, "/save", &owner, Some(save("# Mine"))).await,
+        StatusCode::CONFLICT
+    );
+    assert_eq!(
+        send_request(
+            &app,
+            Method::POST,
+            "/restore_revision",
+            &owner,
+            Some(restore.clone())
+        )
+        .await,
+        StatusCode::CONFLICT
+    );
+
+    //Only the room's own text gets saved
+    send(
+        &mut socket,
+        json!({ "type": "save", "document_id": document_id }),
+    )
+    .await;
+    assert_eq!(receive_update(&mut socket).await["type"], "saved");
+    assert_eq!(
+        document_content(&pool, document_id).await.as_deref(),
+        Some("# First!")
+    );
+
+    socket.close(None).await.unwrap();
+    for _ in 0..50 {
+        let status = send_request(
+            &app,
+            Method::POST,
+            "/restore_revision",
+            &owner,
+            Some(restore.clone()),
+        )
+        .await;
+        if status == StatusCode::OK {
+            assert_eq!(
+                document_content(&pool, document_id).await.as_deref(),
+                Some("# First")
+            );
+            return;
+        }
+        assert_eq!(status, StatusCode::CONFLICT);
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+
+    panic!("The revision couldn't be restored after the room closed");
+}
+
+#[sqlx::test]
+async fn shared_users_edit_with_their_role(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let guest = insert_user(&pool, "guest@example.com").await;
+    let document_id = insert_document(&pool, &owner, "# Group").await;
+    let app = app(pool.clone());
+    let address = serve(app.clone()).await;
+
+    let share = |role: &str| json!({ "document_id": document_id, "email": "guest@example.com", "role": role });
+    send_request(
+        &app,
+        Method::POST,
+        "/share_document",
+        &owner,
+        Some(share("viewer")),
+    )
+    .await;
+
+    let mut socket = connect(address, Some(&guest), PROTOCOL_V1).await.unwrap();
+    send(
+        &mut socket,
+        json!({ "type": "join", "document_id": document_id }),
+    )
+    .await;
+    let joined = receive_update(&mut socket).await;
+    assert_eq!(joined["type"], "joined");
+    assert_eq!(joined["content"], "# Group");
+
+    let operation = |version: u64| json!({ "type": "operation", "document_id": document_id, "version": version, "operation": [7, "!"] });
+    send(&mut socket, operation(0)).await;
+    assert_eq!(receive_update(&mut socket).await["code"], "forbidden");
+
+    send(
+        &mut socket,
+        json!({ "type": "save", "document_id": document_id }),
+    )
+    .await;
+    assert_eq!(receive_update(&mut socket).await["code"], "forbidden");
+
+    //A new role applies to a room the user is already in
+    send_request(
+        &app,
+        Method::POST,
+        "/share_document",
+        &owner,
+        Some(share("editor")),
+    )
+    .await;
+    send(&mut socket, operation(0)).await;
+    assert_eq!(receive_update(&mut socket).await["type"], "ack");
+
+    send(
+        &mut socket,
+        json!({ "type": "save", "document_id": document_id }),
+    )
+    .await;
+    assert_eq!(receive_update(&mut socket).await["type"], "saved");
+    assert_eq!(
+        document_content(&pool, document_id).await.as_deref(),
+        Some("# Group!")
+    );
+
+    let unshare = format!(
+        "/unshare_document?document_id={document_id}&user_id={}",
+        guest.id
+    );
+    send_request(&app, Method::DELETE, &unshare, &owner, None).await;
+
+    let revoked = receive_update(&mut socket).await;
+    assert_eq!(revoked["code"], "forbidden");
+    assert_eq!(revoked["document_id"], json!(document_id));
+
+    send(&mut socket, operation(1)).await;
+    assert_eq!(
+        receive_update(&mut socket).await["code"],
+        "document_not_joined"
+    );
+}
@@ -0,0 +1,80 @@
+use sqlx::{Executor, PgPool};
+use study_buddy::database::run_migrations;
+
+//Loads a schema from before migrations existed along with a user, a document
+//and a recovery code, then migrates it
+async fn migrate_from(pool: &PgPool, schema: &str) -> uuid::Uuid {
+    pool.execute(schema).await.unwrap();
+
+    let user_id = uuid::Uuid::new_v4();
+    sqlx::query("INSERT INTO users (id, email, password) VALUES ($1, 'owner@example.com', 'hash')")
+        .bind(user_id)
+        .execute(pool)
+        .await
+        .unwrap();
+    sqlx::query(
+        "INSERT INTO documents (user_id, title, content, document_id)
+        VALUES ($1, 'Notes', '# Photosynthesis', $2)",
+    )
+    .bind(user_id)
+    .bind(uuid::Uuid::new_v4())
+    .execute(pool)
+    .await
+    .unwrap();
+
+    run_migrations(pool).await.unwrap();
+
+    user_id
+}
+
+async fn column_exists(pool: &PgPool, table: &str, column: &str) -> bool {
+    sqlx::query_scalar(
+        "SELECT EXISTS (
+            SELECT 1 FROM information_schema.columns
+            WHERE table_name = $1 AND column_name = $2
+        )",
+    )
+    .bind(table)
+    .bind(column)
+    .fetch_one(pool)
+    .await
+    .unwrap()
+}
+
+async fn assert_documents_kept(pool: &PgPool, user_id: uuid::Uuid) {
+    let found: Vec<String> = sqlx::query_scalar(
+        "SELECT title FROM documents
+        WHERE user_id = $1 AND deleted_at IS NULL
+            AND search_vector @@ websearch_to_tsquery('english', 'photosynthesis')",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+    .unwrap();
+
+    assert_eq!(found, ["Notes"]);
+}
+
+#[sqlx::test(migrations = false)]
+async fn deployed_schema_is_upgraded(pool: PgPool) {
+    let user_id = migrate_from(&pool, include_str!("fixtures/baseline_schema.sql")).await;
+
+    assert_documents_kept(&pool, user_id).await;
+    assert!(!column_exists(&pool, "users", "session_id").await);
+    assert!(column_exists(&pool, "sessions", "remembered").await);
+
+    let temporary: Option<String> = sqlx::query_scalar("SELECT to_regclass('temporary')::text")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(temporary, None);
+}
+
+#[sqlx::test(migrations = false)]
+async fn test_fixture_schema_is_upgraded(pool: PgPool) {
+    let user_id = migrate_from(&pool, include_str!("fixtures/schema.sql")).await;
+
+    assert_documents_kept(&pool, user_id).await;
+    assert!(column_exists(&pool, "recovery_codes", "failed_attempts").await);
+    assert!(column_exists(&pool, "documents", "notebook_id").await);
+}
@@ -0,0 +1,146 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use common::{app, insert_document, insert_user, send, TestUser};
+use serde_json::json;
+use sqlx::PgPool;
+use study_buddy::users::{list_documents, DocumentFilter};
+
+async fn insert_notebook(
+    pool: &PgPool,
+    owner: &TestUser,
+    parent_id: Option<uuid::Uuid>,
+) -> uuid::Uuid {
+    let notebook_id = uuid::Uuid::new_v4();
+
+    sqlx::query(
+        "INSERT INTO notebooks (notebook_id, user_id, parent_id, name)
+        VALUES ($1, $2, $3, $4)",
+    )
+    .bind(notebook_id)
+    .bind(owner.id)
+    .bind(parent_id)
+    .bind("Notebook")
+    .execute(pool)
+    .await
+    .unwrap();
+
+    notebook_id
+}
+
+async fn file_document(pool: &PgPool, document_id: uuid::Uuid, notebook_id: uuid::Uuid) {
+    sqlx::query("UPDATE documents SET notebook_id = $1 WHERE document_id = $2")
+        .bind(notebook_id)
+        .bind(document_id)
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+#[sqlx::test]
+async fn notebook_cannot_move_into_its_subtree(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let parent = insert_notebook(&pool, &owner, None).await;
+    let child = insert_notebook(&pool, &owner, Some(parent)).await;
+    let app = app(pool.clone());
+
+    for target in [parent, child] {
+        let body = json!({ "notebook_id": parent, "parent_id": target });
+        assert_eq!(
+            send(&app, Method::PUT, "/move_notebook", &owner, Some(body)).await,
+            StatusCode::CONFLICT
+        );
+    }
+
+    let body = json!({ "notebook_id": child, "parent_id": null });
+    assert_eq!(
+        send(&app, Method::PUT, "/move_notebook", &owner, Some(body)).await,
+        StatusCode::OK
+    );
+}
+
+#[sqlx::test]
+async fn other_user_cannot_use_notebook(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let intruder = insert_user(&pool, "intruder@example.com").await;
+    let notebook_id = insert_notebook(&pool, &owner, None).await;
+    let document_id = insert_document(&pool, &intruder, "# Theirs").await;
+    let app = app(pool);
+
+    let body = json!({ "document_id": document_id, "notebook_id": notebook_id });
+    assert_eq!(
+        send(&app, Method::PUT, "/move_document", &intruder, Some(body)).await,
+        StatusCode::FORBIDDEN
+    );
+
+    let uri = format!("/delete_notebook?notebook_id={notebook_id}");
+    assert_eq!(
+        send(&app, Method::DELETE, &uri, &intruder, None).await,
+        StatusCode::FORBIDDEN
+    );
+}
+
+#[sqlx::test]
+async fn deleting_notebook_moves_contents_to_parent(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let parent = insert_notebook(&pool, &owner, None).await;
+    let child = insert_notebook(&pool, &owner, Some(parent)).await;
+    let grandchild = insert_notebook(&pool, &owner, Some(child)).await;
+    let document_id = insert_document(&pool, &owner, "# Filed").await;
+    file_document(&pool, document_id, child).await;
+    let app = app(pool.clone());
+
+    let uri = format!("/delete_notebook?notebook_id={child}");
+    assert_eq!(
+        send(&app, Method::DELETE, &uri, &owner, None).await,
+        StatusCode::OK
+    );
+
+    let document_notebook: Option<uuid::Uuid> =
+        sqlx::query_scalar("SELECT notebook_id FROM documents WHERE document_id = $1")
+            .bind(document_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(document_notebook, Some(parent));
+
+    let grandchild_parent: Option<uuid::Uuid> =
+        sqlx::query_scalar("SELECT parent_id FROM notebooks WHERE notebook_id = $1")
+            .bind(grandchild)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(grandchild_parent, Some(parent));
+}
+
+#[sqlx::test]
+async fn documents_filter_by_notebook(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let parent = insert_notebook(&pool, &owner, None).await;
+    let child = insert_notebook(&pool, &owner, Some(parent)).await;
+    let in_parent = insert_document(&pool, &owner, "# Parent").await;
+    let in_child = insert_document(&pool, &owner, "# Child").await;
+    insert_document(&pool, &owner, "# Loose").await;
+    file_document(&pool, in_parent, parent).await;
+    file_document(&pool, in_child, child).await;
+
+    let count = |notebook_id, include_nested| {
+        let pool = pool.clone();
+        async move {
+            let filter = DocumentFilter {
+                notebook_id,
+                include_nested,
+                ..Default::default()
+            };
+            list_documents(&pool, owner.id, &filter)
+                .await
+                .unwrap()
+                .len()
+        }
+    };
+
+    assert_eq!(count(None, false).await, 3);
+    assert_eq!(count(Some(parent), false).await, 1);
+    assert_eq!(count(Some(parent), true).await, 2);
+    assert_eq!(count(Some(child), true).await, 1);
+}
@@ -0,0 +1,47 @@
+use study_buddy::ot::{transform, Operation};
+
+fn operation(json: &str) -> Operation {
+    serde_json::from_str(json).unwrap()
+}
+
+#[test]
+fn concurrent_operations_converge() {
+    let text = "Photosynthesis 🌱 makes sugar";
+    //Both insert at the start, and both delete part of "makes"
+    let a = operation(r#"["Plant ", 18, -3, "M", 8]"#);
+    let b = operation(r#"["Green ", 15, -8, "uses", 6]"#);
+
+    let (a_prime, b_prime) = transform(&a, &b).unwrap();
+    let a_then_b = b_prime.apply(&a.apply(text).unwrap()).unwrap();
+    let b_then_a = a_prime.apply(&b.apply(text).unwrap()).unwrap();
+
+    assert_eq!(a_then_b, b_then_a);
+    assert!(a_then_b.starts_with("Plant Green "));
+}
+
+#[test]
+fn operations_use_utf16_offsets() {
+    let insert = operation(r#"[2, "!"]"#);
+    assert_eq!(insert.apply("🌱").as_deref(), Some("🌱!"));
+
+    let split = operation(r#"[1, "!", 1]"#);
+    assert_eq!(split.apply("🌱"), None);
+    assert_eq!(insert.apply("🌱🌱"), None);
+
+    assert_eq!(
+        serde_json::to_string(&operation(r#"[1, 1, "a", "b", -1]"#)).unwrap(),
+        r#"[2,"ab",-1]"#
+    );
+    assert!(serde_json::from_str::<Operation>("[0]").is_err());
+}
+
+#[test]
+fn positions_follow_operations() {
+    let operation = operation(r#"[2, "abc", 3, -4, 1]"#);
+
+    assert_eq!(operation.transform_index(1), 1);
+    assert_eq!(operation.transform_index(2), 5);
+    assert_eq!(operation.transform_index(6), 8);
+    assert_eq!(operation.transform_index(9), 8);
+    assert_eq!(operation.transform_index(10), 9);
+}
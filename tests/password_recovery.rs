@@ -0,0 +1,242 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Method, Request, StatusCode},
+    Router,
+};
+use common::{app_with_mailer, insert_user, post_json, send};
+use serde_json::json;
+use sqlx::PgPool;
+use std::sync::Arc;
+use study_buddy::mail::MemoryMailer;
+use tower::ServiceExt;
+
+const EMAIL: &str = "owner@example.com";
+
+fn recovery_code(mailer: &MemoryMailer) -> String {
+    let mail = mailer.last_sent_to(EMAIL).expect("Recovery email was sent");
+    let code_start = mail.html_body.find("<br>").unwrap() + "<br>".len();
+
+    mail.html_body[code_start..code_start + 36].to_string()
+}
+
+//Posts `body` claiming to be forwarded for `forwarded_for`
+async fn post_forwarded(
+    app: &Router,
+    uri: &str,
+    body: serde_json::Value,
+    forwarded_for: &str,
+) -> StatusCode {
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header("content-type", "application/json")
+        .header("x-forwarded-for", forwarded_for)
+        .body(Body::from(body.to_string()))
+        .unwrap();
+
+    app.clone().oneshot(request).await.unwrap().status()
+}
+
+#[sqlx::test]
+async fn recovery_code_resets_password_and_revokes_sessions(pool: PgPool) {
+    let mailer = Arc::new(MemoryMailer::default());
+    let app = app_with_mailer(pool.clone(), mailer.clone());
+    let user = insert_user(&pool, EMAIL).await;
+
+    let status = post_json(&app, "/send_recovery", json!({ "email": EMAIL })).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let code = recovery_code(&mailer);
+    let status = post_json(
+        &app,
+        "/try_recovery_code",
+        json!({ "email": EMAIL, "code": code, "password": "new password" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let password: String = sqlx::query_scalar("SELECT password FROM users WHERE id = $1")
+        .bind(user.id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert!(bcrypt::verify("new password", &password).unwrap());
+
+    let status = send(&app, Method::GET, "/fetch_documents", &user, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[sqlx::test]
+async fn recovery_code_is_single_use(pool: PgPool) {
+    let mailer = Arc::new(MemoryMailer::default());
+    let app = app_with_mailer(pool.clone(), mailer.clone());
+    insert_user(&pool, EMAIL).await;
+
+    post_json(&app, "/send_recovery", json!({ "email": EMAIL })).await;
+    let code = recovery_code(&mailer);
+    let request = json!({ "email": EMAIL, "code": code, "password": "new password" });
+
+    assert_eq!(
+        post_json(&app, "/try_recovery_code", request.clone()).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        post_json(&app, "/try_recovery_code", request).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[sqlx::test]
+async fn new_recovery_code_invalidates_older_one(pool: PgPool) {
+    let mailer = Arc::new(MemoryMailer::default());
+    let app = app_with_mailer(pool.clone(), mailer.clone());
+    insert_user(&pool, EMAIL).await;
+
+    post_json(&app, "/send_recovery", json!({ "email": EMAIL })).await;
+    let old_code = recovery_code(&mailer);
+    post_json(&app, "/send_recovery", json!({ "email": EMAIL })).await;
+    let new_code = recovery_code(&mailer);
+
+    let status = post_json(
+        &app,
+        "/try_recovery_code",
+        json!({ "email": EMAIL, "code": old_code, "password": "new password" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let status = post_json(
+        &app,
+        "/try_recovery_code",
+        json!({ "email": EMAIL, "code": new_code, "password": "new password" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[sqlx::test]
+async fn recovery_emails_are_throttled(pool: PgPool) {
+    let mailer = Arc::new(MemoryMailer::default());
+    let app = app_with_mailer(pool.clone(), mailer.clone());
+    insert_user(&pool, EMAIL).await;
+
+    for _ in 0..3 {
+        let status = post_json(&app, "/send_recovery", json!({ "email": EMAIL })).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let status = post_json(&app, "/send_recovery", json!({ "email": EMAIL })).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(mailer.sent().len(), 3);
+}
+
+#[sqlx::test]
+async fn made_up_forwarded_addresses_dont_escape_throttling(pool: PgPool) {
+    let mailer = Arc::new(MemoryMailer::default());
+    let app = app_with_mailer(pool.clone(), mailer.clone());
+
+    for i in 0..3 {
+        let email = format!("user{i}@example.com");
+        insert_user(&pool, &email).await;
+        let status = post_forwarded(
+            &app,
+            "/send_recovery",
+            json!({ "email": email }),
+            &format!("10.0.0.{i}"),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    insert_user(&pool, EMAIL).await;
+    let status = post_forwarded(
+        &app,
+        "/send_recovery",
+        json!({ "email": EMAIL }),
+        "10.0.0.99",
+    )
+    .await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[sqlx::test]
+async fn recovery_code_attempts_are_throttled(pool: PgPool) {
+    let mailer = Arc::new(MemoryMailer::default());
+    let app = app_with_mailer(pool.clone(), mailer.clone());
+    insert_user(&pool, EMAIL).await;
+
+    for i in 0..10 {
+        let request = json!({
+            "email": EMAIL,
+            "code": uuid::Uuid::new_v4(),
+            "password": "new password",
+        });
+        let status =
+            post_forwarded(&app, "/try_recovery_code", request, &format!("10.0.0.{i}")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    let request = json!({
+        "email": EMAIL,
+        "code": uuid::Uuid::new_v4(),
+        "password": "new password",
+    });
+    assert_eq!(
+        post_json(&app, "/try_recovery_code", request).await,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+}
+
+#[sqlx::test]
+async fn wrong_codes_lock_out_the_code(pool: PgPool) {
+    let mailer = Arc::new(MemoryMailer::default());
+    let app = app_with_mailer(pool.clone(), mailer.clone());
+    insert_user(&pool, EMAIL).await;
+
+    post_json(&app, "/send_recovery", json!({ "email": EMAIL })).await;
+    let code = recovery_code(&mailer);
+
+    for _ in 0..5 {
+        let request = json!({
+            "email": EMAIL,
+            "code": uuid::Uuid::new_v4(),
+            "password": "new password",
+        });
+        assert_eq!(
+            post_json(&app, "/try_recovery_code", request).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    let request = json!({ "email": EMAIL, "code": code, "password": "new password" });
+    assert_eq!(
+        post_json(&app, "/try_recovery_code", request).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[sqlx::test]
+async fn expired_recovery_code_is_rejected(pool: PgPool) {
+    let mailer = Arc::new(MemoryMailer::default());
+    let app = app_with_mailer(pool.clone(), mailer.clone());
+    let user = insert_user(&pool, EMAIL).await;
+
+    post_json(&app, "/send_recovery", json!({ "email": EMAIL })).await;
+    let code = recovery_code(&mailer);
+
+    sqlx::query("UPDATE recovery_codes SET expires_at = now() - interval '1 minute'")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let request = json!({ "email": EMAIL, "code": code, "password": "new password" });
+    assert_eq!(
+        post_json(&app, "/try_recovery_code", request).await,
+        StatusCode::UNAUTHORIZED
+    );
+
+    let status = send(&app, Method::GET, "/fetch_documents", &user, None).await;
+    assert_eq!(status, StatusCode::OK);
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use axum::{
+    body::{Body, HttpBody},
+    http::{header, Method, Request, StatusCode},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use common::app;
+use serde_json::json;
+use sqlx::PgPool;
+use study_buddy::parse_markdown;
+use study_buddy::pdf::{LocalPdfRenderer, PdfRenderer, PdfStyle, RenderedPdf};
+use tower::ServiceExt;
+
+const NOTES: &str = "# Cell biology
+
+## Organelles
+
+- Nucleus
+- Mitochondria
+  1. Matrix
+  2. Cristae
+
+```rust
+fn main() {
+    println!(\"ATP\");
+}
+```
+
+| Organelle | Role |
+| --------- | ---- |
+| Ribosome  | Proteins |
+";
+
+fn assert_is_pdf(pdf: &[u8]) {
+    assert!(pdf.starts_with(b"%PDF-"));
+
+    let document = lopdf::Document::load_mem(pdf).unwrap();
+    let pages: Vec<u32> = document.get_pages().into_keys().collect();
+    let text = document.extract_text(&pages).unwrap();
+
+    for expected in ["Cell biology", "Mitochondria", "println", "Ribosome"] {
+        assert!(
+            text.contains(expected),
+            "{expected} is missing from {text:?}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn local_renderer_writes_a_valid_pdf() {
+    for style in [PdfStyle::Light, PdfStyle::Dark] {
+        let rendered = LocalPdfRenderer
+            .render(&parse_markdown(NOTES), style)
+            .await
+            .unwrap();
+
+        let RenderedPdf::Bytes(pdf) = rendered else {
+            panic!("The local renderer returns the file itself");
+        };
+        assert_is_pdf(&pdf);
+    }
+}
+
+#[sqlx::test]
+async fn downloads_are_sent_inline(pool: PgPool) {
+    let app = app(pool);
+    let body = json!({ "html": parse_markdown(NOTES), "css": "light" });
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/download")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+
+    let mut response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.body_mut().data().await {
+        bytes.extend_from_slice(&chunk.unwrap());
+    }
+    let response: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(response["success"], true);
+
+    let url = response["data"]["url"].as_str().unwrap();
+    let encoded = url
+        .strip_prefix("data:application/pdf;base64,")
+        .expect("A data url");
+    assert_is_pdf(&STANDARD.decode(encoded).unwrap());
+}
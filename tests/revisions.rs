@@ -0,0 +1,104 @@
+mod common;
+
+use common::{insert_document, insert_user};
+use sqlx::PgPool;
+use study_buddy::revisions::{prune_revisions, RetentionPolicy};
+
+//Stores a revision created at `created_at`, an SQL expression relative to now
+async fn insert_revision(pool: &PgPool, document_id: uuid::Uuid, created_at: &str) -> uuid::Uuid {
+    let revision_id = uuid::Uuid::new_v4();
+
+    sqlx::query(&format!(
+        "INSERT INTO document_revisions (revision_id, document_id, content, created_at)
+        VALUES ($1, $2, '', {created_at})"
+    ))
+    .bind(revision_id)
+    .bind(document_id)
+    .execute(pool)
+    .await
+    .unwrap();
+
+    revision_id
+}
+
+async fn remaining(pool: &PgPool, document_id: uuid::Uuid) -> Vec<uuid::Uuid> {
+    sqlx::query_scalar(
+        "SELECT revision_id FROM document_revisions
+        WHERE document_id = $1
+        ORDER BY created_at DESC",
+    )
+    .bind(document_id)
+    .fetch_all(pool)
+    .await
+    .unwrap()
+}
+
+#[sqlx::test]
+async fn revisions_thin_out_with_age(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let document_id = insert_document(&pool, &owner, "# Notes").await;
+
+    //Every revision of the last day is kept
+    let recent = insert_revision(&pool, document_id, "now() - interval '1 hour'").await;
+    let earlier = insert_revision(&pool, document_id, "now() - interval '2 hours'").await;
+
+    //Two days ago only the newest of each hour is kept
+    let hour = "date_trunc('hour', now()) - interval '48 hours'";
+    let hour_newest = insert_revision(
+        &pool,
+        document_id,
+        &format!("{hour} + interval '20 minutes'"),
+    )
+    .await;
+    insert_revision(
+        &pool,
+        document_id,
+        &format!("{hour} + interval '10 minutes'"),
+    )
+    .await;
+    let hour_before = insert_revision(
+        &pool,
+        document_id,
+        &format!("{hour} - interval '30 minutes'"),
+    )
+    .await;
+
+    //Forty days ago only the newest of each day is kept
+    let day = "date_trunc('day', now()) - interval '40 days'";
+    let day_newest =
+        insert_revision(&pool, document_id, &format!("{day} + interval '2 hours'")).await;
+    insert_revision(&pool, document_id, &format!("{day} + interval '1 hour'")).await;
+    let day_before =
+        insert_revision(&pool, document_id, &format!("{day} - interval '1 hour'")).await;
+
+    let pruned = prune_revisions(&pool, &RetentionPolicy::default())
+        .await
+        .unwrap();
+
+    assert_eq!(pruned, 2);
+    assert_eq!(
+        remaining(&pool, document_id).await,
+        [
+            recent,
+            earlier,
+            hour_newest,
+            hour_before,
+            day_newest,
+            day_before
+        ]
+    );
+}
+
+#[sqlx::test]
+async fn latest_revision_survives(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let document_id = insert_document(&pool, &owner, "# Old").await;
+    let only = insert_revision(&pool, document_id, "now() - interval '400 days'").await;
+
+    let policy = RetentionPolicy {
+        keep_all: std::time::Duration::ZERO,
+        keep_hourly: std::time::Duration::ZERO,
+    };
+    assert_eq!(prune_revisions(&pool, &policy).await.unwrap(), 0);
+    assert_eq!(remaining(&pool, document_id).await, [only]);
+}
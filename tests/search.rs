@@ -0,0 +1,56 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use common::{app, fetch_json, insert_document, insert_user, send};
+use sqlx::PgPool;
+
+#[sqlx::test]
+async fn snippets_escape_content_and_mark_matches(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let document_id = insert_document(
+        &pool,
+        &owner,
+        "Photosynthesis needs 1 < 2 > 0 & \"light\" <img src=x onerror=alert('x')",
+    )
+    .await;
+    let app = app(pool);
+
+    let results = fetch_json(&app, "/search?query=photosynthesis", &owner).await;
+    let results = results.as_array().unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0]["document_id"], document_id.to_string());
+
+    let snippet = results[0]["snippet"].as_str().unwrap();
+    assert!(snippet.contains("<mark>Photosynthesis</mark>"));
+    assert!(snippet.contains("1 &lt; 2 &gt; 0 &amp; &quot;light&quot;"));
+    assert!(snippet.contains("&lt;img src=x"));
+    assert!(!snippet.contains("<img"));
+}
+
+#[sqlx::test]
+async fn search_skips_other_users_and_trash(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let other = insert_user(&pool, "other@example.com").await;
+    let kept = insert_document(&pool, &owner, "Mitochondria make energy").await;
+    let trashed = insert_document(&pool, &owner, "Mitochondria in the trash").await;
+    insert_document(&pool, &other, "Mitochondria of someone else").await;
+    let app = app(pool);
+
+    let uri = format!("/delete_document?document_id={trashed}");
+    assert_eq!(
+        send(&app, Method::DELETE, &uri, &owner, None).await,
+        StatusCode::OK
+    );
+
+    let results = fetch_json(&app, "/search?query=mitochondria", &owner).await;
+    let found: Vec<&str> = results
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|result| result["document_id"].as_str().unwrap())
+        .collect();
+    assert_eq!(found, [kept.to_string()]);
+
+    let results = fetch_json(&app, "/search?query=mitochondria", &other).await;
+    assert_eq!(results.as_array().unwrap().len(), 1);
+}
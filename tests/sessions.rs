@@ -0,0 +1,256 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Method, Request, StatusCode},
+    Router,
+};
+use common::{app, fetch_json, insert_user, send, TestUser};
+use sqlx::PgPool;
+use study_buddy::sessions::{prune_sessions, SessionPolicy};
+use tower::ServiceExt;
+
+//Another session of the same user, last used `idle` ago as an SQL interval
+async fn insert_session(pool: &PgPool, user: &TestUser, idle: &str) -> TestUser {
+    let session = TestUser {
+        id: user.id,
+        session_id: uuid::Uuid::new_v4(),
+    };
+
+    sqlx::query(&format!(
+        "INSERT INTO sessions (session_id, user_id, last_seen_at, expires_at, user_agent)
+        VALUES ($1, $2, now() - interval '{idle}', now() + interval '1 day', 'Firefox')"
+    ))
+    .bind(session.session_id)
+    .bind(session.id)
+    .execute(pool)
+    .await
+    .unwrap();
+
+    session
+}
+
+async fn update_session(pool: &PgPool, session: &TestUser, set: &str) {
+    sqlx::query(&format!("UPDATE sessions SET {set} WHERE session_id = $1"))
+        .bind(session.session_id)
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+async fn is_valid(app: &Router, session: &TestUser) -> bool {
+    match send(app, Method::GET, "/fetch_sessions", session, None).await {
+        StatusCode::OK => true,
+        StatusCode::UNAUTHORIZED => false,
+        other => panic!("Unexpected status {other}"),
+    }
+}
+
+#[sqlx::test]
+async fn sessions_are_listed_with_the_current_one_marked(pool: PgPool) {
+    let user = insert_user(&pool, "user@example.com").await;
+    let other = insert_session(&pool, &user, "10 minutes").await;
+    let app = app(pool);
+
+    let sessions = fetch_json(&app, "/fetch_sessions", &user).await;
+    let sessions: Vec<(String, bool)> = sessions
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|session| {
+            (
+                session["session_id"].as_str().unwrap().to_string(),
+                session["current"].as_bool().unwrap(),
+            )
+        })
+        .collect();
+
+    assert_eq!(
+        sessions,
+        [
+            (user.session_id.to_string(), true),
+            (other.session_id.to_string(), false),
+        ]
+    );
+}
+
+#[sqlx::test]
+async fn revoked_sessions_stop_working(pool: PgPool) {
+    let user = insert_user(&pool, "user@example.com").await;
+    let other = insert_session(&pool, &user, "10 minutes").await;
+    let app = app(pool);
+
+    let revoke = format!("/revoke_session?session_id={}", other.session_id);
+    assert_eq!(
+        send(&app, Method::DELETE, &revoke, &user, None).await,
+        StatusCode::OK
+    );
+    assert!(!is_valid(&app, &other).await);
+    assert!(is_valid(&app, &user).await);
+
+    assert_eq!(
+        send(&app, Method::DELETE, &revoke, &user, None).await,
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[sqlx::test]
+async fn revoking_all_can_keep_the_current_session(pool: PgPool) {
+    let user = insert_user(&pool, "user@example.com").await;
+    let laptop = insert_session(&pool, &user, "10 minutes").await;
+    let phone = insert_session(&pool, &user, "20 minutes").await;
+    let app = app(pool);
+
+    assert_eq!(
+        send(
+            &app,
+            Method::DELETE,
+            "/revoke_all_sessions?keep_current=true",
+            &user,
+            None
+        )
+        .await,
+        StatusCode::OK
+    );
+    assert!(is_valid(&app, &user).await);
+    assert!(!is_valid(&app, &laptop).await);
+    assert!(!is_valid(&app, &phone).await);
+
+    assert_eq!(
+        send(&app, Method::DELETE, "/revoke_all_sessions", &user, None).await,
+        StatusCode::OK
+    );
+    assert!(!is_valid(&app, &user).await);
+}
+
+#[sqlx::test]
+async fn sessions_of_other_users_cannot_be_revoked(pool: PgPool) {
+    let user = insert_user(&pool, "user@example.com").await;
+    let victim = insert_user(&pool, "victim@example.com").await;
+    let app = app(pool);
+
+    let revoke = format!("/revoke_session?session_id={}", victim.session_id);
+    assert_eq!(
+        send(&app, Method::DELETE, &revoke, &user, None).await,
+        StatusCode::NOT_FOUND
+    );
+    assert!(is_valid(&app, &victim).await);
+    assert!(is_valid(&app, &user).await);
+
+    let sessions = fetch_json(&app, "/fetch_sessions", &user).await;
+    assert_eq!(sessions.as_array().unwrap().len(), 1);
+}
+
+#[sqlx::test]
+async fn idle_and_expired_sessions_are_refused(pool: PgPool) {
+    let user = insert_user(&pool, "user@example.com").await;
+    let recent = insert_session(&pool, &user, "1 hour").await;
+    let idle = insert_session(&pool, &user, "3 hours").await;
+
+    let expired = insert_session(&pool, &user, "1 minute").await;
+    update_session(
+        &pool,
+        &expired,
+        "created_at = now() - interval '2 days', expires_at = now() - interval '1 minute'",
+    )
+    .await;
+
+    //Remembered sessions may stay unused for longer
+    let remembered = insert_session(&pool, &user, "3 hours").await;
+    update_session(&pool, &remembered, "remembered = true").await;
+    let forgotten = insert_session(&pool, &user, "31 days").await;
+    update_session(&pool, &forgotten, "remembered = true").await;
+
+    let app = app(pool);
+    assert!(is_valid(&app, &recent).await);
+    assert!(!is_valid(&app, &idle).await);
+    assert!(!is_valid(&app, &expired).await);
+    assert!(is_valid(&app, &remembered).await);
+    assert!(!is_valid(&app, &forgotten).await);
+}
+
+//The Max-Age of the session cookie set by a request, if any
+async fn cookie_max_age(app: &Router, session: &TestUser) -> Option<i64> {
+    let request = Request::builder()
+        .uri("/fetch_sessions")
+        .header(header::COOKIE, format!("session_id={}", session.session_id))
+        .body(Body::empty())
+        .unwrap();
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let cookie = response
+        .headers()
+        .get(header::SET_COOKIE)?
+        .to_str()
+        .unwrap();
+    assert!(cookie.starts_with(&format!("session_id={}", session.session_id)));
+
+    let max_age = cookie
+        .split(';')
+        .find_map(|attribute| attribute.trim().strip_prefix("Max-Age="))
+        .expect("Remembered cookies are persistent");
+    Some(max_age.parse().unwrap())
+}
+
+#[sqlx::test]
+async fn remembered_cookies_slide_forward(pool: PgPool) {
+    let user = insert_user(&pool, "user@example.com").await;
+
+    let remembered = insert_session(&pool, &user, "1 day").await;
+    update_session(
+        &pool,
+        &remembered,
+        "remembered = true, expires_at = now() + interval '300 days'",
+    )
+    .await;
+
+    //The idle timeout can't push the cookie past the end of the session
+    let ending = insert_session(&pool, &user, "1 day").await;
+    update_session(
+        &pool,
+        &ending,
+        "remembered = true, expires_at = now() + interval '1 hour'",
+    )
+    .await;
+
+    let app = app(pool);
+    assert_eq!(
+        cookie_max_age(&app, &remembered).await,
+        Some(30 * 24 * 60 * 60)
+    );
+
+    let max_age = cookie_max_age(&app, &ending).await.unwrap();
+    assert!((3500..=3600).contains(&max_age), "{max_age}");
+
+    assert_eq!(cookie_max_age(&app, &user).await, None);
+}
+
+#[sqlx::test]
+async fn pruning_deletes_only_expired_sessions(pool: PgPool) {
+    let user = insert_user(&pool, "user@example.com").await;
+    insert_session(&pool, &user, "3 hours").await;
+
+    let expired = insert_session(&pool, &user, "1 minute").await;
+    update_session(&pool, &expired, "expires_at = now() - interval '1 minute'").await;
+
+    let remembered = insert_session(&pool, &user, "3 hours").await;
+    update_session(&pool, &remembered, "remembered = true").await;
+    let forgotten = insert_session(&pool, &user, "31 days").await;
+    update_session(&pool, &forgotten, "remembered = true").await;
+
+    let pruned = prune_sessions(&pool, &SessionPolicy::default())
+        .await
+        .unwrap();
+    assert_eq!(pruned, 3);
+
+    let mut remaining: Vec<uuid::Uuid> = sqlx::query_scalar("SELECT session_id FROM sessions")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    let mut kept = vec![user.session_id, remembered.session_id];
+    remaining.sort();
+    kept.sort();
+    assert_eq!(remaining, kept);
+}
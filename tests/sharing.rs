@@ -0,0 +1,274 @@
+mod common;
+
+use axum::{
+    http::{Method, StatusCode},
+    Router,
+};
+use common::{app, document_content, fetch_json, insert_document, insert_user, send, TestUser};
+use serde_json::json;
+use sqlx::PgPool;
+
+async fn share(
+    app: &Router,
+    owner: &TestUser,
+    document_id: uuid::Uuid,
+    email: &str,
+    role: &str,
+) -> StatusCode {
+    let body = json!({ "document_id": document_id, "email": email, "role": role });
+    send(app, Method::POST, "/share_document", owner, Some(body)).await
+}
+
+#[sqlx::test]
+async fn roles_limit_what_shared_users_can_do(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let viewer = insert_user(&pool, "viewer@example.com").await;
+    let editor = insert_user(&pool, "editor@example.com").await;
+    let stranger = insert_user(&pool, "stranger@example.com").await;
+    let document_id = insert_document(&pool, &owner, "# Shared").await;
+    let app = app(pool.clone());
+
+    let shared = share(&app, &owner, document_id, "viewer@example.com", "viewer").await;
+    assert_eq!(shared, StatusCode::OK);
+    let shared = share(&app, &owner, document_id, "editor@example.com", "editor").await;
+    assert_eq!(shared, StatusCode::OK);
+
+    let fetch = format!("/fetch_content?document_id={document_id}");
+    let revisions = format!("/revisions?document_id={document_id}");
+    let delete = format!("/delete_document?document_id={document_id}");
+    let save = |text: &str| json!({ "document_id": document_id, "text": text });
+    let rename = json!({ "document_id": document_id, "title": "Renamed" });
+    let pin = json!({ "document_id": document_id, "pinned": true });
+
+    assert_eq!(
+        send(&app, Method::GET, &fetch, &viewer, None).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        send(&app, Method::GET, &revisions, &viewer, None).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        send(&app, Method::PUT, "/save", &viewer, Some(save("# Viewer"))).await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        send(
+            &app,
+            Method::PUT,
+            "/rename_document",
+            &viewer,
+            Some(rename.clone())
+        )
+        .await,
+        StatusCode::FORBIDDEN
+    );
+    let duplicate = json!({ "document_id": document_id });
+    assert_eq!(
+        send(
+            &app,
+            Method::POST,
+            "/duplicate_document",
+            &viewer,
+            Some(duplicate)
+        )
+        .await,
+        StatusCode::OK
+    );
+
+    assert_eq!(
+        send(&app, Method::PUT, "/save", &editor, Some(save("# Edited"))).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        document_content(&pool, document_id).await.as_deref(),
+        Some("# Edited")
+    );
+    assert_eq!(
+        send(&app, Method::PUT, "/rename_document", &editor, Some(rename)).await,
+        StatusCode::OK
+    );
+    let tag = json!({ "document_id": document_id, "tag": "exam" });
+    assert_eq!(
+        send(&app, Method::POST, "/add_tag", &editor, Some(tag)).await,
+        StatusCode::OK
+    );
+
+    //Pinning, trashing and sharing stay with the owner
+    assert_eq!(
+        send(&app, Method::PUT, "/pin_document", &editor, Some(pin)).await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        send(&app, Method::DELETE, &delete, &editor, None).await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        share(&app, &editor, document_id, "stranger@example.com", "editor").await,
+        StatusCode::FORBIDDEN
+    );
+
+    assert_eq!(
+        send(&app, Method::GET, &fetch, &stranger, None).await,
+        StatusCode::FORBIDDEN
+    );
+}
+
+#[sqlx::test]
+async fn commenters_can_read_but_not_change(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let commenter = insert_user(&pool, "commenter@example.com").await;
+    let document_id = insert_document(&pool, &owner, "# Shared").await;
+    let app = app(pool.clone());
+
+    assert_eq!(
+        share(
+            &app,
+            &owner,
+            document_id,
+            "commenter@example.com",
+            "commenter"
+        )
+        .await,
+        StatusCode::OK
+    );
+
+    let fetch = format!("/fetch_content?document_id={document_id}");
+    let shares = format!("/fetch_shares?document_id={document_id}");
+    let delete = format!("/delete_document?document_id={document_id}");
+    let save = json!({ "document_id": document_id, "text": "# Commented" });
+    let rename = json!({ "document_id": document_id, "title": "Renamed" });
+
+    assert_eq!(
+        send(&app, Method::GET, &fetch, &commenter, None).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        send(&app, Method::GET, &shares, &commenter, None).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        send(&app, Method::PUT, "/save", &commenter, Some(save)).await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        send(
+            &app,
+            Method::PUT,
+            "/rename_document",
+            &commenter,
+            Some(rename)
+        )
+        .await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        send(&app, Method::DELETE, &delete, &commenter, None).await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        share(&app, &commenter, document_id, "owner@example.com", "viewer").await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        document_content(&pool, document_id).await.as_deref(),
+        Some("# Shared")
+    );
+
+    let listing = fetch_json(&app, "/fetch_documents", &commenter).await;
+    assert_eq!(listing["shared_with_me"][0]["role"], "commenter");
+}
+
+#[sqlx::test]
+async fn access_is_listed_and_revoked(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let editor = insert_user(&pool, "editor@example.com").await;
+    let viewer = insert_user(&pool, "viewer@example.com").await;
+    let document_id = insert_document(&pool, &owner, "# Shared").await;
+    let app = app(pool);
+
+    assert_eq!(
+        share(&app, &owner, document_id, "owner@example.com", "editor").await,
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(
+        share(&app, &owner, document_id, "editor@example.com", "owner").await,
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(
+        share(&app, &owner, document_id, "nobody@example.com", "editor").await,
+        StatusCode::NOT_FOUND
+    );
+
+    share(&app, &owner, document_id, "editor@example.com", "viewer").await;
+    share(&app, &owner, document_id, "editor@example.com", "editor").await;
+    share(&app, &owner, document_id, "viewer@example.com", "commenter").await;
+
+    let shares = fetch_json(
+        &app,
+        &format!("/fetch_shares?document_id={document_id}"),
+        &editor,
+    )
+    .await;
+    let roles: Vec<(&str, &str)> = shares
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|share| {
+            (
+                share["email"].as_str().unwrap(),
+                share["role"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        roles,
+        [
+            ("owner@example.com", "owner"),
+            ("editor@example.com", "editor"),
+            ("viewer@example.com", "commenter"),
+        ]
+    );
+
+    let listing = fetch_json(&app, "/fetch_documents", &editor).await;
+    assert_eq!(listing["documents"], json!([]));
+    let shared = &listing["shared_with_me"][0];
+    assert_eq!(shared["document_id"], json!(document_id));
+    assert_eq!(shared["owner_email"], "owner@example.com");
+    assert_eq!(shared["role"], "editor");
+
+    let unshare = format!(
+        "/unshare_document?document_id={document_id}&user_id={}",
+        editor.id
+    );
+    assert_eq!(
+        send(&app, Method::DELETE, &unshare, &viewer, None).await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        send(&app, Method::DELETE, &unshare, &owner, None).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        send(&app, Method::DELETE, &unshare, &owner, None).await,
+        StatusCode::NOT_FOUND
+    );
+
+    let fetch = format!("/fetch_content?document_id={document_id}");
+    assert_eq!(
+        send(&app, Method::GET, &fetch, &editor, None).await,
+        StatusCode::FORBIDDEN
+    );
+
+    //Anyone can give up their own access
+    let leave = format!(
+        "/unshare_document?document_id={document_id}&user_id={}",
+        viewer.id
+    );
+    assert_eq!(
+        send(&app, Method::DELETE, &leave, &viewer, None).await,
+        StatusCode::OK
+    );
+    let listing = fetch_json(&app, "/fetch_documents", &viewer).await;
+    assert_eq!(listing["shared_with_me"], json!([]));
+}
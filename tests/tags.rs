@@ -0,0 +1,154 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use common::{app, insert_document, insert_user, send, TestUser};
+use serde_json::json;
+use sqlx::PgPool;
+use study_buddy::tags::extract_tags;
+use study_buddy::users::{list_documents, DocumentFilter, TagMatch};
+
+async fn document_tags(pool: &PgPool, document_id: uuid::Uuid) -> Vec<String> {
+    sqlx::query_scalar("SELECT tag FROM document_tags WHERE document_id = $1 ORDER BY tag")
+        .bind(document_id)
+        .fetch_all(pool)
+        .await
+        .unwrap()
+}
+
+async fn save(app: &axum::Router, user: &TestUser, document_id: uuid::Uuid, text: &str) {
+    let body = json!({ "document_id": document_id, "text": text });
+    assert_eq!(
+        send(app, Method::PUT, "/save", user, Some(body)).await,
+        StatusCode::OK
+    );
+}
+
+#[test]
+fn tags_are_extracted_from_front_matter_and_text() {
+    let markdown = "---\n\
+        title: Notes\n\
+        tags: [Biology, \"exam-prep\"]\n\
+        ---\n\
+        # Heading\n\
+        Cells #Mitosis and #study/week1, not an issue #12 or a url#anchor\n\
+        `#inline_code`\n\
+        ```\n\
+        #include <stdio.h>\n\
+        ```\n";
+
+    let tags: Vec<String> = extract_tags(markdown).into_iter().collect();
+    assert_eq!(tags, ["biology", "exam-prep", "mitosis", "study/week1"]);
+
+    let block_list = "---\ntags:\n  - one\n  - two\n---\n";
+    let tags: Vec<String> = extract_tags(block_list).into_iter().collect();
+    assert_eq!(tags, ["one", "two"]);
+}
+
+#[test]
+fn hex_colours_are_not_tags() {
+    let markdown = "Text color: #fff, border:  #A0B0C0 and `#abcdef`
+<span style=\"background: red; color: #12345678\">x</span> <b style='fill: red #abcd'>y</b>
+Status: #fffx #ff_00 and after the span #beef\n";
+
+    let tags: Vec<String> = extract_tags(markdown).into_iter().collect();
+    assert_eq!(tags, ["beef", "ff_00", "fffx"]);
+}
+
+#[test]
+fn hex_words_are_tags() {
+    let markdown = "Topics #cafe #bad #add #deed #facade #fff\n";
+
+    let tags: Vec<String> = extract_tags(markdown).into_iter().collect();
+    assert_eq!(tags, ["add", "bad", "cafe", "deed", "facade", "fff"]);
+}
+
+#[sqlx::test]
+async fn saving_syncs_content_tags_and_keeps_manual_ones(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let document_id = insert_document(&pool, &owner, "").await;
+    let app = app(pool.clone());
+
+    save(&app, &owner, document_id, "#draft #biology").await;
+    assert_eq!(
+        document_tags(&pool, document_id).await,
+        ["biology", "draft"]
+    );
+
+    let body = json!({ "document_id": document_id, "tag": "#Pinned" });
+    assert_eq!(
+        send(&app, Method::POST, "/add_tag", &owner, Some(body)).await,
+        StatusCode::OK
+    );
+
+    save(&app, &owner, document_id, "#biology").await;
+    assert_eq!(
+        document_tags(&pool, document_id).await,
+        ["biology", "pinned"]
+    );
+
+    let uri = format!("/remove_tag?document_id={document_id}&tag=pinned");
+    assert_eq!(
+        send(&app, Method::DELETE, &uri, &owner, None).await,
+        StatusCode::OK
+    );
+    assert_eq!(document_tags(&pool, document_id).await, ["biology"]);
+
+    let body = json!({ "document_id": document_id, "tag": "not a tag" });
+    assert_eq!(
+        send(&app, Method::POST, "/add_tag", &owner, Some(body)).await,
+        StatusCode::BAD_REQUEST
+    );
+}
+
+#[sqlx::test]
+async fn other_user_cannot_tag(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let intruder = insert_user(&pool, "intruder@example.com").await;
+    let document_id = insert_document(&pool, &owner, "").await;
+    let app = app(pool);
+
+    let body = json!({ "document_id": document_id, "tag": "mine" });
+    assert_eq!(
+        send(&app, Method::POST, "/add_tag", &intruder, Some(body)).await,
+        StatusCode::FORBIDDEN
+    );
+}
+
+#[sqlx::test]
+async fn documents_filter_by_tags(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let both = insert_document(&pool, &owner, "").await;
+    let only_math = insert_document(&pool, &owner, "").await;
+    insert_document(&pool, &owner, "").await;
+    let app = app(pool.clone());
+
+    save(&app, &owner, both, "#math #physics").await;
+    save(&app, &owner, only_math, "#math").await;
+
+    let matching = |tags: &str, tag_match| {
+        let pool = pool.clone();
+        let filter = DocumentFilter {
+            tags: Some(tags.to_string()),
+            tag_match,
+            ..Default::default()
+        };
+        async move {
+            let mut documents: Vec<uuid::Uuid> = list_documents(&pool, owner.id, &filter)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|document| document.document_id)
+                .collect();
+            documents.sort();
+            documents
+        }
+    };
+
+    let mut math = vec![both, only_math];
+    math.sort();
+
+    assert_eq!(matching("math", TagMatch::All).await, math);
+    assert_eq!(matching("Math,physics", TagMatch::All).await, [both]);
+    assert_eq!(matching("math,physics", TagMatch::Any).await, math);
+    assert!(matching("history", TagMatch::Any).await.is_empty());
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use common::{app, document_content, insert_document, insert_user, send};
+use serde_json::json;
+use sqlx::PgPool;
+use std::time::Duration;
+use study_buddy::trash::purge_trash;
+use study_buddy::users::{list_documents, DocumentFilter};
+
+async fn listed(pool: &PgPool, user_id: uuid::Uuid) -> usize {
+    list_documents(pool, user_id, &DocumentFilter::default())
+        .await
+        .unwrap()
+        .len()
+}
+
+#[sqlx::test]
+async fn deleted_document_can_be_restored(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let document_id = insert_document(&pool, &owner, "# Oops").await;
+    let app = app(pool.clone());
+
+    let uri = format!("/delete_document?document_id={document_id}");
+    assert_eq!(
+        send(&app, Method::DELETE, &uri, &owner, None).await,
+        StatusCode::OK
+    );
+    assert_eq!(listed(&pool, owner.id).await, 0);
+
+    let body = json!({ "document_id": document_id, "text": "# Gone" });
+    assert_eq!(
+        send(&app, Method::PUT, "/save", &owner, Some(body)).await,
+        StatusCode::NOT_FOUND
+    );
+
+    let body = json!({ "document_id": document_id });
+    assert_eq!(
+        send(&app, Method::POST, "/restore_document", &owner, Some(body)).await,
+        StatusCode::OK
+    );
+    assert_eq!(listed(&pool, owner.id).await, 1);
+    assert_eq!(
+        document_content(&pool, document_id).await.as_deref(),
+        Some("# Oops")
+    );
+}
+
+#[sqlx::test]
+async fn only_trashed_documents_can_be_purged(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let intruder = insert_user(&pool, "intruder@example.com").await;
+    let document_id = insert_document(&pool, &owner, "# Keep").await;
+    let app = app(pool.clone());
+
+    let uri = format!("/purge_document?document_id={document_id}");
+    assert_eq!(
+        send(&app, Method::DELETE, &uri, &owner, None).await,
+        StatusCode::NOT_FOUND
+    );
+
+    let delete_uri = format!("/delete_document?document_id={document_id}");
+    assert_eq!(
+        send(&app, Method::DELETE, &delete_uri, &owner, None).await,
+        StatusCode::OK
+    );
+
+    assert_eq!(
+        send(&app, Method::DELETE, &uri, &intruder, None).await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        send(&app, Method::DELETE, &uri, &owner, None).await,
+        StatusCode::OK
+    );
+    assert_eq!(document_content(&pool, document_id).await, None);
+}
+
+#[sqlx::test]
+async fn trash_is_purged_after_retention(pool: PgPool) {
+    let owner = insert_user(&pool, "owner@example.com").await;
+    let old = insert_document(&pool, &owner, "# Old").await;
+    let recent = insert_document(&pool, &owner, "# Recent").await;
+
+    sqlx::query(
+        "UPDATE documents
+        SET deleted_at = now() - interval '31 days'
+        WHERE document_id = $1",
+    )
+    .bind(old)
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    sqlx::query("UPDATE documents SET deleted_at = now() WHERE document_id = $1")
+        .bind(recent)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let retention = Duration::from_secs(30 * 24 * 60 * 60);
+    assert_eq!(purge_trash(&pool, retention).await.unwrap(), 1);
+    assert_eq!(document_content(&pool, old).await, None);
+    assert!(document_content(&pool, recent).await.is_some());
+}